@@ -55,6 +55,20 @@ fn create_string_table(c: &mut Criterion) {
     });
 }
 
+fn create_functions(c: &mut Criterion) {
+    c.bench_function("create functions 1000", |b| {
+        b.iter_batched_ref(
+            || Lua::new(),
+            |lua| {
+                for _ in 0..1000 {
+                    lua.create_function(|_, ()| Ok(())).unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        );
+    });
+}
+
 fn call_add_function(c: &mut Criterion) {
     c.bench_function("call add function 3 10", |b| {
         b.iter_batched_ref(
@@ -200,6 +214,7 @@ criterion_group! {
         create_table,
         create_array,
         create_string_table,
+        create_functions,
         call_add_function,
         call_add_callback,
         call_append_callback,
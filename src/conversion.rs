@@ -1,18 +1,22 @@
+use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
 use std::ffi::{CStr, CString};
 use std::hash::{BuildHasher, Hash};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::string::String as StdString;
+use std::sync::Arc;
 
+#[cfg(feature = "bstr")]
 use bstr::{BStr, BString};
 use num_traits::cast;
 
 use crate::error::{Error, Result};
 use crate::function::Function;
-use crate::lua::Lua;
+use crate::lua::{FloatPolicy, Lua};
 use crate::string::String;
 use crate::table::Table;
 use crate::thread::Thread;
-use crate::types::{LightUserData, Number};
+use crate::types::{LightUserData, Number, TypedLightUserData, TypedNumber};
 use crate::userdata::{AnyUserData, UserData};
 use crate::value::{FromLua, Nil, ToLua, Value};
 
@@ -37,7 +41,7 @@ impl<'lua> ToLua<'lua> for String<'lua> {
 impl<'lua> FromLua<'lua> for String<'lua> {
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<String<'lua>> {
         let ty = value.type_name();
-        lua.coerce_string(value)?
+        lua.convert_string(value)?
             .ok_or_else(|| Error::FromLuaConversionError {
                 from: ty,
                 to: "String",
@@ -195,6 +199,25 @@ impl<'lua> FromLua<'lua> for LightUserData {
     }
 }
 
+impl<'lua, T> ToLua<'lua> for TypedLightUserData<T> {
+    fn to_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::LightUserData(self.into()))
+    }
+}
+
+impl<'lua, T> FromLua<'lua> for TypedLightUserData<T> {
+    fn from_lua(value: Value, _: &'lua Lua) -> Result<Self> {
+        match value {
+            Value::LightUserData(ud) => Ok(TypedLightUserData::from_ptr(ud.0 as *mut T)),
+            _ => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "light userdata",
+                message: None,
+            }),
+        }
+    }
+}
+
 impl<'lua> ToLua<'lua> for StdString {
     fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(lua.create_string(&self)?))
@@ -205,7 +228,7 @@ impl<'lua> FromLua<'lua> for StdString {
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         let ty = value.type_name();
         Ok(lua
-            .coerce_string(value)?
+            .convert_string(value)?
             .ok_or_else(|| Error::FromLuaConversionError {
                 from: ty,
                 to: "String",
@@ -222,6 +245,24 @@ impl<'lua, 'a> ToLua<'lua> for &'a str {
     }
 }
 
+impl<'lua, 'a> ToLua<'lua> for Cow<'a, str> {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::String(lua.create_string(self.as_ref())?))
+    }
+}
+
+impl<'lua> ToLua<'lua> for Arc<str> {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::String(lua.create_string(self.as_ref())?))
+    }
+}
+
+impl<'lua, 'a> ToLua<'lua> for &'a [u8] {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::String(lua.create_string(self)?))
+    }
+}
+
 impl<'lua> ToLua<'lua> for CString {
     fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(lua.create_string(self.as_bytes())?))
@@ -232,7 +273,7 @@ impl<'lua> FromLua<'lua> for CString {
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         let ty = value.type_name();
         let string = lua
-            .coerce_string(value)?
+            .convert_string(value)?
             .ok_or_else(|| Error::FromLuaConversionError {
                 from: ty,
                 to: "CString",
@@ -256,31 +297,57 @@ impl<'lua, 'a> ToLua<'lua> for &'a CStr {
     }
 }
 
-impl<'lua, 'a> ToLua<'lua> for BString {
+#[cfg(feature = "bstr")]
+impl<'lua> ToLua<'lua> for BString {
     fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(lua.create_string(&self)?))
     }
 }
 
+#[cfg(feature = "bstr")]
 impl<'lua> FromLua<'lua> for BString {
-    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
-        let ty = value.type_name();
-        Ok(BString::from(
-            lua.coerce_string(value)?
-                .ok_or_else(|| Error::FromLuaConversionError {
-                    from: ty,
-                    to: "String",
-                    message: Some("expected string or number".to_string()),
-                })?
-                .as_bytes()
-                .to_vec(),
-        ))
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+        match value {
+            Value::String(s) => Ok(BString::from(s.as_bytes())),
+            _ => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "BString",
+                message: Some("expected string".to_string()),
+            }),
+        }
     }
 }
 
-impl<'lua, 'a> ToLua<'lua> for &BStr {
+#[cfg(feature = "bstr")]
+impl<'lua, 'a> ToLua<'lua> for &'a BStr {
     fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
-        Ok(Value::String(lua.create_string(&self)?))
+        Ok(Value::String(lua.create_string(self)?))
+    }
+}
+
+impl<'lua> ToLua<'lua> for TypedNumber {
+    fn to_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(match self {
+            TypedNumber::Integer(i) => Value::Integer(i),
+            TypedNumber::Float(n) => Value::Number(n),
+        })
+    }
+}
+
+impl<'lua> FromLua<'lua> for TypedNumber {
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+        match value {
+            #[cfg(feature = "lua53")]
+            Value::Integer(i) => Ok(TypedNumber::Integer(i)),
+            #[cfg(not(feature = "lua53"))]
+            Value::Integer(i) => Ok(TypedNumber::Float(i as Number)),
+            Value::Number(n) => Ok(TypedNumber::Float(n)),
+            _ => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "TypedNumber",
+                message: Some("expected number".to_string()),
+            }),
+        }
     }
 }
 
@@ -305,10 +372,10 @@ macro_rules! lua_convert_int {
         impl<'lua> FromLua<'lua> for $x {
             fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
                 let ty = value.type_name();
-                (if let Some(i) = lua.coerce_integer(value.clone())? {
+                (if let Some(i) = lua.convert_integer(value.clone())? {
                     cast(i)
                 } else {
-                    cast(lua.coerce_number(value)?.ok_or_else(|| {
+                    cast(lua.convert_number(value)?.ok_or_else(|| {
                         Error::FromLuaConversionError {
                             from: ty,
                             to: stringify!($x),
@@ -344,15 +411,26 @@ lua_convert_int!(usize);
 macro_rules! lua_convert_float {
     ($x:ty) => {
         impl<'lua> ToLua<'lua> for $x {
-            fn to_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
-                Ok(Value::Number(self as Number))
+            fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+                if self.is_finite() {
+                    return Ok(Value::Number(self as Number));
+                }
+                match lua.float_policy() {
+                    FloatPolicy::Passthrough => Ok(Value::Number(self as Number)),
+                    FloatPolicy::ErrorOnNonFinite => Err(Error::ToLuaConversionError {
+                        from: stringify!($x),
+                        to: "number",
+                        message: Some(format!("non-finite value {}", self)),
+                    }),
+                    FloatPolicy::CoerceToNil => Ok(Nil),
+                }
             }
         }
 
         impl<'lua> FromLua<'lua> for $x {
             fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
                 let ty = value.type_name();
-                lua.coerce_number(value)?
+                lua.convert_number(value)?
                     .ok_or_else(|| Error::FromLuaConversionError {
                         from: ty,
                         to: stringify!($x),
@@ -373,6 +451,43 @@ macro_rules! lua_convert_float {
 lua_convert_float!(f32);
 lua_convert_float!(f64);
 
+macro_rules! lua_convert_addr {
+    ($x:ty) => {
+        impl<'lua> ToLua<'lua> for $x {
+            fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+                Ok(Value::String(lua.create_string(&self.to_string())?))
+            }
+        }
+
+        impl<'lua> FromLua<'lua> for $x {
+            fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+                let ty = value.type_name();
+                match value {
+                    Value::String(s) => {
+                        s.to_str()?
+                            .parse()
+                            .map_err(|e| Error::FromLuaConversionError {
+                                from: ty,
+                                to: stringify!($x),
+                                message: Some(format!("{}", e)),
+                            })
+                    }
+                    _ => Err(Error::FromLuaConversionError {
+                        from: ty,
+                        to: stringify!($x),
+                        message: Some("expected string".to_string()),
+                    }),
+                }
+            }
+        }
+    };
+}
+
+lua_convert_addr!(IpAddr);
+lua_convert_addr!(Ipv4Addr);
+lua_convert_addr!(Ipv6Addr);
+lua_convert_addr!(SocketAddr);
+
 impl<'lua, T: ToLua<'lua>> ToLua<'lua> for Vec<T> {
     fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Table(lua.create_sequence_from(self)?))
@@ -4,6 +4,8 @@ use std::result::Result as StdResult;
 use std::string::String as StdString;
 use std::sync::Arc;
 
+use crate::types::RegistryKey;
+
 /// Error type returned by `mlua` methods.
 #[derive(Debug, Clone)]
 pub enum Error {
@@ -36,7 +38,10 @@ pub enum Error {
     /// A mutable callback has triggered Lua code that has called the same mutable callback again.
     ///
     /// This is an error because a mutable callback can only be borrowed mutably once.
-    RecursiveMutCallback,
+    RecursiveMutCallback {
+        /// Name of the userdata method which was called recursively, if it is one.
+        method: Option<StdString>,
+    },
     /// Either a callback or a userdata method has been called, but the callback or userdata has
     /// been destructed.
     ///
@@ -52,6 +57,14 @@ pub enum Error {
     StackError,
     /// Too many arguments to `Function::bind`
     BindError,
+    /// Too many nested calls to Rust callbacks.
+    ///
+    /// Lua code calling a Rust callback which calls back into Lua uses the native stack for each
+    /// level. To avoid overflowing it with deep (or unbounded) recursion, the number of nested
+    /// callback calls is limited, see [`Lua::set_max_callback_depth`].
+    ///
+    /// [`Lua::set_max_callback_depth`]: struct.Lua.html#method.set_max_callback_depth
+    RecursionLimit,
     /// A Rust value could not be converted to a Lua value.
     ToLuaConversionError {
         /// Name of the Rust type that could not be converted.
@@ -98,7 +111,10 @@ pub enum Error {
     ///
     /// [`AnyUserData`]: struct.AnyUserData.html
     /// [`UserData`]: trait.UserData.html
-    UserDataBorrowError,
+    UserDataBorrowError {
+        /// Name of the userdata method which needed the borrow, if any.
+        method: Option<StdString>,
+    },
     /// An [`AnyUserData`] mutable borrow failed because it is already borrowed.
     ///
     /// This error can occur when a method on a [`UserData`] type calls back into Lua, which then
@@ -107,9 +123,15 @@ pub enum Error {
     ///
     /// [`AnyUserData`]: struct.AnyUserData.html
     /// [`UserData`]: trait.UserData.html
-    UserDataBorrowMutError,
+    UserDataBorrowMutError {
+        /// Name of the userdata method which needed the borrow, if any.
+        method: Option<StdString>,
+    },
     /// A `RegistryKey` produced from a different Lua state was used.
     MismatchedRegistryKey,
+    /// A handle (such as a `Table` or `Function`) created by a different Lua state was passed to
+    /// this one.
+    MismatchedLua,
     /// A Rust callback returned `Err`, raising the contained `Error` as a Lua error.
     CallbackError {
         /// Lua call stack backtrace.
@@ -125,6 +147,21 @@ pub enum Error {
     /// error. The Rust code that originally invoked the Lua code then receives a `CallbackError`,
     /// from which the original error (and a stack traceback) can be recovered.
     ExternalError(Arc<dyn StdError + Send + Sync>),
+    /// A Lua error carrying an arbitrary Lua value, such as a table, instead of a message.
+    ///
+    /// Created with [`Lua::create_error_value`]. Returning this error from a Rust callback raises
+    /// the stored value itself as the Lua error, so `pcall` in Lua receives the original value.
+    ///
+    /// [`Lua::create_error_value`]: struct.Lua.html#method.create_error_value
+    RuntimeValue {
+        /// Registry key of the raised value.
+        key: Arc<RegistryKey>,
+        /// Lua type name of the raised value.
+        type_name: &'static str,
+    },
+    /// Deserialization of a Lua value failed.
+    #[cfg(feature = "serialize")]
+    DeserializeError(StdString),
 }
 
 /// A specialized `Result` type used by `mlua`'s API.
@@ -142,7 +179,12 @@ impl fmt::Display for Error {
             Error::GarbageCollectorError(ref msg) => {
                 write!(fmt, "garbage collector error: {}", msg)
             }
-            Error::RecursiveMutCallback => write!(fmt, "mutable callback called recursively"),
+            Error::RecursiveMutCallback { method: None } => {
+                write!(fmt, "mutable callback called recursively")
+            }
+            Error::RecursiveMutCallback {
+                method: Some(ref method),
+            } => write!(fmt, "mutable method '{}' called recursively", method),
             Error::CallbackDestructed => write!(
                 fmt,
                 "a destructed callback or destructed userdata method was called"
@@ -155,6 +197,7 @@ impl fmt::Display for Error {
                 fmt,
                 "too many arguments to Function::bind"
             ),
+            Error::RecursionLimit => write!(fmt, "too many nested calls to Rust callbacks"),
             Error::ToLuaConversionError {
                 from,
                 to,
@@ -179,15 +222,39 @@ impl fmt::Display for Error {
             }
             Error::CoroutineInactive => write!(fmt, "cannot resume inactive coroutine"),
             Error::UserDataTypeMismatch => write!(fmt, "userdata is not expected type"),
-            Error::UserDataBorrowError => write!(fmt, "userdata already mutably borrowed"),
-            Error::UserDataBorrowMutError => write!(fmt, "userdata already borrowed"),
+            Error::UserDataBorrowError { method: None } => {
+                write!(fmt, "userdata already mutably borrowed")
+            }
+            Error::UserDataBorrowError {
+                method: Some(ref method),
+            } => write!(
+                fmt,
+                "cannot borrow userdata for method '{}' because it is already mutably borrowed",
+                method
+            ),
+            Error::UserDataBorrowMutError { method: None } => {
+                write!(fmt, "userdata already borrowed")
+            }
+            Error::UserDataBorrowMutError {
+                method: Some(ref method),
+            } => write!(
+                fmt,
+                "cannot mutably borrow userdata for method '{}' because it is already borrowed",
+                method
+            ),
             Error::MismatchedRegistryKey => {
                 write!(fmt, "RegistryKey used from different Lua state")
             }
+            Error::MismatchedLua => write!(fmt, "Lua value used from different Lua state"),
             Error::CallbackError { ref traceback, ref cause } => {
                 write!(fmt, "callback error: {}: {}", cause, traceback)
             }
             Error::ExternalError(ref err) => write!(fmt, "external error: {}", err),
+            Error::RuntimeValue { type_name, .. } => {
+                write!(fmt, "runtime error with {} value", type_name)
+            }
+            #[cfg(feature = "serialize")]
+            Error::DeserializeError(ref err) => write!(fmt, "deserialize error: {}", err),
         }
     }
 }
@@ -206,6 +273,19 @@ impl Error {
     pub fn external<T: Into<Box<dyn StdError + Send + Sync>>>(err: T) -> Error {
         Error::ExternalError(err.into().into())
     }
+
+    // Attaches the name of the userdata method being called to borrow errors which lack one.
+    pub(crate) fn with_method(self, name: &[u8]) -> Error {
+        let method = Some(StdString::from_utf8_lossy(name).into_owned());
+        match self {
+            Error::RecursiveMutCallback { method: None } => Error::RecursiveMutCallback { method },
+            Error::UserDataBorrowError { method: None } => Error::UserDataBorrowError { method },
+            Error::UserDataBorrowMutError { method: None } => {
+                Error::UserDataBorrowMutError { method }
+            }
+            err => err,
+        }
+    }
 }
 
 pub trait ExternalError {
@@ -233,3 +313,10 @@ where
         self.map_err(|e| e.to_lua_err())
     }
 }
+
+#[cfg(feature = "serialize")]
+impl serde::de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::DeserializeError(msg.to_string())
+    }
+}
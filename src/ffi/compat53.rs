@@ -50,8 +50,8 @@ use super::lua::{
 
 #[cfg(any(feature = "lua51", feature = "luajit"))]
 use super::lua::{
-    lua_checkstack, lua_concat, lua_equal, lua_getfenv, lua_getinfo, lua_getmetatable,
-    lua_isnumber, lua_lessthan, lua_newtable, lua_next, lua_objlen, lua_pushcclosure,
+    lua_Reader, lua_checkstack, lua_concat, lua_equal, lua_getfenv, lua_getinfo, lua_getmetatable,
+    lua_isnumber, lua_lessthan, lua_load_old, lua_newtable, lua_next, lua_objlen, lua_pushcclosure,
     lua_pushlightuserdata, lua_pushnil, lua_pushstring_old, lua_rawequal, lua_remove,
     lua_resume_old, lua_setfenv, lua_settop, LUA_OPADD, LUA_OPUNM,
 };
@@ -219,6 +219,7 @@ unsafe fn compat53_call_lua(L: *mut lua_State, code: &str, nargs: c_int, nret: c
 // lua ported functions
 //
 
+/// Returns the pseudo-index of the `i`-th upvalue of the running C function.
 #[cfg(any(feature = "lua51", feature = "luajit"))]
 #[inline(always)]
 pub fn lua_upvalueindex(i: c_int) -> c_int {
@@ -469,6 +470,17 @@ pub unsafe fn lua_dump(
     lua_dump_old(L, writer, data)
 }
 
+#[cfg(any(feature = "lua51", feature = "luajit"))]
+pub unsafe fn lua_load(
+    L: *mut lua_State,
+    reader: lua_Reader,
+    data: *mut c_void,
+    chunkname: *const c_char,
+    _mode: *const c_char,
+) -> c_int {
+    lua_load_old(L, reader, data, chunkname)
+}
+
 #[cfg(any(feature = "lua51", feature = "luajit"))]
 pub unsafe fn lua_resume(L: *mut lua_State, _from: *mut lua_State, narg: c_int) -> c_int {
     lua_resume_old(L, narg)
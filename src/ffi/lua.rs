@@ -50,13 +50,15 @@ pub use super::compat53::{
 
 #[cfg(any(feature = "lua51", feature = "luajit"))]
 pub use super::compat53::{
-    lua_absindex, lua_arith, lua_compare, lua_copy, lua_len, lua_pushglobaltable, lua_pushstring,
-    lua_rawlen, lua_rawsetp, lua_resume, lua_setuservalue, lua_tonumberx, lua_upvalueindex,
+    lua_absindex, lua_arith, lua_compare, lua_copy, lua_len, lua_load, lua_pushglobaltable,
+    lua_pushstring, lua_rawlen, lua_rawsetp, lua_resume, lua_setuservalue, lua_tonumberx,
+    lua_upvalueindex,
 };
 
 #[cfg(feature = "lua52")]
 pub use super::compat53::lua_getglobal;
 
+/// Returns the pseudo-index of the `i`-th upvalue of the running C function.
 #[cfg(any(feature = "lua53", feature = "lua52"))]
 #[inline(always)]
 pub fn lua_upvalueindex(i: c_int) -> c_int {
@@ -403,7 +405,7 @@ extern "C" {
     #[cfg(any(feature = "lua51", feature = "luajit"))]
     pub fn lua_pcall(L: *mut lua_State, nargs: c_int, nresults: c_int, errfunc: c_int) -> c_int;
 
-    // TODO
+    #[cfg(any(feature = "lua53", feature = "lua52"))]
     pub fn lua_load(
         L: *mut lua_State,
         reader: lua_Reader,
@@ -411,6 +413,14 @@ extern "C" {
         chunkname: *const c_char,
         mode: *const c_char,
     ) -> c_int;
+    #[cfg(any(feature = "lua51", feature = "luajit"))]
+    #[link_name = "lua_load"]
+    pub fn lua_load_old(
+        L: *mut lua_State,
+        reader: lua_Reader,
+        dt: *mut c_void,
+        chunkname: *const c_char,
+    ) -> c_int;
 
     #[cfg(feature = "lua53")]
     pub fn lua_dump(
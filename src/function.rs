@@ -1,13 +1,19 @@
+#[cfg(any(feature = "lua53", feature = "lua52"))]
+use std::ffi::CStr;
 use std::os::raw::c_int;
 use std::ptr;
 
 use crate::error::{Error, Result};
 use crate::ffi;
+use crate::lua::{Lua, NoYieldGuard};
+use crate::table::Table;
+use crate::thread::ThreadStatus;
 use crate::types::LuaRef;
 use crate::util::{
     assert_stack, check_stack, error_traceback, pop_error, protect_lua_closure, StackGuard,
 };
-use crate::value::{FromLuaMulti, MultiValue, ToLuaMulti};
+use crate::value::{FromLuaMulti, MultiValue, ToLuaMulti, Value};
+use crate::weak::Weak;
 
 /// Handle to an internal Lua function.
 #[derive(Clone, Debug)]
@@ -55,12 +61,199 @@ impl<'lua> Function<'lua> {
     /// # }
     /// ```
     pub fn call<A: ToLuaMulti<'lua>, R: FromLuaMulti<'lua>>(&self, args: A) -> Result<R> {
-        let lua = self.0.lua;
+        let mut results = MultiValue::new();
+        self.call_raw(args.to_lua_multi(self.0.lua)?, &mut results)?;
+        R::from_lua_multi(results, self.0.lua)
+    }
+
+    /// Calls the function, passing the values in `args` as arguments.
+    ///
+    /// This is useful when the number of arguments is only known at runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let count: Function = lua.load("function(...) return select('#', ...) end").eval()?;
+    ///
+    /// let args: Vec<Value> = (0..5).map(Value::Integer).collect();
+    /// assert_eq!(count.call_slice::<usize>(&args)?, 5);
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_slice<R: FromLuaMulti<'lua>>(&self, args: &[Value<'lua>]) -> Result<R> {
+        self.call_multi(args.iter().cloned().collect())
+    }
+
+    /// Calls the function, passing the values in `args` as arguments.
+    ///
+    /// Like [`call_slice`], but takes ownership of an already built [`MultiValue`].
+    ///
+    /// [`call_slice`]: #method.call_slice
+    /// [`MultiValue`]: struct.MultiValue.html
+    pub fn call_multi<R: FromLuaMulti<'lua>>(&self, args: MultiValue<'lua>) -> Result<R> {
+        let mut results = MultiValue::new();
+        self.call_raw(args, &mut results)?;
+        R::from_lua_multi(results, self.0.lua)
+    }
+
+    /// Calls the function with `env` as its environment for the duration of the call.
+    ///
+    /// The function's environment (its `_ENV` upvalue, or its function environment on Lua 5.1 and
+    /// LuaJIT) is replaced by `env` before the call and restored afterwards, also if the call
+    /// fails or a Rust callback panics. This allows running the same compiled function against
+    /// several isolated sets of globals without reloading it. Functions without an environment,
+    /// such as Rust callbacks, are called unchanged.
+    ///
+    /// On Lua 5.2 and 5.3, the `_ENV` upvalue is shared with the chunk which defined the function,
+    /// so other functions of that chunk also see `env` while the call is running.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let greet: Function = lua.load("function() return 'hello ' .. name end").eval()?;
+    ///
+    /// let env = lua.create_table()?;
+    /// env.set("name", "world")?;
+    /// assert_eq!(greet.call_in_env::<_, String>(env, ())?, "hello world");
+    ///
+    /// // The original environment is back in place
+    /// assert!(greet.call::<_, String>(()).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_in_env<A, R>(&self, env: Table<'lua>, args: A) -> Result<R>
+    where
+        A: ToLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        let args = args.to_lua_multi(self.0.lua)?;
+        let _guard = EnvGuard {
+            func: self,
+            env: self.swap_env(Value::Table(env))?,
+        };
+        self.call(args)
+    }
 
+    /// Calls the function, guaranteeing that it cannot yield.
+    ///
+    /// Functions called from Rust never run in a yieldable context, so a `coroutine.yield` reached
+    /// during the call always fails, but the resulting error message depends on the Lua version
+    /// and on where the yield was attempted. This method runs the function on a new thread
+    /// instead, and reports a yield which suspends that thread as an
+    /// `Error::RuntimeError("attempt to yield across a non-yieldable boundary")`, making it
+    /// suitable for contexts such as finalizers where a yield must be rejected explicitly.
+    ///
+    /// [`Lua::yield_with`] fails with the same message as soon as it is called from a callback
+    /// running on that thread, and the resulting `Error::CallbackError` is returned as it is.
+    /// Yields attempted across a non-yieldable boundary inside the function (such as a nested call
+    /// from Rust) still fail with the error raised by Lua, which a `pcall` may catch.
+    ///
+    /// Since the function runs on a coroutine of its own, it observes a coroutine context rather
+    /// than the caller's: `coroutine.running()` returns the new thread, `coroutine.isyieldable()`
+    /// returns `true` with Lua 5.3, and [`Lua::is_in_coroutine`] returns `true` in callbacks it
+    /// calls. Creating that thread also makes every call more expensive than [`call`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Error, Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let f: Function = lua.load("function() coroutine.yield(1) end").eval()?;
+    /// match f.call_no_yield::<_, ()>(()) {
+    ///     Err(Error::RuntimeError(msg)) => {
+    ///         assert_eq!(msg, "attempt to yield across a non-yieldable boundary")
+    ///     }
+    ///     r => panic!("unexpected result: {:?}", r),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Lua::yield_with`]: struct.Lua.html#method.yield_with
+    /// [`Lua::is_in_coroutine`]: struct.Lua.html#method.is_in_coroutine
+    /// [`call`]: #method.call
+    pub fn call_no_yield<A, R>(&self, args: A) -> Result<R>
+    where
+        A: ToLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        let lua = self.0.lua;
         let args = args.to_lua_multi(lua)?;
+
+        // The function runs on a thread of its own, so a yield reaching this call suspends that
+        // thread instead of raising a version-dependent error.
+        let thread = lua.create_thread(self.clone())?;
+        let thread_state = unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 1);
+            lua.push_ref(&thread.0);
+            ffi::lua_tothread(lua.state, -1)
+        };
+
+        let results = {
+            let _guard = NoYieldGuard::enter(lua, thread_state);
+            thread.resume::<_, MultiValue>(args)?
+        };
+        if thread.status() == ThreadStatus::Resumable {
+            return Err(Error::RuntimeError(
+                "attempt to yield across a non-yieldable boundary".to_string(),
+            ));
+        }
+        R::from_lua_multi(results, lua)
+    }
+
+    /// Calls the function, using `buf` as scratch space for the returned values.
+    ///
+    /// This works like [`call`], but instead of allocating a new [`MultiValue`] for the results of
+    /// every call, `buf` is cleared and refilled, and `R` is converted from it. Reusing the same
+    /// buffer when calling a function in a loop avoids repeated allocations. Any values that are
+    /// not consumed by the conversion are removed from `buf` before returning.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, MultiValue, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let sum_prod: Function = lua.load("function(a, b) return a + b, a * b end").eval()?;
+    ///
+    /// let mut buf = MultiValue::new();
+    /// for i in 0..10 {
+    ///     let (sum, prod): (i64, i64) = sum_prod.call_into((i, 3), &mut buf)?;
+    ///     assert_eq!((sum, prod), (i + 3, i * 3));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`call`]: #method.call
+    /// [`MultiValue`]: struct.MultiValue.html
+    pub fn call_into<A, R>(&self, args: A, buf: &mut MultiValue<'lua>) -> Result<R>
+    where
+        A: ToLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        buf.clear();
+        self.call_raw(args.to_lua_multi(self.0.lua)?, buf)?;
+        let res = R::from_lua_multi_buf(buf, self.0.lua);
+        buf.clear();
+        res
+    }
+
+    // Calls the function and pushes the returned values to `results`, which must be empty.
+    fn call_raw(&self, args: MultiValue<'lua>, results: &mut MultiValue<'lua>) -> Result<()> {
+        let lua = self.0.lua;
         let nargs = args.len() as c_int;
 
-        let results = unsafe {
+        unsafe {
             let _sg = StackGuard::new(lua.state);
             check_stack(lua.state, nargs + 3)?;
 
@@ -75,15 +268,14 @@ impl<'lua> Function<'lua> {
                 return Err(pop_error(lua.state, ret));
             }
             let nresults = ffi::lua_gettop(lua.state) - stack_start;
-            let mut results = MultiValue::new();
+            results.reserve(nresults as usize);
             assert_stack(lua.state, 2);
             for _ in 0..nresults {
                 results.push_front(lua.pop_value());
             }
             ffi::lua_pop(lua.state, 1);
-            results
-        };
-        R::from_lua_multi(results, lua)
+        }
+        Ok(())
     }
 
     /// Returns a function that, when called, calls `self`, passing `args` as the first set of
@@ -159,6 +351,116 @@ impl<'lua> Function<'lua> {
             Ok(Function(lua.pop_ref()))
         }
     }
+
+    /// Converts a callable value into a `Function`.
+    ///
+    /// Functions are returned as they are. Tables and userdata which are callable according to
+    /// [`Value::is_callable`] are wrapped in a Lua closure which forwards its arguments to the
+    /// value's `__call` metamethod and returns its results. Other values fail with
+    /// `Error::FromLuaConversionError`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let counter: Value = lua.load(r#"
+    ///     setmetatable({ n = 0 }, {
+    ///         __call = function(self, step) self.n = self.n + step return self.n end
+    ///     })
+    /// "#).eval()?;
+    ///
+    /// let func = Function::from_callable(counter)?;
+    /// assert_eq!(func.call::<_, i64>(2)?, 2);
+    /// assert_eq!(func.call::<_, i64>(3)?, 5);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Value::is_callable`]: enum.Value.html#method.is_callable
+    pub fn from_callable(value: Value<'lua>) -> Result<Function<'lua>> {
+        let lua = match value {
+            Value::Function(f) => return Ok(f),
+            Value::Table(ref t) if value.is_callable() => t.0.lua,
+            Value::UserData(ref ud) if value.is_callable() => ud.0.lua,
+            _ => {
+                return Err(Error::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "Function",
+                    message: Some("value is not callable".to_string()),
+                })
+            }
+        };
+
+        let wrap = lua.cached_chunk(
+            &FROM_CALLABLE_REGISTRY_KEY,
+            "=from_callable",
+            "local callable = ... return function(...) return callable(...) end",
+        )?;
+        wrap.call(value)
+    }
+
+    /// Returns `true` if this function was created by `lua`, or by a `Lua` sharing its main state.
+    ///
+    /// Passing handles to a different Lua state fails with [`Error::MismatchedLua`].
+    ///
+    /// [`Error::MismatchedLua`]: enum.Error.html#variant.MismatchedLua
+    pub fn belongs_to(&self, lua: &Lua) -> bool {
+        self.0.lua.shares_state(lua)
+    }
+
+    /// Creates a weak reference to this function.
+    ///
+    /// The weak reference does not keep the function alive. Refer to [`Weak`] for details.
+    ///
+    /// [`Weak`]: struct.Weak.html
+    pub fn downgrade(&self) -> Result<Weak<'lua, Function<'lua>>> {
+        Weak::new(self.0.lua, Value::Function(self.clone()))
+    }
+
+    // Replaces the environment of the function, returning the previous one, or `None` if the
+    // function has no environment.
+    fn swap_env(&self, env: Value<'lua>) -> Result<Option<Value<'lua>>> {
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 3);
+
+            lua.push_ref(&self.0);
+
+            #[cfg(any(feature = "lua53", feature = "lua52"))]
+            {
+                let mut n = 1;
+                loop {
+                    let name = ffi::lua_getupvalue(lua.state, -1, n);
+                    if name.is_null() {
+                        return Ok(None);
+                    }
+                    if CStr::from_ptr(name).to_bytes() == b"_ENV" {
+                        let old = lua.pop_value();
+                        lua.push_value(env)?;
+                        ffi::lua_setupvalue(lua.state, -2, n);
+                        return Ok(Some(old));
+                    }
+                    ffi::lua_pop(lua.state, 1);
+                    n += 1;
+                }
+            }
+
+            #[cfg(any(feature = "lua51", feature = "luajit"))]
+            {
+                if ffi::lua_iscfunction(lua.state, -1) != 0 {
+                    return Ok(None);
+                }
+                ffi::lua_getfenv(lua.state, -1);
+                let old = lua.pop_value();
+                lua.push_value(env)?;
+                ffi::lua_setfenv(lua.state, -2);
+                Ok(Some(old))
+            }
+        }
+    }
 }
 
 impl<'lua> PartialEq for Function<'lua> {
@@ -166,3 +468,20 @@ impl<'lua> PartialEq for Function<'lua> {
         self.0 == other.0
     }
 }
+
+// Restores the environment replaced by `Function::call_in_env` when dropped.
+struct EnvGuard<'a, 'lua> {
+    func: &'a Function<'lua>,
+    env: Option<Value<'lua>>,
+}
+
+impl<'a, 'lua> Drop for EnvGuard<'a, 'lua> {
+    fn drop(&mut self) {
+        if let Some(env) = self.env.take() {
+            let _ = self.func.swap_env(env);
+        }
+    }
+}
+
+// Registry key of the compiled wrapper used by `Function::from_callable`.
+static FROM_CALLABLE_REGISTRY_KEY: u8 = 0;
@@ -19,6 +19,9 @@
 //! Most code in `mlua` is generic over implementors of those traits, so in most places the normal
 //! Rust data structures are accepted without having to write any boilerplate.
 //!
+//! With the `bstr` feature (enabled by default), Lua strings can also be converted to and from
+//! [`BString`] and [`&BStr`] as raw bytes, without UTF-8 validation.
+//!
 //! # Custom Userdata
 //!
 //! The [`UserData`] trait can be implemented by user-defined types to make them available to Lua.
@@ -35,6 +38,8 @@
 //! [`FromLuaMulti`]: trait.FromLuaMulti.html
 //! [`UserData`]: trait.UserData.html
 //! [`UserDataMethods`]: trait.UserDataMethods.html
+//! [`BString`]: https://docs.rs/bstr/0.2/bstr/struct.BString.html
+//! [`&BStr`]: https://docs.rs/bstr/0.2/bstr/struct.BStr.html
 
 // Deny warnings inside doc tests / examples. When this isn't present, rustdoc doesn't show *any*
 // warnings at all.
@@ -58,20 +63,28 @@ mod types;
 mod userdata;
 mod util;
 mod value;
+mod weak;
 
-pub use crate::ffi::lua_State;
+pub use crate::ffi::{lua_CFunction, lua_State, lua_upvalueindex};
 
 pub use crate::error::{Error, ExternalError, ExternalResult, Result};
 pub use crate::function::Function;
-pub use crate::lua::{Chunk, Lua};
+#[cfg(not(feature = "luajit"))]
+pub use crate::lua::{Allocator, CoverageReport, InterruptHandle};
+pub use crate::lua::{Chunk, Coercion, FloatPolicy, GlobalsSnapshot, Lua, LuaVersion, PinnedValue};
 pub use crate::multi::Variadic;
 pub use crate::scope::Scope;
 pub use crate::stdlib::StdLib;
 pub use crate::string::String;
 pub use crate::table::{Table, TablePairs, TableSequence};
-pub use crate::thread::{Thread, ThreadStatus};
-pub use crate::types::{Integer, LightUserData, Number, RegistryKey};
+pub use crate::thread::{Thread, ThreadStatus, Yield};
+pub use crate::types::{
+    Integer, LightUserData, Number, OwnedValue, RegistryKey, TypedLightUserData, TypedNumber,
+};
 pub use crate::userdata::{AnyUserData, MetaMethod, UserData, UserDataMethods};
 pub use crate::value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti, Value};
+pub use crate::weak::Weak;
 
 pub mod prelude;
+#[cfg(feature = "serialize")]
+pub mod serde;
@@ -1,11 +1,21 @@
-use std::any::TypeId;
+use std::any::{Any, TypeId};
 use std::cell::{RefCell, UnsafeCell};
-use std::collections::HashMap;
-use std::ffi::CString;
+#[cfg(not(feature = "luajit"))]
+use std::collections::BTreeMap;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{CStr, CString};
+use std::io::{self, Read};
 use std::marker::PhantomData;
 use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::string::String as StdString;
+#[cfg(not(feature = "luajit"))]
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::{mem, ptr, str};
+use std::{mem, ptr, str, thread};
+
+#[cfg(feature = "serialize")]
+use serde::de::DeserializeOwned;
 
 use crate::error::{Error, Result};
 use crate::ffi;
@@ -13,8 +23,8 @@ use crate::function::Function;
 use crate::scope::Scope;
 use crate::stdlib::StdLib;
 use crate::string::String;
-use crate::table::Table;
-use crate::thread::Thread;
+use crate::table::{ordered_keys_marker, Table};
+use crate::thread::{take_yield_marker, Thread, ThreadStatus, Yield};
 use crate::types::{Callback, Integer, LightUserData, LuaRef, Number, RegistryKey};
 use crate::userdata::{AnyUserData, MetaMethod, UserData, UserDataMethods};
 #[cfg(any(feature = "lua51", feature = "luajit"))]
@@ -27,6 +37,23 @@ use crate::util::{
 use crate::value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti, Value};
 
 /// Top level Lua struct which holds the Lua state itself.
+///
+/// # Multi-threaded access
+///
+/// `Lua` is `Send` but not `Sync`: the state may be moved to another thread, but only one thread
+/// may use it at a time.  To share a single Lua state between several threads, wrap it into a
+/// `Mutex` (for example `Arc<Mutex<Lua>>`), which serializes all access to the underlying
+/// `lua_State`.  Handle types such as [`Table`] or [`Function`] borrow the `Lua` they came from, so
+/// they cannot escape the lock guard and must be stored as [`RegistryKey`]s between lock
+/// acquisitions.
+///
+/// Since a panic inside of a Rust callback is propagated through Lua without corrupting the state,
+/// a lock poisoned by such a panic may be recovered with [`PoisonError::into_inner`].
+///
+/// [`Table`]: struct.Table.html
+/// [`Function`]: struct.Function.html
+/// [`RegistryKey`]: struct.RegistryKey.html
+/// [`PoisonError::into_inner`]: https://doc.rust-lang.org/std/sync/struct.PoisonError.html#method.into_inner
 pub struct Lua {
     pub(crate) state: *mut ffi::lua_State,
     main_state: *mut ffi::lua_State,
@@ -39,12 +66,76 @@ pub struct Lua {
 // Data associated with the lua_State.
 struct ExtraData {
     registered_userdata: HashMap<TypeId, c_int>,
+    // Types of registered userdata, by the address of their metatable.
+    registered_userdata_mt: HashMap<*const c_void, TypeId>,
     registry_unref_list: Arc<Mutex<Option<Vec<c_int>>>>,
 
     ref_thread: *mut ffi::lua_State,
     ref_stack_size: c_int,
     ref_stack_max: c_int,
     ref_free: Vec<c_int>,
+
+    // Registry reference to a userdata holding a clone of this `ExtraData`, shared as an upvalue
+    // by every callback instead of allocating a new one per function.
+    callback_extra: c_int,
+
+    // Whether `Thread::resume` adds a traceback to errors.
+    resume_traceback: bool,
+
+    // Number of nested Rust callback calls currently running, and the limit for it.
+    callback_depth: usize,
+    max_callback_depth: usize,
+
+    // How non-finite floats are converted to Lua.
+    float_policy: FloatPolicy,
+    // Whether conversions from Lua coerce between strings and numbers.
+    coercion: Coercion,
+
+    // Threads running a `Function::call_no_yield`, which must not be yielded from.
+    no_yield_states: Vec<*mut ffi::lua_State>,
+
+    // Functions registered with `Lua::on_drop`, run after the state is closed.
+    drop_callbacks: Vec<Box<dyn FnOnce() + Send>>,
+
+    // Allocator given to `Lua::new_with_allocator`, freed after the state is closed.
+    #[cfg(not(feature = "luajit"))]
+    allocator: Option<*mut Box<dyn Allocator>>,
+}
+
+// Keeps track of the callback depth while a Rust callback is running.
+struct CallbackDepthGuard<'a>(&'a RefCell<ExtraData>);
+
+impl<'a> CallbackDepthGuard<'a> {
+    fn enter(extra: &'a RefCell<ExtraData>) -> Result<CallbackDepthGuard<'a>> {
+        let mut data = extra.borrow_mut();
+        if data.callback_depth >= data.max_callback_depth {
+            return Err(Error::RecursionLimit);
+        }
+        data.callback_depth += 1;
+        Ok(CallbackDepthGuard(extra))
+    }
+}
+
+impl<'a> Drop for CallbackDepthGuard<'a> {
+    fn drop(&mut self) {
+        self.0.borrow_mut().callback_depth -= 1;
+    }
+}
+
+// Forbids `Lua::yield_with` on a thread while a `Function::call_no_yield` runs on it.
+pub(crate) struct NoYieldGuard<'a>(&'a Lua);
+
+impl<'a> NoYieldGuard<'a> {
+    pub(crate) fn enter(lua: &'a Lua, state: *mut ffi::lua_State) -> NoYieldGuard<'a> {
+        lua.extra.borrow_mut().no_yield_states.push(state);
+        NoYieldGuard(lua)
+    }
+}
+
+impl<'a> Drop for NoYieldGuard<'a> {
+    fn drop(&mut self) {
+        self.0.extra.borrow_mut().no_yield_states.pop();
+    }
 }
 
 unsafe impl Send for Lua {}
@@ -61,6 +152,19 @@ impl Drop for Lua {
                 );
                 *mlua_expect!(extra.registry_unref_list.lock(), "unref list poisoned") = None;
                 ffi::lua_close(self.state);
+
+                #[cfg(not(feature = "luajit"))]
+                {
+                    if let Some(allocator) = extra.allocator.take() {
+                        drop(Box::from_raw(allocator));
+                    }
+                }
+
+                let drop_callbacks = mem::replace(&mut extra.drop_callbacks, Vec::new());
+                drop(extra);
+                for f in drop_callbacks {
+                    f();
+                }
             }
         }
     }
@@ -78,24 +182,51 @@ impl Lua {
     ///
     /// [`StdLib`]: struct.StdLib.html
     pub fn new_with(libs: StdLib) -> Lua {
+        unsafe { Lua::from_new_state(ffi::luaL_newstate(), libs) }
+    }
+
+    /// Creates a new Lua state which uses `allocator` for all of its memory, and loads the
+    /// standard library without the `debug` library.
+    ///
+    /// The allocator is kept alive for as long as the state, and dropped right after the state
+    /// is closed. Refer to [`Allocator`] for the requirements on it.
+    ///
+    /// Not available with LuaJIT, which does not support custom allocators on 64-bit platforms.
+    ///
+    /// [`Allocator`]: trait.Allocator.html
+    #[cfg(not(feature = "luajit"))]
+    pub fn new_with_allocator<A: Allocator>(allocator: A) -> Lua {
         unsafe {
-            let state = ffi::luaL_newstate();
+            let allocator: *mut Box<dyn Allocator> = Box::into_raw(Box::new(Box::new(allocator)));
+            let state = ffi::lua_newstate(allocator_shim, allocator as *mut c_void);
+            if state.is_null() {
+                drop(Box::from_raw(allocator));
+                panic!("cannot create Lua state: out of memory");
+            }
+            ffi::lua_atpanic(state, lua_panic);
 
-            ffi::luaL_requiref(state, cstr!("_G"), ffi::luaopen_base, 1);
-            ffi::lua_pop(state, 1);
+            let lua = Lua::from_new_state(state, StdLib::ALL_NO_DEBUG);
+            lua.extra.borrow_mut().allocator = Some(allocator);
+            lua
+        }
+    }
 
-            let mut lua = Lua::init_from_ptr(state);
-            lua.ephemeral = false;
+    // Initializes a freshly created Lua state and loads the standard libraries into it.
+    unsafe fn from_new_state(state: *mut ffi::lua_State, libs: StdLib) -> Lua {
+        ffi::luaL_requiref(state, cstr!("_G"), ffi::luaopen_base, 1);
+        ffi::lua_pop(state, 1);
 
-            mlua_expect!(
-                protect_lua_closure(lua.main_state, 0, 0, |state| {
-                    load_from_std_lib(state, libs);
-                }),
-                "Error during loading standard libraries"
-            );
+        let mut lua = Lua::init_from_ptr(state);
+        lua.ephemeral = false;
 
-            lua
-        }
+        mlua_expect!(
+            protect_lua_closure(lua.main_state, 0, 0, |state| {
+                load_from_std_lib(state, libs);
+            }),
+            "Error during loading standard libraries"
+        );
+
+        lua
     }
 
     /// Loads the specified set of standard libraries into an existing Lua state.
@@ -111,6 +242,38 @@ impl Lua {
         }
     }
 
+    /// Returns the version of the Lua implementation that mlua was built with.
+    ///
+    /// The version is chosen at compile time with one of the `lua53`, `lua52`, `lua51` and
+    /// `luajit` features.
+    pub fn version() -> LuaVersion {
+        #[cfg(feature = "lua53")]
+        return LuaVersion::Lua53;
+        #[cfg(feature = "lua52")]
+        return LuaVersion::Lua52;
+        #[cfg(feature = "lua51")]
+        return LuaVersion::Lua51;
+        #[cfg(feature = "luajit")]
+        return LuaVersion::LuaJit;
+    }
+
+    /// Returns the size in bytes of the Lua integer type, [`Integer`].
+    ///
+    /// Only Lua 5.3 has an integer number subtype. With older versions, Lua numbers are always
+    /// floats, and `Integer` is only used when converting numbers in the C API.
+    ///
+    /// [`Integer`]: type.Integer.html
+    pub fn integer_size() -> usize {
+        mem::size_of::<Integer>()
+    }
+
+    /// Returns the size in bytes of the Lua float type, [`Number`].
+    ///
+    /// [`Number`]: type.Number.html
+    pub fn float_size() -> usize {
+        mem::size_of::<Number>()
+    }
+
     /// Constructs a new Lua instance from the existing state.
     pub unsafe fn init_from_ptr(state: *mut ffi::lua_State) -> Lua {
         #[cfg(any(feature = "lua53", feature = "lua52"))]
@@ -163,6 +326,44 @@ impl Lua {
 
                 ffi::lua_rawset(state, ffi::LUA_REGISTRYINDEX);
 
+                // Create the flag shared with `InterruptHandle`s, unless another `Lua` instance for
+                // this state has already done so.
+                #[cfg(not(feature = "luajit"))]
+                {
+                    ffi::lua_pushlightuserdata(
+                        state,
+                        &INTERRUPT_FLAG_REGISTRY_KEY as *const u8 as *mut c_void,
+                    );
+                    ffi::lua_rawget(state, ffi::LUA_REGISTRYINDEX);
+                    let has_interrupt_flag = ffi::lua_isnil(state, -1) == 0;
+                    ffi::lua_pop(state, 1);
+
+                    if !has_interrupt_flag {
+                        ffi::lua_pushlightuserdata(
+                            state,
+                            &INTERRUPT_FLAG_REGISTRY_KEY as *const u8 as *mut c_void,
+                        );
+
+                        let flag = ffi::lua_newuserdata(state, mem::size_of::<Arc<AtomicBool>>())
+                            as *mut Arc<AtomicBool>;
+                        ptr::write(flag, Arc::new(AtomicBool::new(false)));
+
+                        ffi::lua_newtable(state);
+
+                        ffi::lua_pushstring(state, cstr!("__gc"));
+                        ffi::lua_pushcfunction(state, userdata_destructor::<Arc<AtomicBool>>);
+                        ffi::lua_rawset(state, -3);
+
+                        ffi::lua_pushstring(state, cstr!("__metatable"));
+                        ffi::lua_pushboolean(state, 0);
+                        ffi::lua_rawset(state, -3);
+
+                        ffi::lua_setmetatable(state, -2);
+
+                        ffi::lua_rawset(state, ffi::LUA_REGISTRYINDEX);
+                    }
+                }
+
                 // Create ref stack thread and place it in the registry to prevent it from being garbage
                 // collected.
 
@@ -177,14 +378,41 @@ impl Lua {
 
         let extra = Arc::new(RefCell::new(ExtraData {
             registered_userdata: HashMap::new(),
+            registered_userdata_mt: HashMap::new(),
             registry_unref_list: Arc::new(Mutex::new(Some(Vec::new()))),
             ref_thread,
             // We need 1 extra stack space to move values in and out of the ref stack.
             ref_stack_size: ffi::LUA_MINSTACK - 1,
             ref_stack_max: 0,
             ref_free: Vec::new(),
+            callback_extra: ffi::LUA_NOREF,
+            resume_traceback: true,
+            float_policy: FloatPolicy::Passthrough,
+            coercion: Coercion::Lenient,
+            callback_depth: 0,
+            max_callback_depth: DEFAULT_MAX_CALLBACK_DEPTH,
+            no_yield_states: Vec::new(),
+            drop_callbacks: Vec::new(),
+            #[cfg(not(feature = "luajit"))]
+            allocator: None,
         }));
 
+        let callback_extra = mlua_expect!(
+            push_userdata::<Arc<RefCell<ExtraData>>>(main_state, extra.clone()).and_then(|_| {
+                ffi::lua_pushlightuserdata(
+                    main_state,
+                    &FUNCTION_EXTRA_METATABLE_REGISTRY_KEY as *const u8 as *mut c_void,
+                );
+                ffi::lua_rawget(main_state, ffi::LUA_REGISTRYINDEX);
+                ffi::lua_setmetatable(main_state, -2);
+                protect_lua_closure(main_state, 1, 0, |state| {
+                    ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+                })
+            }),
+            "Error during Lua construction",
+        );
+        extra.borrow_mut().callback_extra = callback_extra;
+
         mlua_debug_assert!(
             ffi::lua_gettop(main_state) == main_state_top,
             "stack leak during creation"
@@ -302,6 +530,206 @@ impl Lua {
         }
     }
 
+    /// Checks that `source` is syntactically valid Lua code, without running it.
+    ///
+    /// The source is compiled and the resulting function discarded. On failure, this returns an
+    /// [`Error::SyntaxError`], whose `incomplete_input` flag tells whether the source ends
+    /// prematurely, so that a REPL can keep reading more lines.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Error, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.check_syntax("error('not run')")?;
+    ///
+    /// match lua.check_syntax("if x then") {
+    ///     Err(Error::SyntaxError { incomplete_input, .. }) => assert!(incomplete_input),
+    ///     r => panic!("unexpected result: {:?}", r),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Error::SyntaxError`]: enum.Error.html#variant.SyntaxError
+    pub fn check_syntax<S>(&self, source: &S) -> Result<()>
+    where
+        S: ?Sized + AsRef<[u8]>,
+    {
+        self.load_chunk(source.as_ref(), None, None).map(|_| ())
+    }
+
+    /// Evaluates Lua source code with the given arguments.
+    ///
+    /// This is a shorthand for `lua.load(source).eval_with(args)`: the source is evaluated as an
+    /// expression if possible, and as a block otherwise. The arguments are available to the code
+    /// as varargs (`...`), like for any other chunk.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let sum: i64 = lua.eval_with("select(1, ...) + select(2, ...)", (1, 2))?;
+    /// assert_eq!(sum, 3);
+    ///
+    /// let greeting: String = lua.eval_with("local name = ... return 'hello ' .. name", "world")?;
+    /// assert_eq!(greeting, "hello world");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_with<'lua, S, A, R>(&'lua self, source: &S, args: A) -> Result<R>
+    where
+        S: ?Sized + AsRef<[u8]>,
+        A: ToLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        self.load(source).eval_with(args)
+    }
+
+    /// Compiles Lua source code into a `Function` with the given chunk name.
+    ///
+    /// This is a shorthand for `lua.load(source).set_name(name)?.into_function()`. The source is
+    /// parsed once, and the returned function can be called repeatedly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let double = lua.compile("double", "return ... * 2")?;
+    /// for i in 0..10 {
+    ///     assert_eq!(double.call::<_, i64>(i)?, i * 2);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn compile<'lua, S>(&'lua self, name: &str, source: &S) -> Result<Function<'lua>>
+    where
+        S: ?Sized + AsRef<[u8]>,
+    {
+        self.load(source).set_name(name)?.into_function()
+    }
+
+    /// Loads a Lua chunk from `reader`, returning it as a function without running it.
+    ///
+    /// The source is read in pieces and fed to the Lua compiler as it goes, so it never has to be
+    /// held in memory all at once. `name` is used as the chunk name, like [`Chunk::set_name`].
+    /// Only text chunks are accepted.
+    ///
+    /// If reading fails, compilation is aborted and the I/O error is returned as
+    /// [`Error::ExternalError`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let source: &[u8] = b"return 1 + 2";
+    /// let func = lua.load_from_reader("stream", source)?;
+    /// assert_eq!(func.call::<_, i64>(())?, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Chunk::set_name`]: struct.Chunk.html#method.set_name
+    /// [`Error::ExternalError`]: enum.Error.html#variant.ExternalError
+    pub fn load_from_reader<'lua, R: Read>(
+        &'lua self,
+        name: &str,
+        reader: R,
+    ) -> Result<Function<'lua>> {
+        struct ReaderState<R> {
+            reader: R,
+            buffer: Vec<u8>,
+            started: bool,
+            error: Option<Error>,
+            panic: Option<Box<dyn Any + Send>>,
+        }
+
+        unsafe extern "C" fn read_chunk<R: Read>(
+            _state: *mut ffi::lua_State,
+            data: *mut c_void,
+            size: *mut usize,
+        ) -> *const c_char {
+            let rs = &mut *(data as *mut ReaderState<R>);
+            *size = 0;
+            if rs.error.is_some() || rs.panic.is_some() {
+                return ptr::null();
+            }
+
+            let read = catch_unwind(AssertUnwindSafe(|| loop {
+                match rs.reader.read(&mut rs.buffer) {
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    res => return res,
+                }
+            }));
+            match read {
+                Ok(Ok(0)) => ptr::null(),
+                Ok(Ok(n)) => {
+                    if !rs.started && rs.buffer[0] == ffi::LUA_SIGNATURE[0] {
+                        rs.error = Some(Error::SyntaxError {
+                            message: "attempt to load a binary chunk".to_string(),
+                            incomplete_input: false,
+                        });
+                        return ptr::null();
+                    }
+                    rs.started = true;
+                    *size = n;
+                    rs.buffer.as_ptr() as *const c_char
+                }
+                Ok(Err(err)) => {
+                    rs.error = Some(Error::external(err));
+                    ptr::null()
+                }
+                Err(p) => {
+                    rs.panic = Some(p);
+                    ptr::null()
+                }
+            }
+        }
+
+        let name = CString::new(name).map_err(|e| Error::ToLuaConversionError {
+            from: "&str",
+            to: "string",
+            message: Some(e.to_string()),
+        })?;
+        let mut rs = ReaderState {
+            reader,
+            buffer: vec![0; 8192],
+            started: false,
+            error: None,
+            panic: None,
+        };
+
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 1);
+
+            let status = ffi::lua_load(
+                self.state,
+                read_chunk::<R>,
+                &mut rs as *mut ReaderState<R> as *mut c_void,
+                name.as_ptr(),
+                cstr!("t"),
+            );
+            if let Some(p) = rs.panic.take() {
+                resume_unwind(p);
+            }
+            if let Some(err) = rs.error.take() {
+                return Err(err);
+            }
+            match status {
+                ffi::LUA_OK => Ok(Function(self.pop_ref())),
+                err => Err(pop_error(self.state, err)),
+            }
+        }
+    }
+
     fn load_chunk<'lua>(
         &'lua self,
         source: &[u8],
@@ -373,24 +801,202 @@ impl Lua {
         }
     }
 
+    /// Creates an empty table whose reads are served by a Rust callback.
+    ///
+    /// `index_fn` is installed as the `__index` metamethod, so reading any key not present in the
+    /// table calls it with that key and returns its result. This allows exposing large or dynamic
+    /// data (host configuration, for instance) without materializing it in Lua.
+    ///
+    /// Writes are stored in the table itself, which then shadows `index_fn` for those keys. Use
+    /// [`Table::set_newindex_handler`] on the returned table to route writes to Rust as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Error, Lua, Result, Table, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let squares = lua.create_virtual_table(|_, key| match key {
+    ///     Value::Integer(i) => Ok(Value::Integer(i * i)),
+    ///     _ => Ok(Value::Nil),
+    /// })?;
+    /// squares.set_newindex_handler(lua.create_function(|_, (_, key, _): (Table, Value, Value)| {
+    ///     Err::<(), _>(Error::RuntimeError(format!("cannot assign to {:?}", key)))
+    /// })?)?;
+    ///
+    /// lua.globals().set("squares", squares)?;
+    /// assert_eq!(lua.load("squares[12]").eval::<i64>()?, 144);
+    /// # #[cfg(not(feature = "luajit"))]
+    /// assert!(lua.load("squares[1] = 2").exec().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Table::set_newindex_handler`]: struct.Table.html#method.set_newindex_handler
+    pub fn create_virtual_table<'lua, 'callback, F>(&'lua self, index_fn: F) -> Result<Table<'lua>>
+    where
+        F: 'static + Send + Fn(&'callback Lua, Value<'callback>) -> Result<Value<'callback>>,
+    {
+        let table = self.create_table()?;
+        let index =
+            self.create_function(move |lua, (_, key): (Table, Value)| index_fn(lua, key))?;
+        table.set_index_handler(index)?;
+        Ok(table)
+    }
+
     /// Creates a table and fills it with values from an iterator.
+    ///
+    /// The pairs are set in iteration order without invoking metamethods, so if the iterator
+    /// yields the same key more than once, the last value wins. The table is preallocated using
+    /// the lower bound of the iterator's `size_hint`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table = lua.create_table_from(vec![("a", 1), ("b", 2), ("a", 3)])?;
+    /// assert_eq!(table.get::<_, i32>("a")?, 3);
+    /// assert_eq!(table.get::<_, i32>("b")?, 2);
+    /// # Ok(())
+    /// # }
+    /// ```
     pub fn create_table_from<'lua, K, V, I>(&'lua self, cont: I) -> Result<Table<'lua>>
     where
         K: ToLua<'lua>,
         V: ToLua<'lua>,
         I: IntoIterator<Item = (K, V)>,
     {
+        let cont = cont.into_iter();
+        let nrec = cont.size_hint().0;
+        self.create_table_with_pairs(0, nrec, cont)
+    }
+
+    /// Creates a table from an iterator of values, using `1..` as the keys.
+    pub fn create_sequence_from<'lua, T, I>(&'lua self, cont: I) -> Result<Table<'lua>>
+    where
+        T: ToLua<'lua>,
+        I: IntoIterator<Item = T>,
+    {
+        let cont = cont.into_iter();
+        let narr = cont.size_hint().0;
+        self.create_table_with_pairs(narr, 0, cont.enumerate().map(|(k, v)| (k + 1, v)))
+    }
+
+    /// Creates a table which remembers the order in which its keys were inserted.
+    ///
+    /// The returned table is an empty proxy: its contents live in a hidden table, and its
+    /// metatable records every new key in an order index as it is assigned. Use
+    /// [`Table::ordered_pairs`] to iterate it in insertion order; with Lua 5.2 and 5.3, the Lua
+    /// `pairs` function does the same. Assigning `nil` to a key removes it from the index, and
+    /// assigning it again later moves it to the end.
+    ///
+    /// Keeping the index has a cost: every new key is also appended to the index, and removing a
+    /// key is linear in the number of keys. Since the proxy itself is empty, raw accesses (such as
+    /// [`Table::raw_get`] or [`Table::pairs`]) do not see its contents; use [`Table::get`] and
+    /// [`Table::set`] instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config = lua.create_ordered_table()?;
+    /// config.set("name", "app")?;
+    /// config.set("version", 3)?;
+    /// config.set("debug", false)?;
+    ///
+    /// let keys = config
+    ///     .ordered_pairs::<String, mlua::Value>()?
+    ///     .into_iter()
+    ///     .map(|(k, _)| k)
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(keys, vec!["name", "version", "debug"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Table::ordered_pairs`]: struct.Table.html#method.ordered_pairs
+    /// [`Table::raw_get`]: struct.Table.html#method.raw_get
+    /// [`Table::pairs`]: struct.Table.html#method.pairs
+    /// [`Table::get`]: struct.Table.html#method.get
+    /// [`Table::set`]: struct.Table.html#method.set
+    pub fn create_ordered_table(&self) -> Result<Table> {
+        // The chunk is shared by every ordered table and must not depend on globals, which scripts
+        // may have replaced
+        let init = self.cached_chunk(
+            &ORDERED_TABLE_REGISTRY_KEY,
+            "=create_ordered_table",
+            r#"
+                local marker = ...
+                local data, keys, positions = {}, {}, {}
+                local mt = {
+                    __index = data,
+                    __newindex = function(_, k, v)
+                        local pos = positions[k]
+                        if v == nil then
+                            if pos ~= nil then
+                                local n = #keys
+                                for i = pos, n - 1 do
+                                    keys[i] = keys[i + 1]
+                                    positions[keys[i]] = i
+                                end
+                                keys[n] = nil
+                                positions[k] = nil
+                            end
+                        elseif pos == nil then
+                            keys[#keys + 1] = k
+                            positions[k] = #keys
+                        end
+                        data[k] = v
+                    end,
+                    __pairs = function(t)
+                        local i = 0
+                        return function()
+                            i = i + 1
+                            local k = keys[i]
+                            if k ~= nil then
+                                return k, data[k]
+                            end
+                        end, t, nil
+                    end,
+                }
+                mt[marker] = keys
+                return {}, mt
+                "#,
+        )?;
+        let (proxy, mt): (Table, Table) = init.call(ordered_keys_marker())?;
+        proxy.set_metatable(Some(mt));
+        Ok(proxy)
+    }
+
+    // Creates a table with space preallocated for `narr` sequence elements and `nrec` other
+    // elements, and raw sets the given pairs in order.
+    fn create_table_with_pairs<'lua, K, V, I>(
+        &'lua self,
+        narr: usize,
+        nrec: usize,
+        cont: I,
+    ) -> Result<Table<'lua>>
+    where
+        K: ToLua<'lua>,
+        V: ToLua<'lua>,
+        I: Iterator<Item = (K, V)>,
+    {
+        let narr = narr.min(c_int::max_value() as usize) as c_int;
+        let nrec = nrec.min(c_int::max_value() as usize) as c_int;
+
         unsafe {
             let _sg = StackGuard::new(self.state);
             // `Lua` instance assumes that on any callback, the Lua stack has at least LUA_MINSTACK
             // slots available to avoid panics.
             check_stack(self.state, 5 + ffi::LUA_MINSTACK)?;
 
-            unsafe extern "C" fn new_table(state: *mut ffi::lua_State) -> c_int {
-                ffi::lua_newtable(state);
-                1
-            }
-            protect_lua(self.state, 0, new_table)?;
+            protect_lua_closure(self.state, 0, 1, |state| {
+                ffi::lua_createtable(state, narr, nrec)
+            })?;
 
             for (k, v) in cont {
                 self.push_value(k.to_lua(self)?)?;
@@ -405,15 +1011,6 @@ impl Lua {
         }
     }
 
-    /// Creates a table from an iterator of values, using `1..` as the keys.
-    pub fn create_sequence_from<'lua, T, I>(&'lua self, cont: I) -> Result<Table<'lua>>
-    where
-        T: ToLua<'lua>,
-        I: IntoIterator<Item = T>,
-    {
-        self.create_table_from(cont.into_iter().enumerate().map(|(k, v)| (k + 1, v)))
-    }
-
     /// Wraps a Rust function or closure, creating a callable Lua function handle to it.
     ///
     /// The function's return value is always a `Result`: If the function returns `Err`, the error
@@ -457,8 +1054,33 @@ impl Lua {
     /// # }
     /// ```
     ///
+    /// Errors of other types can be raised with [`ExternalResult::to_lua_err`]. Note that if the
+    /// function returns `Ok` with a nested `Result`, the inner result is converted to values
+    /// instead, following the Lua idiom of returning `nil` and an error on failure:
+    ///
+    /// ```
+    /// # use mlua::{ExternalResult, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// // Raises a Lua error if the argument is not a number
+    /// let parse = lua.create_function(|_, s: String| s.parse::<i64>().to_lua_err())?;
+    /// lua.globals().set("parse", parse)?;
+    /// # #[cfg(not(feature = "luajit"))]
+    /// assert!(lua.load("parse('nan')").exec().is_err());
+    ///
+    /// // Returns `nil` and an error message if the argument is not a number
+    /// let try_parse = lua.create_function(|_, s: String| {
+    ///     Ok(s.parse::<i64>().map_err(|e| e.to_string()))
+    /// })?;
+    /// lua.globals().set("try_parse", try_parse)?;
+    /// lua.load("local n, err = try_parse('nan'); assert(n == nil and err ~= nil)").exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
     /// [`ToLua`]: trait.ToLua.html
     /// [`ToLuaMulti`]: trait.ToLuaMulti.html
+    /// [`ExternalResult::to_lua_err`]: trait.ExternalResult.html#tymethod.to_lua_err
     pub fn create_function<'lua, 'callback, A, R, F>(&'lua self, func: F) -> Result<Function<'lua>>
     where
         A: FromLuaMulti<'callback>,
@@ -470,17 +1092,63 @@ impl Lua {
         }))
     }
 
-    /// Wraps a Rust mutable closure, creating a callable Lua function handle to it.
+    /// Wraps a Rust function or closure whose arguments have names, creating a callable Lua
+    /// function handle to it.
     ///
-    /// This is a version of [`create_function`] that accepts a FnMut argument.  Refer to
-    /// [`create_function`] for more information about the implementation.
+    /// This works like [`create_function`], but `names` are the names of the function's
+    /// arguments, in order. When an argument cannot be converted, the message of the resulting
+    /// `Error::FromLuaConversionError` starts with the argument's position and name, for example
+    /// "bad argument #2 'count'". Arguments beyond the end of `names`, as well as those collected
+    /// by a trailing `Variadic` or `MultiValue`, are reported as with [`create_function`].
     ///
-    /// [`create_function`]: #method.create_function
-    pub fn create_function_mut<'lua, 'callback, A, R, F>(
-        &'lua self,
-        func: F,
-    ) -> Result<Function<'lua>>
-    where
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let repeat = lua.create_function_named(&["text", "count"], |_, (text, count): (String, usize)| {
+    ///     Ok(text.repeat(count))
+    /// })?;
+    /// lua.globals().set("repeat_text", repeat)?;
+    ///
+    /// # #[cfg(not(feature = "luajit"))]
+    /// # {
+    /// let err = lua.load("repeat_text('ab', {})").exec().unwrap_err();
+    /// assert!(err.to_string().contains("bad argument #2 'count'"));
+    /// # }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`create_function`]: #method.create_function
+    pub fn create_function_named<'lua, 'callback, A, R, F>(
+        &'lua self,
+        names: &[&str],
+        func: F,
+    ) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'callback>,
+        R: ToLuaMulti<'callback>,
+        F: 'static + Send + Fn(&'callback Lua, A) -> Result<R>,
+    {
+        let names: Vec<StdString> = names.iter().map(|name| name.to_string()).collect();
+        self.create_callback(Box::new(move |lua, args| {
+            func(lua, A::from_lua_args(args, 0, &names, lua)?)?.to_lua_multi(lua)
+        }))
+    }
+
+    /// Wraps a Rust mutable closure, creating a callable Lua function handle to it.
+    ///
+    /// This is a version of [`create_function`] that accepts a FnMut argument.  Refer to
+    /// [`create_function`] for more information about the implementation.
+    ///
+    /// [`create_function`]: #method.create_function
+    pub fn create_function_mut<'lua, 'callback, A, R, F>(
+        &'lua self,
+        func: F,
+    ) -> Result<Function<'lua>>
+    where
         A: FromLuaMulti<'callback>,
         R: ToLuaMulti<'callback>,
         F: 'static + Send + FnMut(&'callback Lua, A) -> Result<R>,
@@ -489,10 +1157,206 @@ impl Lua {
         self.create_function(move |lua, args| {
             (&mut *func
                 .try_borrow_mut()
-                .map_err(|_| Error::RecursiveMutCallback)?)(lua, args)
+                .map_err(|_| Error::RecursiveMutCallback { method: None })?)(lua, args)
         })
     }
 
+    /// Wraps a Rust iterator into a Lua iterator function, for use in a generic `for` loop.
+    ///
+    /// Every call of the returned function advances `iter` and returns the next item, or `nil`
+    /// once the iterator is exhausted (and on any later call). Items are produced lazily, so the
+    /// whole sequence never needs to be materialized in a table. An `Err` item is raised as a Lua
+    /// error from the call that reached it.
+    ///
+    /// Since `nil` marks the end of the iteration, a loop stops early at an item converting to
+    /// `nil`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let squares = lua.create_iterator((1..=4).map(|i| Ok(i * i)))?;
+    /// lua.globals().set("squares", squares)?;
+    ///
+    /// let sum: i64 = lua.load(r#"
+    ///     local sum = 0
+    ///     for x in squares do
+    ///         sum = sum + x
+    ///     end
+    ///     return sum
+    /// "#).eval()?;
+    /// assert_eq!(sum, 30);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_iterator<'lua, 'callback, T, I>(&'lua self, iter: I) -> Result<Function<'lua>>
+    where
+        T: ToLua<'callback>,
+        I: 'static + Send + Iterator<Item = Result<T>>,
+    {
+        let mut iter = iter.fuse();
+        self.create_function_mut(move |_, ()| iter.next().transpose())
+    }
+
+    /// Wraps a raw C function into a Lua function, with the given values as its upvalues.
+    ///
+    /// Unlike [`create_function`], no conversion of arguments or return values takes place: the
+    /// function works directly with the Lua stack, as with `lua_pushcclosure` in the C API.
+    /// Upvalues are available to it at pseudo-indices [`lua_upvalueindex(1)`] and up, in the order
+    /// given.
+    ///
+    /// # Safety
+    ///
+    /// `func` must follow the Lua C API rules for functions called by Lua. In particular, it
+    /// must not raise a Lua error (or `longjmp`) across Rust frames with destructors, nor panic.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{lua_State, Lua, Result};
+    /// # use std::os::raw::c_int;
+    /// extern "C" {
+    ///     fn lua_gettop(state: *mut lua_State) -> c_int;
+    /// }
+    ///
+    /// // Returns all of its arguments
+    /// unsafe extern "C" fn identity(state: *mut lua_State) -> c_int {
+    ///     lua_gettop(state)
+    /// }
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let f = unsafe { lua.create_c_function(identity, &[])? };
+    /// assert_eq!(f.call::<_, (i64, String)>((1, "two"))?, (1, "two".to_string()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`create_function`]: #method.create_function
+    /// [`lua_upvalueindex(1)`]: fn.lua_upvalueindex.html
+    pub unsafe fn create_c_function<'lua>(
+        &'lua self,
+        func: ffi::lua_CFunction,
+        upvalues: &[Value<'lua>],
+    ) -> Result<Function<'lua>> {
+        let nupvalues = upvalues.len() as c_int;
+        if nupvalues > ffi::LUA_MAX_UPVALUES {
+            return Err(Error::RuntimeError("too many upvalues".to_owned()));
+        }
+
+        let _sg = StackGuard::new(self.state);
+        check_stack(self.state, nupvalues + 3)?;
+
+        for value in upvalues {
+            self.push_value(value.clone())?;
+        }
+        protect_lua_closure(self.state, nupvalues, 1, |state| {
+            ffi::lua_pushcclosure(state, func, nupvalues);
+        })?;
+
+        Ok(Function(self.pop_ref()))
+    }
+
+    /// Returns the index of the top element of the current Lua stack.
+    ///
+    /// This is the number of values on the stack of the state this `Lua` currently operates on,
+    /// which is useful for checking the stack balance of raw FFI code run with [`with_stack`].
+    ///
+    /// [`with_stack`]: #method.with_stack
+    pub fn stack_top(&self) -> c_int {
+        unsafe { ffi::lua_gettop(self.state) }
+    }
+
+    /// Calls `f` with the raw Lua state, restoring the stack top afterwards.
+    ///
+    /// This is an entry point for FFI code that needs to manipulate the Lua stack directly. Any
+    /// values that `f` leaves on the stack are removed when it returns.
+    ///
+    /// Using the state requires `unsafe` code, which must follow the Lua C API rules: `f` must
+    /// ensure there is enough stack space with `lua_checkstack`, must not raise a Lua error (or
+    /// `longjmp`) across Rust frames, and must not pop values it did not push. Popping too many
+    /// values is a logic error which results in a panic.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{lua_State, Lua, Result};
+    /// # use std::os::raw::c_int;
+    /// extern "C" {
+    ///     fn lua_pushboolean(state: *mut lua_State, b: c_int);
+    ///     fn lua_gettop(state: *mut lua_State) -> c_int;
+    /// }
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let top = lua.stack_top();
+    /// let pushed = lua.with_stack(|state| unsafe {
+    ///     lua_pushboolean(state, 1);
+    ///     lua_gettop(state)
+    /// });
+    /// assert_eq!(pushed, top + 1);
+    /// assert_eq!(lua.stack_top(), top);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_stack<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(*mut ffi::lua_State) -> R,
+    {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            f(self.state)
+        }
+    }
+
+    /// Pushes `value` onto the Lua stack and keeps it there until the returned guard is dropped.
+    ///
+    /// The value stays at [`PinnedValue::index`] for the lifetime of the guard, so FFI code run
+    /// with [`with_stack`] can operate on it directly without pushing it again every time. Other
+    /// API calls can be made while values are pinned.
+    ///
+    /// Pinned values must be released in the reverse order they were pinned in, and any values
+    /// pushed above them must be removed first. Dropping a guard while the value is not at the top
+    /// of the stack is a logic error which results in a panic.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{lua_State, Lua, Result, Value};
+    /// # use std::os::raw::c_int;
+    /// extern "C" {
+    ///     fn lua_type(state: *mut lua_State, idx: c_int) -> c_int;
+    /// }
+    /// const LUA_TTABLE: c_int = 5;
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table = lua.create_sequence_from(vec![1, 2, 3])?;
+    /// let pinned = lua.pin(&Value::Table(table))?;
+    /// for _ in 0..3 {
+    ///     let ty = lua.with_stack(|state| unsafe { lua_type(state, pinned.index()) });
+    ///     assert_eq!(ty, LUA_TTABLE);
+    /// }
+    /// drop(pinned);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`PinnedValue::index`]: struct.PinnedValue.html#method.index
+    /// [`with_stack`]: #method.with_stack
+    pub fn pin<'lua>(&'lua self, value: &Value<'lua>) -> Result<PinnedValue<'lua>> {
+        unsafe {
+            check_stack(self.state, 1)?;
+            self.push_value(value.clone())?;
+            Ok(PinnedValue {
+                lua: self,
+                index: ffi::lua_gettop(self.state),
+            })
+        }
+    }
+
     /// Wraps a Lua function into a new thread (or coroutine).
     ///
     /// Equivalent to `coroutine.create`.
@@ -503,14 +1367,121 @@ impl Lua {
 
             let thread_state =
                 protect_lua_closure(self.state, 0, 1, |state| ffi::lua_newthread(state))?;
-            self.push_ref(&func.0);
+            self.push_owned_ref(&func.0)?;
             ffi::lua_xmove(self.state, thread_state, 1);
 
             Ok(Thread(self.pop_ref()))
         }
     }
 
+    /// Wraps a Rust function into a new thread (or coroutine).
+    ///
+    /// This is a shorthand for [`create_function`] followed by [`create_thread`]. Arguments passed
+    /// to the first [`Thread::resume`] are given to `func`, and its return values are returned by
+    /// that `resume`.
+    ///
+    /// `func` can suspend the thread by returning the [`Yield`] created by [`yield_with`]: the
+    /// values given to it are returned by the current `resume`, and the values passed to the next
+    /// `resume` become the return values of the thread. Calling `coroutine.yield` from inside `func` raises an "attempt to yield
+    /// across a C-call boundary" error instead, since Rust frames cannot be suspended.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, ThreadStatus};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let thread = lua.create_thread_from_fn(|_, (a, b): (i64, i64)| Ok(a + b))?;
+    ///
+    /// assert_eq!(thread.resume::<_, i64>((1, 2))?, 3);
+    /// assert_eq!(thread.status(), ThreadStatus::Unresumable);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`create_function`]: #method.create_function
+    /// [`create_thread`]: #method.create_thread
+    /// [`Thread::resume`]: struct.Thread.html#method.resume
+    /// [`Yield`]: struct.Yield.html
+    /// [`yield_with`]: #method.yield_with
+    pub fn create_thread_from_fn<'lua, 'callback, A, R, F>(
+        &'lua self,
+        func: F,
+    ) -> Result<Thread<'lua>>
+    where
+        A: FromLuaMulti<'callback>,
+        R: ToLuaMulti<'callback>,
+        F: 'static + Send + Fn(&'callback Lua, A) -> Result<R>,
+    {
+        let func = self.create_function(func)?;
+        // Lua 5.1 cannot resume a coroutine whose body is a C function that yielded, so `func` is
+        // called from a Lua function there to make `yield_with` work
+        #[cfg(feature = "lua51")]
+        let func: Function = self
+            .load("local func = ... return function(...) return func(...) end")
+            .set_name("=create_thread_from_fn")?
+            .into_function()?
+            .call(func)?;
+        self.create_thread(func)
+    }
+
+    /// Runs a set of threads cooperatively until none of them can be resumed.
+    ///
+    /// Each thread which is [`Resumable`] is resumed in turn without arguments, round-robin, and
+    /// the values it yields are discarded. Threads which finish are removed from the set. This
+    /// returns once no thread is left, or with the first error raised by any of them, in which case
+    /// the remaining threads are left suspended.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Thread};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.load(r#"
+    ///     log = {}
+    ///     function worker(name)
+    ///         return coroutine.create(function()
+    ///             for i = 1, 2 do
+    ///                 table.insert(log, name .. i)
+    ///                 coroutine.yield()
+    ///             end
+    ///         end)
+    ///     end
+    /// "#).exec()?;
+    ///
+    /// let a: Thread = lua.load("worker('a')").eval()?;
+    /// let b: Thread = lua.load("worker('b')").eval()?;
+    /// lua.run_scheduler(vec![a, b])?;
+    ///
+    /// let log: Vec<String> = lua.load("log").eval()?;
+    /// assert_eq!(log, vec!["a1", "b1", "a2", "b2"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Resumable`]: enum.ThreadStatus.html#variant.Resumable
+    pub fn run_scheduler<'lua, I>(&'lua self, threads: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Thread<'lua>>,
+    {
+        let mut queue: VecDeque<Thread> = threads.into_iter().collect();
+        while let Some(thread) = queue.pop_front() {
+            if thread.status() != ThreadStatus::Resumable {
+                continue;
+            }
+            thread.resume::<_, MultiValue>(())?;
+            if thread.status() == ThreadStatus::Resumable {
+                queue.push_back(thread);
+            }
+        }
+        Ok(())
+    }
+
     /// Create a Lua userdata object from a custom userdata type.
+    ///
+    /// The metatable for `T` is built on first use and shared by all userdata of that type
+    /// afterwards.
     pub fn create_userdata<T>(&self, data: T) -> Result<AnyUserData>
     where
         T: 'static + Send + UserData,
@@ -518,6 +1489,222 @@ impl Lua {
         unsafe { self.make_userdata(data) }
     }
 
+    /// Builds and caches the metatable for userdata of type `T` ahead of time.
+    ///
+    /// This is normally done on the first call to [`create_userdata`] for a type. Registering it
+    /// up front moves that cost to startup, and surfaces any error while setting up the methods of
+    /// `T` early. Registering the same type more than once does nothing.
+    ///
+    /// [`create_userdata`]: #method.create_userdata
+    pub fn register_userdata_metatable<T>(&self) -> Result<()>
+    where
+        T: 'static + UserData,
+    {
+        unsafe { self.userdata_metatable::<T>().map(|_| ()) }
+    }
+
+    /// Sets whether errors raised while resuming a [`Thread`] include a traceback.
+    ///
+    /// By default, an error from [`Thread::resume`] carries a traceback of the coroutine's stack,
+    /// like errors from [`Function::call`]. Building the traceback can be expensive for deep
+    /// stacks, so code that resumes coroutines in a hot loop and does not need it can disable it.
+    /// The error message is still returned, and errors from Rust callbacks are returned as they
+    /// are instead of being wrapped in [`Error::CallbackError`].
+    ///
+    /// [`Thread`]: struct.Thread.html
+    /// [`Thread::resume`]: struct.Thread.html#method.resume
+    /// [`Function::call`]: struct.Function.html#method.call
+    /// [`Error::CallbackError`]: enum.Error.html#variant.CallbackError
+    pub fn set_error_traceback(&self, enabled: bool) {
+        self.extra.borrow_mut().resume_traceback = enabled;
+    }
+
+    pub(crate) fn resume_traceback(&self) -> bool {
+        self.extra.borrow().resume_traceback
+    }
+
+    /// Sets how NaN and infinite `f32` and `f64` values are converted to Lua.
+    ///
+    /// This applies to the [`ToLua`] implementations of `f32` and `f64`, including when they are
+    /// passed as function arguments or returned from callbacks. The default is
+    /// [`FloatPolicy::Passthrough`]. Values which are already Lua numbers are not affected.
+    ///
+    /// [`ToLua`]: trait.ToLua.html
+    /// [`FloatPolicy::Passthrough`]: enum.FloatPolicy.html#variant.Passthrough
+    pub fn set_float_policy(&self, policy: FloatPolicy) {
+        self.extra.borrow_mut().float_policy = policy;
+    }
+
+    pub(crate) fn float_policy(&self) -> FloatPolicy {
+        self.extra.borrow().float_policy
+    }
+
+    /// Sets whether converting Lua values to Rust types coerces between strings and numbers.
+    ///
+    /// With the default [`Coercion::Lenient`], the [`FromLua`] implementations follow Lua's own
+    /// coercion rules:
+    ///
+    /// - `String`, `std::string::String` and `CString` accept Lua numbers, which are
+    ///   formatted like Lua does.
+    /// - The integer and float types accept strings which Lua can convert to a number, such as
+    ///   `"42"` or `" 0x10 "`.
+    ///
+    /// With [`Coercion::Strict`], these conversions fail with `Error::FromLuaConversionError`
+    /// instead. Other conversions are not affected: for example, integer types still accept
+    /// floats with an exact integer value. [`coerce_string`], [`coerce_integer`] and
+    /// [`coerce_number`] always coerce.
+    ///
+    /// [`Coercion::Lenient`]: enum.Coercion.html#variant.Lenient
+    /// [`Coercion::Strict`]: enum.Coercion.html#variant.Strict
+    /// [`FromLua`]: trait.FromLua.html
+    /// [`coerce_string`]: #method.coerce_string
+    /// [`coerce_integer`]: #method.coerce_integer
+    /// [`coerce_number`]: #method.coerce_number
+    pub fn set_coercion(&self, coercion: Coercion) {
+        self.extra.borrow_mut().coercion = coercion;
+    }
+
+    pub(crate) fn coercion(&self) -> Coercion {
+        self.extra.borrow().coercion
+    }
+
+    // Converts a value to a string for `FromLua`, coercing numbers unless coercion is strict.
+    pub(crate) fn convert_string<'lua>(&'lua self, v: Value<'lua>) -> Result<Option<String<'lua>>> {
+        match v {
+            Value::String(s) => Ok(Some(s)),
+            _ if self.extra.borrow().coercion == Coercion::Strict => Ok(None),
+            v => self.coerce_string(v),
+        }
+    }
+
+    // Converts a value to an integer for `FromLua`, coercing strings unless coercion is strict.
+    pub(crate) fn convert_integer(&self, v: Value) -> Result<Option<Integer>> {
+        match v {
+            Value::String(_) if self.extra.borrow().coercion == Coercion::Strict => Ok(None),
+            v => self.coerce_integer(v),
+        }
+    }
+
+    // Converts a value to a number for `FromLua`, coercing strings unless coercion is strict.
+    pub(crate) fn convert_number(&self, v: Value) -> Result<Option<Number>> {
+        match v {
+            Value::String(_) if self.extra.borrow().coercion == Coercion::Strict => Ok(None),
+            v => self.coerce_number(v),
+        }
+    }
+
+    /// Sets the maximum number of nested calls to Rust callbacks.
+    ///
+    /// Every time Lua code calls a Rust callback which in turn calls Lua code, the native stack
+    /// grows. To prevent deeply recursive scripts from overflowing it, calling a Rust callback
+    /// while `depth` callbacks are already running raises an [`Error::RecursionLimit`] error
+    /// instead. The default is 200.
+    ///
+    /// [`Error::RecursionLimit`]: enum.Error.html#variant.RecursionLimit
+    pub fn set_max_callback_depth(&self, depth: usize) {
+        self.extra.borrow_mut().max_callback_depth = depth;
+    }
+
+    /// Registers a function to run when this Lua state is closed.
+    ///
+    /// The state is closed when the `Lua` created by [`new`] or [`new_with`] is dropped. Functions
+    /// registered with this method run right after that, in the order they were registered. By
+    /// then the state has been closed, so all userdata have already been finalized and their
+    /// `Drop` implementations have run. The functions do not run if the `Lua` is leaked.
+    ///
+    /// The functions must be `Send`, since they run on whichever thread drops the `Lua`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::Lua;
+    /// # use std::sync::{Arc, Mutex};
+    /// let log = Arc::new(Mutex::new(Vec::new()));
+    ///
+    /// let lua = Lua::new();
+    /// let log2 = log.clone();
+    /// lua.on_drop(move || log2.lock().unwrap().push("closed"));
+    /// assert!(log.lock().unwrap().is_empty());
+    ///
+    /// drop(lua);
+    /// assert_eq!(*log.lock().unwrap(), vec!["closed"]);
+    /// ```
+    ///
+    /// [`new`]: #method.new
+    /// [`new_with`]: #method.new_with
+    pub fn on_drop<F: 'static + Send + FnOnce()>(&self, f: F) {
+        self.extra.borrow_mut().drop_callbacks.push(Box::new(f));
+    }
+
+    /// Yields from the coroutine running the current Rust callback.
+    ///
+    /// Rust frames cannot be suspended, so this does not yield by itself. Instead it returns a
+    /// [`Yield`] holding `args`, which the callback must return as its result: the coroutine is
+    /// suspended once the callback has returned, and `args` are passed to the `coroutine.resume`
+    /// (or [`Thread::resume`]) call that resumed it. A `Yield` which is dropped instead of being
+    /// returned has no effect.
+    ///
+    /// When the coroutine is resumed again, the callback is not re-entered. Instead, the values
+    /// passed to the resume call become the return values of the callback's call site in Lua.
+    ///
+    /// Returns an error if the current callback does not run on a coroutine (for example on the
+    /// main thread). Yielding across a metamethod or C-call boundary is reported by Lua as a
+    /// runtime error once the callback returns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Thread};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let pause = lua.create_function(|lua, n: i64| lua.yield_with(n * 2))?;
+    /// lua.globals().set("pause", pause)?;
+    ///
+    /// let thread: Thread = lua.load("coroutine.create(function(n) return pause(n) + 1 end)").eval()?;
+    /// assert_eq!(thread.resume::<_, i64>(20)?, 40);
+    /// assert_eq!(thread.resume::<_, i64>(1)?, 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Yield`]: struct.Yield.html
+    /// [`Thread::resume`]: struct.Thread.html#method.resume
+    pub fn yield_with<'lua, A: ToLuaMulti<'lua>>(&'lua self, args: A) -> Result<Yield<'lua>> {
+        if self.extra.borrow().callback_depth == 0 || !self.is_yieldable() {
+            return Err(Error::RuntimeError(
+                "attempt to yield from outside a coroutine".to_string(),
+            ));
+        }
+        if self.extra.borrow().no_yield_states.contains(&self.state) {
+            return Err(Error::RuntimeError(
+                "attempt to yield across a non-yieldable boundary".to_string(),
+            ));
+        }
+        // Lua 5.1 cannot resume into a C function which is the body of a coroutine
+        #[cfg(feature = "lua51")]
+        unsafe {
+            let mut ar: ffi::lua_Debug = mem::zeroed();
+            if ffi::lua_getstack(self.state, 1, &mut ar) == 0 {
+                return Err(Error::RuntimeError(
+                    "attempt to yield from a Rust function used as a coroutine body".to_string(),
+                ));
+            }
+        }
+
+        Ok(Yield::new(args.to_lua_multi(self)?))
+    }
+
+    fn is_yieldable(&self) -> bool {
+        #[cfg(feature = "lua53")]
+        unsafe {
+            ffi::lua_isyieldable(self.state) != 0
+        }
+        #[cfg(not(feature = "lua53"))]
+        {
+            self.is_in_coroutine()
+        }
+    }
+
     /// Returns a handle to the global environment.
     pub fn globals(&self) -> Table {
         unsafe {
@@ -531,40 +1718,654 @@ impl Lua {
         }
     }
 
-    /// Returns a handle to the active `Thread`.  For calls to `Lua` this will be the main Lua thread,
-    /// for parameters given to a callback, this will be whatever Lua thread called the callback.
-    pub fn current_thread<'lua>(&'lua self) -> Thread<'lua> {
-        unsafe {
-            ffi::lua_pushthread(self.state);
-            Thread(self.pop_ref())
-        }
+    /// Takes a snapshot of the global variables, to be restored with [`restore_globals`].
+    ///
+    /// The snapshot is shallow: it records which value each global variable holds, not the
+    /// contents of those values. This allows running several independent scripts (such as test
+    /// cases) in the same `Lua` instance without globals leaking from one to the next, but
+    /// modifications made inside tables (including library tables like `string`) are not undone.
+    ///
+    /// The globals table is accessed raw, without invoking any metamethods.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.load("x = 1").exec()?;
+    /// let snapshot = lua.snapshot_globals()?;
+    ///
+    /// lua.load("x = 2; y = 3; print = nil").exec()?;
+    /// lua.restore_globals(&snapshot)?;
+    ///
+    /// assert!(lua.load("x == 1 and y == nil and print ~= nil").eval::<bool>()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`restore_globals`]: #method.restore_globals
+    pub fn snapshot_globals(&self) -> Result<GlobalsSnapshot> {
+        let globals = self.globals();
+        let copy = self.create_table()?;
+        for pair in globals.pairs::<Value, Value>() {
+            let (k, v) = pair?;
+            copy.raw_set(k, v)?;
+        }
+        Ok(GlobalsSnapshot(self.create_registry_value(copy)?))
+    }
+
+    /// Restores the global variables recorded by [`snapshot_globals`].
+    ///
+    /// Globals defined since the snapshot are removed, and all others are reset to the value they
+    /// held when the snapshot was taken. The snapshot can be restored any number of times.
+    ///
+    /// Returns `Error::MismatchedRegistryKey` if the snapshot was taken from an unrelated `Lua`
+    /// state.
+    ///
+    /// [`snapshot_globals`]: #method.snapshot_globals
+    pub fn restore_globals(&self, snapshot: &GlobalsSnapshot) -> Result<()> {
+        let saved: Table = self.registry_value(&snapshot.0)?;
+        let globals = self.globals();
+
+        let mut added = Vec::new();
+        for pair in globals.clone().pairs::<Value, Value>() {
+            let (k, _) = pair?;
+            if let Nil = saved.raw_get::<_, Value>(k.clone())? {
+                added.push(k);
+            }
+        }
+        for k in added {
+            globals.raw_set(k, Nil)?;
+        }
+        for pair in saved.pairs::<Value, Value>() {
+            let (k, v) = pair?;
+            globals.raw_set(k, v)?;
+        }
+        Ok(())
+    }
+
+    /// Registers `table` as a module, so that `require(name)` in Lua returns it.
+    ///
+    /// The table is stored in `package.loaded[name]`, replacing any module previously loaded
+    /// under that name. Returns an error if the `package` library is not loaded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let module = lua.create_table()?;
+    /// module.set("answer", 42)?;
+    /// lua.register_module("mymod", module)?;
+    ///
+    /// let answer: i32 = lua.load("require('mymod').answer").eval()?;
+    /// assert_eq!(answer, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn register_module<'lua, S: AsRef<str>>(
+        &'lua self,
+        name: S,
+        table: Table<'lua>,
+    ) -> Result<()> {
+        let name = name.as_ref();
+        let loaded = match self.globals().raw_get::<_, Value>("package")? {
+            Value::Table(package) => package.raw_get::<_, Table>("loaded")?,
+            _ => {
+                return Err(Error::RuntimeError(format!(
+                    "cannot register module '{}': the package library is not loaded",
+                    name
+                )))
+            }
+        };
+        loaded.raw_set(name, table)
+    }
+
+    /// Returns a handle to the active `Thread`.  For calls to `Lua` this will be the main Lua thread,
+    /// for parameters given to a callback, this will be whatever Lua thread called the callback.
+    pub fn current_thread<'lua>(&'lua self) -> Thread<'lua> {
+        unsafe {
+            ffi::lua_pushthread(self.state);
+            Thread(self.pop_ref())
+        }
+    }
+
+    /// Returns the status of the active thread.
+    ///
+    /// The active thread is the one [`current_thread`] returns. As it is running, this normally
+    /// returns [`ThreadStatus::Unresumable`], matching what [`Thread::status`] reports for a
+    /// running thread. Unlike `current_thread`, this does not touch the stack.
+    ///
+    /// [`current_thread`]: #method.current_thread
+    /// [`ThreadStatus::Unresumable`]: enum.ThreadStatus.html#variant.Unresumable
+    /// [`Thread::status`]: struct.Thread.html#method.status
+    pub fn current_thread_status(&self) -> ThreadStatus {
+        match unsafe { ffi::lua_status(self.state) } {
+            ffi::LUA_OK => ThreadStatus::Unresumable,
+            ffi::LUA_YIELD => ThreadStatus::Resumable,
+            _ => ThreadStatus::Error,
+        }
+    }
+
+    /// Returns `true` if the active thread is a coroutine rather than the main Lua thread.
+    ///
+    /// This is the case for a callback called from a coroutine. Note that [`yield_with`] may still
+    /// fail inside a coroutine if there is a metamethod or C-call boundary in between.
+    ///
+    /// [`yield_with`]: #method.yield_with
+    pub fn is_in_coroutine(&self) -> bool {
+        self.state != self.main_state
+    }
+
+    /// Returns the name under which the currently running callback was called.
+    ///
+    /// This is the name Lua's debug library reports for the innermost running function (see
+    /// `debug.getinfo(1, "n")`), such as the global variable, table field or method name used to
+    /// reach it. It lets a single Rust function registered under several names branch on the name
+    /// it was invoked as.
+    ///
+    /// Returns `None` when the name is unavailable, for example when the function was called by
+    /// Rust code, passed to another function as an argument, or (depending on the Lua version)
+    /// through a tail call. Also returns `None` when not called from within a callback.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let dispatch = lua.create_function(|lua, ()| Ok(lua.callback_name()))?;
+    /// let api = lua.create_table()?;
+    /// api.set("start", dispatch.clone())?;
+    /// api.set("stop", dispatch)?;
+    /// lua.globals().set("api", api)?;
+    ///
+    /// assert_eq!(lua.load("local r = api.start() return r").eval::<String>()?, "start");
+    /// assert_eq!(lua.load("local r = api:stop() return r").eval::<String>()?, "stop");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn callback_name(&self) -> Option<StdString> {
+        unsafe {
+            let mut ar: ffi::lua_Debug = mem::zeroed();
+            if ffi::lua_getstack(self.state, 0, &mut ar) == 0
+                || ffi::lua_getinfo(self.state, cstr!("n"), &mut ar) == 0
+                || ar.name.is_null()
+            {
+                return None;
+            }
+            Some(CStr::from_ptr(ar.name).to_string_lossy().into_owned())
+        }
+    }
+
+    /// Calls `f` with a `Lua` handle which operates on the main Lua thread.
+    ///
+    /// The `Lua` passed to a callback operates on the thread (coroutine) which called it. Most
+    /// operations behave the same on both handles: values, the registry and [`RegistryKey`]s are
+    /// shared by all threads of a Lua state, so they can be used interchangeably. Operations
+    /// which depend on the running thread do differ, such as [`current_thread`], [`stack_top`]
+    /// and [`with_stack`]. When called on the main handle, `f` receives an equivalent handle.
+    ///
+    /// The main handle is only borrowed for the duration of `f`, so neither it nor any value
+    /// created through it can outlive the call.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Thread};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let in_main = lua.create_function(|lua, ()| {
+    ///     Ok(lua.with_main_state(|main| main.current_thread() == lua.current_thread()))
+    /// })?;
+    /// lua.globals().set("in_main", in_main)?;
+    ///
+    /// let co: Thread = lua.load("coroutine.create(function() return in_main() end)").eval()?;
+    /// assert_eq!(co.resume::<_, bool>(())?, false);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`RegistryKey`]: struct.RegistryKey.html
+    /// [`current_thread`]: #method.current_thread
+    /// [`stack_top`]: #method.stack_top
+    /// [`with_stack`]: #method.with_stack
+    pub fn with_main_state<R, F: FnOnce(&Lua) -> R>(&self, f: F) -> R {
+        let main = Lua {
+            state: self.main_state,
+            main_state: self.main_state,
+            extra: self.extra.clone(),
+            ephemeral: true,
+            _no_ref_unwind_safe: PhantomData,
+        };
+        f(&main)
+    }
+
+    /// Returns a handle which can be used to interrupt Lua code running in this state, possibly
+    /// from another thread.
+    ///
+    /// The first call installs an instruction count hook which checks the interrupt flag every
+    /// few thousand Lua VM instructions. After [`InterruptHandle::interrupt`] is called, the
+    /// running Lua code raises a runtime error with the message "interrupted" at the next check,
+    /// and the flag is cleared so that subsequent calls run normally. An interrupt requested while
+    /// no Lua code is running stays pending until the next Lua code runs.
+    ///
+    /// Once every handle has been dropped, the count hook removes itself the next time it runs.
+    ///
+    /// Coroutines created before the first call to this method get the hook the next time they
+    /// are resumed with [`Thread::resume`]. Threads with an instruction limit set by
+    /// [`Thread::set_instruction_limit`] check the flag as often as any other thread.
+    /// The error can be caught by `pcall` inside Lua code like any other error.
+    ///
+    /// Not available with LuaJIT, which does not run debug hooks inside JIT-compiled code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # use std::{thread, time::Duration};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let handle = lua.interrupt_handle();
+    /// thread::spawn(move || {
+    ///     thread::sleep(Duration::from_millis(10));
+    ///     handle.interrupt();
+    /// });
+    ///
+    /// assert!(lua.load("while true do end").exec().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`InterruptHandle::interrupt`]: struct.InterruptHandle.html#method.interrupt
+    /// [`Thread::resume`]: struct.Thread.html#method.resume
+    /// [`Thread::set_instruction_limit`]: struct.Thread.html#method.set_instruction_limit
+    #[cfg(not(feature = "luajit"))]
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 1);
+
+            ffi::lua_pushlightuserdata(
+                self.state,
+                &INTERRUPT_FLAG_REGISTRY_KEY as *const u8 as *mut c_void,
+            );
+            ffi::lua_rawget(self.state, ffi::LUA_REGISTRYINDEX);
+            let flag = (*get_userdata::<Arc<AtomicBool>>(self.state, -1)).clone();
+
+            for &state in &[self.main_state, self.state] {
+                let mask = ffi::lua_gethookmask(state);
+                if mask & ffi::LUA_MASKCOUNT == 0 {
+                    ffi::lua_sethook(
+                        state,
+                        debug_hook,
+                        mask | ffi::LUA_MASKCOUNT,
+                        INTERRUPT_HOOK_INSTRUCTIONS,
+                    );
+                }
+            }
+
+            InterruptHandle(flag)
+        }
+    }
+
+    /// Starts recording which lines of Lua code are executed.
+    ///
+    /// This installs a line hook which counts how many times each line of every chunk runs, until
+    /// [`stop_coverage`] is called. Lines are recorded for the main thread and for coroutines
+    /// created while coverage is running, including those with an instruction limit. Coroutines
+    /// created before the call to this method are not tracked. Calling it again while coverage is
+    /// already running keeps the counts recorded so far.
+    ///
+    /// Not available with LuaJIT, which does not run debug hooks inside JIT-compiled code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.start_coverage()?;
+    /// lua.load("local x = 0\nfor i = 1, 3 do\n  x = x + i\nend")
+    ///     .set_name("=example")?
+    ///     .exec()?;
+    /// let report = lua.stop_coverage();
+    ///
+    /// assert_eq!(report.hits("=example", 1), 1);
+    /// assert!(report.hits("=example", 3) >= 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`stop_coverage`]: #method.stop_coverage
+    #[cfg(not(feature = "luajit"))]
+    pub fn start_coverage(&self) -> Result<()> {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 3);
+
+            ffi::lua_pushlightuserdata(
+                self.state,
+                &COVERAGE_REGISTRY_KEY as *const u8 as *mut c_void,
+            );
+            ffi::lua_rawget(self.state, ffi::LUA_REGISTRYINDEX);
+            let running = ffi::lua_isnil(self.state, -1) == 0;
+            ffi::lua_pop(self.state, 1);
+
+            if !running {
+                protect_lua_closure(self.state, 0, 0, |state| {
+                    ffi::lua_pushlightuserdata(
+                        state,
+                        &COVERAGE_REGISTRY_KEY as *const u8 as *mut c_void,
+                    );
+
+                    let hits = ffi::lua_newuserdata(state, mem::size_of::<CoverageHits>())
+                        as *mut CoverageHits;
+                    ptr::write(hits, HashMap::new());
+
+                    ffi::lua_newtable(state);
+                    ffi::lua_pushstring(state, cstr!("__gc"));
+                    ffi::lua_pushcfunction(state, userdata_destructor::<CoverageHits>);
+                    ffi::lua_rawset(state, -3);
+                    ffi::lua_setmetatable(state, -2);
+
+                    ffi::lua_rawset(state, ffi::LUA_REGISTRYINDEX);
+                })?;
+            }
+
+            for &state in &[self.main_state, self.state] {
+                let mask = ffi::lua_gethookmask(state);
+                let count = ffi::lua_gethookcount(state);
+                let hook = match ffi::lua_gethook(state) {
+                    Some(hook) if mask != 0 => hook,
+                    _ => debug_hook,
+                };
+                ffi::lua_sethook(state, hook, mask | ffi::LUA_MASKLINE, count);
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Stops recording executed lines and returns the lines recorded since [`start_coverage`].
+    ///
+    /// Returns an empty report if coverage is not running. Coroutines created while coverage was
+    /// running keep their line hook, but no longer record anything.
+    ///
+    /// Not available with LuaJIT.
+    ///
+    /// [`start_coverage`]: #method.start_coverage
+    #[cfg(not(feature = "luajit"))]
+    pub fn stop_coverage(&self) -> CoverageReport {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 2);
+
+            for &state in &[self.main_state, self.state] {
+                let mask = ffi::lua_gethookmask(state) & !ffi::LUA_MASKLINE;
+                if let Some(hook) = ffi::lua_gethook(state) {
+                    let count = ffi::lua_gethookcount(state);
+                    ffi::lua_sethook(state, hook, mask, if mask == 0 { 0 } else { count });
+                }
+            }
+
+            ffi::lua_pushlightuserdata(
+                self.state,
+                &COVERAGE_REGISTRY_KEY as *const u8 as *mut c_void,
+            );
+            ffi::lua_rawget(self.state, ffi::LUA_REGISTRYINDEX);
+            if ffi::lua_isnil(self.state, -1) != 0 {
+                return CoverageReport::default();
+            }
+            let hits = mem::replace(
+                &mut *get_userdata::<CoverageHits>(self.state, -1),
+                HashMap::new(),
+            );
+            ffi::lua_pop(self.state, 1);
+
+            ffi::lua_pushlightuserdata(
+                self.state,
+                &COVERAGE_REGISTRY_KEY as *const u8 as *mut c_void,
+            );
+            ffi::lua_pushnil(self.state);
+            ffi::lua_rawset(self.state, ffi::LUA_REGISTRYINDEX);
+
+            CoverageReport {
+                sources: hits
+                    .into_iter()
+                    .map(|(source, lines)| {
+                        (
+                            StdString::from_utf8_lossy(&source).into_owned(),
+                            lines.into_iter().collect(),
+                        )
+                    })
+                    .collect(),
+            }
+        }
+    }
+
+    /// Calls the given function with a `Scope` parameter, giving the function the ability to create
+    /// userdata and callbacks from rust types that are !Send or non-'static.
+    ///
+    /// The lifetime of any function or userdata created through `Scope` lasts only until the
+    /// completion of this method call, on completion all such created values are automatically
+    /// dropped and Lua references to them are invalidated.  If a script accesses a value created
+    /// through `Scope` outside of this method, a Lua error will result.  Since we can ensure the
+    /// lifetime of values created through `Scope`, and we know that `Lua` cannot be sent to another
+    /// thread while `Scope` is live, it is safe to allow !Send datatypes and whose lifetimes only
+    /// outlive the scope lifetime.
+    ///
+    /// Inside the scope callback, all handles created through Scope will share the same unique 'lua
+    /// lifetime of the parent `Lua`.  This allows scoped and non-scoped values to be mixed in
+    /// API calls, which is very useful (e.g. passing a scoped userdata to a non-scoped function).
+    /// However, this also enables handles to scoped values to be trivially leaked from the given
+    /// callback. This is not dangerous, though!  After the callback returns, all scoped values are
+    /// invalidated, which means that though references may exist, the Rust types backing them have
+    /// dropped.  `Function` types will error when called, and `AnyUserData` will be typeless.  It
+    /// would be impossible to prevent handles to scoped values from escaping anyway, since you
+    /// would always be able to smuggle them through Lua state.
+    ///
+    /// Whatever the callback returns is returned from `scope`, so a script can be run against
+    /// scoped values and its result handed back out. The returned value cannot borrow from the
+    /// `Scope` itself.
+    pub fn scope<'scope, 'lua: 'scope, F, R>(&'lua self, f: F) -> R
+    where
+        F: FnOnce(&Scope<'lua, 'scope>) -> R,
+    {
+        f(&Scope::new(self))
+    }
+
+    /// Runs a Rust closure as a protected Lua call, capturing a traceback for any error it returns.
+    ///
+    /// The closure is called from Lua through a temporary callback, so an `Err` returned by it is
+    /// reported as an [`Error::CallbackError`] carrying the Lua traceback at the point of failure
+    /// and the original error as its cause. This gives a single boundary at which multi-step host
+    /// logic touching Lua is isolated, instead of handling each call separately.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #![allow(unused)]
+    /// # use mlua::{Error, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// # #[cfg(not(feature = "luajit"))]
+    /// # {
+    /// let res = lua.try_catch(|| {
+    ///     lua.load("x = 1").exec()?;
+    ///     lua.load("error('failed')").exec()?;
+    ///     lua.load("x = 2").exec()
+    /// });
+    ///
+    /// match res {
+    ///     Err(Error::CallbackError { .. }) => {}
+    ///     r => panic!("unexpected result: {:?}", r),
+    /// }
+    /// assert_eq!(lua.globals().get::<_, i64>("x")?, 1);
+    /// # }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Error::CallbackError`]: enum.Error.html#variant.CallbackError
+    pub fn try_catch<R, F>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce() -> Result<R>,
+    {
+        let mut f = Some(f);
+        let mut result = None;
+        self.scope(|scope| {
+            let func = scope.create_function_mut(|_, ()| {
+                let f = mlua_expect!(f.take(), "try_catch closure called twice");
+                result = Some(f()?);
+                Ok(())
+            })?;
+            func.call::<_, ()>(())
+        })?;
+        Ok(mlua_expect!(result, "try_catch closure did not run"))
+    }
+
+    /// Converts a value to a string exactly like the Lua `tostring` function does.
+    ///
+    /// Numbers are formatted the way Lua formats them (for example `1.0` with Lua 5.3 or `1e+15`),
+    /// and tables and userdata with a `__tostring` metamethod are converted by calling it. Errors
+    /// raised by `__tostring` are returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// assert_eq!(lua.tostring(0.1)?, "0.1");
+    /// assert_eq!(lua.tostring(true)?, "true");
+    ///
+    /// let point = lua.load("setmetatable({}, { __tostring = function() return '(1, 2)' end })");
+    /// assert_eq!(lua.tostring(point.eval::<mlua::Table>()?)?, "(1, 2)");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn tostring<'lua, V: ToLua<'lua>>(&'lua self, v: V) -> Result<String<'lua>> {
+        let v = v.to_lua(self)?;
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 4);
+
+            self.push_value(v)?;
+            protect_lua_closure(self.state, 1, 1, |state| {
+                ffi::luaL_tolstring(state, -1, ptr::null_mut());
+            })?;
+            Ok(String(self.pop_ref()))
+        }
+    }
+
+    /// Converts a value to a string like [`tostring`] does, returning its raw bytes.
+    ///
+    /// Lua strings are not necessarily valid UTF-8, so this is the variant to use when the
+    /// result may contain arbitrary bytes (as produced by a `__tostring` metamethod, for
+    /// instance). No UTF-8 validation is performed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let packet: mlua::Table = lua
+    ///     .load(r#"setmetatable({}, { __tostring = function() return "\255\0" end })"#)
+    ///     .eval()?;
+    /// assert_eq!(lua.tostring_bytes(packet)?, b"\xff\x00");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`tostring`]: #method.tostring
+    pub fn tostring_bytes<'lua, V: ToLua<'lua>>(&'lua self, v: V) -> Result<Vec<u8>> {
+        Ok(self.tostring(v)?.as_bytes().to_vec())
+    }
+
+    /// Compares two values the way Lua's `==` operator does.
+    ///
+    /// Values of different types are never equal, primitive values are compared by value, and
+    /// tables and userdata fall back to their `__eq` metamethod when they are not the same object.
+    /// The metamethod runs in protected mode, so errors it raises are returned rather than
+    /// propagated as panics. Unlike [`Value`]'s `PartialEq`, this may call into Lua.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let (a, b): (Value, Value) = lua.load(r#"
+    ///     local mt = { __eq = function(a, b) return a.id == b.id end }
+    ///     return setmetatable({ id = 1 }, mt), setmetatable({ id = 1 }, mt)
+    /// "#).eval()?;
+    ///
+    /// assert_ne!(a, b);
+    /// assert!(lua.values_equal(&a, &b)?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Value`]: enum.Value.html
+    pub fn values_equal<'lua>(&'lua self, a: &Value<'lua>, b: &Value<'lua>) -> Result<bool> {
+        self.compare_values(a, b, ffi::LUA_OPEQ)
+    }
+
+    /// Checks whether `a` is less than `b`, the way Lua's `<` operator does.
+    ///
+    /// Numbers are compared numerically and strings according to the current locale (using
+    /// `strcoll`). Other values use the `__lt` metamethod, and an error is returned if there is
+    /// none, just as a script would raise one for incomparable operands.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let one = Value::Integer(1);
+    /// let two = Value::Number(2.0);
+    /// assert!(lua.values_less_than(&one, &two)?);
+    /// # #[cfg(not(feature = "luajit"))]
+    /// assert!(lua.values_less_than(&one, &Value::Boolean(true)).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn values_less_than<'lua>(&'lua self, a: &Value<'lua>, b: &Value<'lua>) -> Result<bool> {
+        self.compare_values(a, b, ffi::LUA_OPLT)
     }
 
-    /// Calls the given function with a `Scope` parameter, giving the function the ability to create
-    /// userdata and callbacks from rust types that are !Send or non-'static.
+    /// Checks whether `a` is less than or equal to `b`, the way Lua's `<=` operator does.
     ///
-    /// The lifetime of any function or userdata created through `Scope` lasts only until the
-    /// completion of this method call, on completion all such created values are automatically
-    /// dropped and Lua references to them are invalidated.  If a script accesses a value created
-    /// through `Scope` outside of this method, a Lua error will result.  Since we can ensure the
-    /// lifetime of values created through `Scope`, and we know that `Lua` cannot be sent to another
-    /// thread while `Scope` is live, it is safe to allow !Send datatypes and whose lifetimes only
-    /// outlive the scope lifetime.
+    /// This works like [`values_less_than`], consulting the `__le` metamethod for values other
+    /// than numbers and strings. If `__le` is not defined, Lua falls back to `not (b < a)` using
+    /// `__lt`.
     ///
-    /// Inside the scope callback, all handles created through Scope will share the same unique 'lua
-    /// lifetime of the parent `Lua`.  This allows scoped and non-scoped values to be mixed in
-    /// API calls, which is very useful (e.g. passing a scoped userdata to a non-scoped function).
-    /// However, this also enables handles to scoped values to be trivially leaked from the given
-    /// callback. This is not dangerous, though!  After the callback returns, all scoped values are
-    /// invalidated, which means that though references may exist, the Rust types backing them have
-    /// dropped.  `Function` types will error when called, and `AnyUserData` will be typeless.  It
-    /// would be impossible to prevent handles to scoped values from escaping anyway, since you
-    /// would always be able to smuggle them through Lua state.
-    pub fn scope<'scope, 'lua: 'scope, F, R>(&'lua self, f: F) -> R
-    where
-        F: FnOnce(&Scope<'lua, 'scope>) -> R,
-    {
-        f(&Scope::new(self))
+    /// [`values_less_than`]: #method.values_less_than
+    pub fn values_less_equal<'lua>(&'lua self, a: &Value<'lua>, b: &Value<'lua>) -> Result<bool> {
+        self.compare_values(a, b, ffi::LUA_OPLE)
+    }
+
+    fn compare_values<'lua>(
+        &'lua self,
+        a: &Value<'lua>,
+        b: &Value<'lua>,
+        op: c_int,
+    ) -> Result<bool> {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 5);
+
+            self.push_value(a.clone())?;
+            self.push_value(b.clone())?;
+            protect_lua_closure(self.state, 2, 0, |state| {
+                ffi::lua_compare(state, -2, -1, op) != 0
+            })
+        }
     }
 
     /// Attempts to coerce a Lua value into a String in a manner consistent with Lua's internal
@@ -651,11 +2452,74 @@ impl Lua {
     }
 
     /// Converts a value that implements `ToLuaMulti` into a `MultiValue` instance.
+    ///
+    /// This is the inverse of [`unpack_multi`]. Tuples become one value per element.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let values = lua.pack_multi(("key", 42))?;
+    /// assert_eq!(values.len(), 2);
+    ///
+    /// let mut iter = values.into_iter();
+    /// assert_eq!(lua.unpack::<String>(iter.next().unwrap())?, "key");
+    /// assert_eq!(lua.unpack::<i64>(iter.next().unwrap())?, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`unpack_multi`]: #method.unpack_multi
     pub fn pack_multi<'lua, T: ToLuaMulti<'lua>>(&'lua self, t: T) -> Result<MultiValue<'lua>> {
         t.to_lua_multi(self)
     }
 
     /// Converts a `MultiValue` instance into a value that implements `FromLuaMulti`.
+    ///
+    /// This is the inverse of [`pack_multi`], and follows the same rules Lua uses for function
+    /// arguments: when unpacking into a tuple, missing values are treated as `nil` and extra values
+    /// are discarded. An error is returned only if a value (or a missing one) cannot be converted
+    /// to the corresponding tuple element.
+    ///
+    /// # Examples
+    ///
+    /// Converting a `(key, value)` pair, such as one produced by iterating a table:
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let pair = lua.pack_multi(("answer", 42))?;
+    /// let (k, v): (String, i64) = lua.unpack_multi(pair)?;
+    /// assert_eq!(k, "answer");
+    /// assert_eq!(v, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Arity mismatches:
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// // Extra values are dropped
+    /// let (a,): (i64,) = lua.unpack_multi(lua.pack_multi((1, 2, 3))?)?;
+    /// assert_eq!(a, 1);
+    ///
+    /// // Missing values are `nil`, which is fine for an `Option`...
+    /// let (a, b): (i64, Option<i64>) = lua.unpack_multi(lua.pack_multi(1)?)?;
+    /// assert_eq!((a, b), (1, None));
+    ///
+    /// // ...but is a conversion error otherwise
+    /// assert!(lua.unpack_multi::<(i64, i64)>(lua.pack_multi(1)?).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`pack_multi`]: #method.pack_multi
     pub fn unpack_multi<'lua, T: FromLuaMulti<'lua>>(
         &'lua self,
         value: MultiValue<'lua>,
@@ -663,6 +2527,40 @@ impl Lua {
         T::from_lua_multi(value, self)
     }
 
+    /// Deserializes a Lua `Value` into any type implementing serde's `DeserializeOwned`.
+    ///
+    /// Lua tables are deserialized as sequences when they have a non-empty array part, and as maps
+    /// otherwise. Enums are externally tagged: a unit variant is read from a string, and other
+    /// variants from a table with a single key naming the variant. Unknown fields are ignored,
+    /// unless the type asks otherwise with `#[serde(deny_unknown_fields)]`.
+    ///
+    /// Requires `feature = "serialize"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Value};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize, Debug, PartialEq)]
+    /// enum Mode {
+    ///     Fast,
+    ///     Limited(u32),
+    /// }
+    ///
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let value: Value = lua.load(r#"{ "Fast", { Limited = 10 } }"#).eval()?;
+    /// let modes: Vec<Mode> = lua.from_value(value)?;
+    /// assert_eq!(modes, vec![Mode::Fast, Mode::Limited(10)]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "serialize")]
+    pub fn from_value<'lua, T: DeserializeOwned>(&'lua self, value: Value<'lua>) -> Result<T> {
+        T::deserialize(crate::serde::Deserializer::new(value))
+    }
+
     /// Set a value in the Lua registry based on a string name.
     ///
     /// This value will be available to rust from all `Lua` instances which share the same main
@@ -779,6 +2677,31 @@ impl Lua {
         T::from_lua(value, self)
     }
 
+    /// Returns true if the registry slot of the given `RegistryKey` currently holds a non-nil
+    /// value.
+    ///
+    /// This is mostly useful for tracking down leaks: a key that is still set keeps its value
+    /// alive, along with anything reachable from it. Checking a key does not create any new
+    /// reference to the value. Returns false for keys created by an unrelated `Lua` state and for
+    /// keys created from `nil`.
+    pub fn registry_value_is_set(&self, key: &RegistryKey) -> bool {
+        if !self.owns_registry_value(key) {
+            return false;
+        }
+
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 1);
+
+            ffi::lua_rawgeti(
+                self.state,
+                ffi::LUA_REGISTRYINDEX,
+                key.registry_id as ffi::lua_Integer,
+            );
+            ffi::lua_isnil(self.state, -1) == 0
+        }
+    }
+
     /// Removes a value from the Lua registry.
     ///
     /// You may call this function to manually remove a value placed in the registry with
@@ -809,6 +2732,50 @@ impl Lua {
         Arc::ptr_eq(&key.unref_list, &self.extra.borrow().registry_unref_list)
     }
 
+    /// Creates an error which raises the given value, rather than a message, as a Lua error.
+    ///
+    /// When a Rust callback returns the resulting [`Error::RuntimeValue`], Lua code calling it
+    /// with `pcall` receives the original value (a table, userdata, etc.). If the callback was
+    /// called from Rust instead, the value is converted to a string and reported as a
+    /// `RuntimeError`.
+    ///
+    /// The value is kept in the registry until the error is raised, or until the error and all of
+    /// its clones are dropped and [`expire_registry_values`] is called.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let fail = lua.create_function(|lua, ()| -> Result<()> {
+    ///     let err = lua.create_table()?;
+    ///     err.set("code", 404)?;
+    ///     err.set("message", "not found")?;
+    ///     Err(lua.create_error_value(err)?)
+    /// })?;
+    /// lua.globals().set("fail", fail)?;
+    ///
+    /// # #[cfg(not(feature = "luajit"))]
+    /// lua.load(r#"
+    ///     local ok, err = pcall(fail)
+    ///     assert(not ok and err.code == 404 and err.message == "not found")
+    /// "#).exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Error::RuntimeValue`]: enum.Error.html#variant.RuntimeValue
+    /// [`expire_registry_values`]: #method.expire_registry_values
+    pub fn create_error_value<'lua, T: ToLua<'lua>>(&'lua self, t: T) -> Result<Error> {
+        let value = t.to_lua(self)?;
+        let type_name = value.type_name();
+        Ok(Error::RuntimeValue {
+            key: Arc::new(self.create_registry_value(value)?),
+            type_name,
+        })
+    }
+
     /// Remove any registry values whose `RegistryKey`s have all been dropped.
     ///
     /// Unlike normal handle values, `RegistryKey`s do not automatically remove themselves on Drop,
@@ -853,23 +2820,23 @@ impl Lua {
             }
 
             Value::String(s) => {
-                self.push_ref(&s.0);
+                self.push_owned_ref(&s.0)?;
             }
 
             Value::Table(t) => {
-                self.push_ref(&t.0);
+                self.push_owned_ref(&t.0)?;
             }
 
             Value::Function(f) => {
-                self.push_ref(&f.0);
+                self.push_owned_ref(&f.0)?;
             }
 
             Value::Thread(t) => {
-                self.push_ref(&t.0);
+                self.push_owned_ref(&t.0)?;
             }
 
             Value::UserData(ud) => {
-                self.push_ref(&ud.0);
+                self.push_owned_ref(&ud.0)?;
             }
 
             Value::Error(e) => {
@@ -947,6 +2914,21 @@ impl Lua {
         ffi::lua_xmove(extra.ref_thread, self.state, 1);
     }
 
+    // Like `push_ref`, but returns `Error::MismatchedLua` if the reference belongs to a different
+    // main Lua state instead of panicking.
+    unsafe fn push_owned_ref<'lua>(&'lua self, lref: &LuaRef<'lua>) -> Result<()> {
+        if !lref.lua.shares_state(self) {
+            return Err(Error::MismatchedLua);
+        }
+        self.push_ref(lref);
+        Ok(())
+    }
+
+    // Returns true if both `Lua` instances share the same main state.
+    pub(crate) fn shares_state(&self, other: &Lua) -> bool {
+        self.main_state == other.main_state
+    }
+
     // Pops the topmost element of the stack and stores a reference to it.  This pins the object,
     // preventing garbage collection until the returned `LuaRef` is dropped.
     //
@@ -1027,18 +3009,64 @@ impl Lua {
             ffi::lua_pop(self.state, 1);
         }
 
+        let mt_ptr = ffi::lua_topointer(self.state, -1);
         let id = protect_lua_closure(self.state, 1, 0, |state| {
             ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
         })?;
 
-        self.extra
-            .borrow_mut()
-            .registered_userdata
-            .insert(TypeId::of::<T>(), id);
+        let mut extra = self.extra.borrow_mut();
+        extra.registered_userdata.insert(TypeId::of::<T>(), id);
+        extra
+            .registered_userdata_mt
+            .insert(mt_ptr, TypeId::of::<T>());
 
         Ok(id)
     }
 
+    // Returns the type of the userdata at `idx`, if it was created from a registered type.
+    pub(crate) unsafe fn get_userdata_type_id(&self, idx: c_int) -> Option<TypeId> {
+        let _sg = StackGuard::new(self.state);
+        assert_stack(self.state, 1);
+
+        if ffi::lua_getmetatable(self.state, idx) == 0 {
+            return None;
+        }
+        let mt_ptr = ffi::lua_topointer(self.state, -1);
+        self.extra
+            .borrow()
+            .registered_userdata_mt
+            .get(&mt_ptr)
+            .copied()
+    }
+
+    // Returns the function compiled from the Lua chunk `source`, which is only compiled the first
+    // time and then kept in the registry under `key`. Used for internal helpers written in Lua.
+    pub(crate) fn cached_chunk<'lua>(
+        &'lua self,
+        key: &'static u8,
+        name: &str,
+        source: &str,
+    ) -> Result<Function<'lua>> {
+        unsafe {
+            let _sg = StackGuard::new(self.state);
+            assert_stack(self.state, 2);
+
+            ffi::lua_pushlightuserdata(self.state, key as *const u8 as *mut c_void);
+            ffi::lua_rawget(self.state, ffi::LUA_REGISTRYINDEX);
+            if ffi::lua_isnil(self.state, -1) == 0 {
+                return Ok(Function(self.pop_ref()));
+            }
+
+            let func = self.load(source).set_name(name)?.into_function()?;
+            ffi::lua_pushlightuserdata(self.state, key as *const u8 as *mut c_void);
+            self.push_ref(&func.0);
+            protect_lua_closure(self.state, 2, 0, |state| {
+                ffi::lua_rawset(state, ffi::LUA_REGISTRYINDEX)
+            })?;
+            Ok(func)
+        }
+    }
+
     // Creates a Function out of a Callback containing a 'static Fn.  This is safe ONLY because the
     // Fn is 'static, otherwise it could capture 'callback arguments improperly.  Without ATCs, we
     // cannot easily deal with the "correct" callback type of:
@@ -1047,12 +3075,16 @@ impl Lua {
     //
     // So we instead use a caller provided lifetime, which without the 'static requirement would be
     // unsafe.
+    //
+    // Closures that capture nothing are zero-sized, so boxing them into a `Callback` does not
+    // allocate, and the `ExtraData` upvalue is shared between all callbacks.
     pub(crate) fn create_callback<'lua, 'callback>(
         &'lua self,
         func: Callback<'callback, 'static>,
     ) -> Result<Function<'lua>> {
         unsafe extern "C" fn call_callback(state: *mut ffi::lua_State) -> c_int {
-            callback_error(state, |nargs| {
+            let mut yielding = false;
+            let nresults = callback_error(state, |nargs| {
                 if ffi::lua_type(state, ffi::lua_upvalueindex(1)) == ffi::LUA_TNIL {
                     return Err(Error::CallbackDestructed);
                 }
@@ -1075,6 +3107,8 @@ impl Lua {
                     _no_ref_unwind_safe: PhantomData,
                 };
 
+                let _depth = CallbackDepthGuard::enter(&lua.extra)?;
+
                 let mut args = MultiValue::new();
                 args.reserve(nargs as usize);
                 for _ in 0..nargs {
@@ -1083,7 +3117,14 @@ impl Lua {
 
                 let func = get_userdata::<Callback>(state, ffi::lua_upvalueindex(1));
 
-                let results = (*func)(&lua, args)?;
+                let mut results = match (*func)(&lua, args) {
+                    Err(Error::RuntimeValue { ref key, .. }) if !lua.owns_registry_value(key) => {
+                        return Err(Error::MismatchedRegistryKey);
+                    }
+                    results => results?,
+                };
+                yielding = take_yield_marker(&mut results);
+
                 let nresults = results.len() as c_int;
 
                 check_stack(state, nresults)?;
@@ -1092,7 +3133,14 @@ impl Lua {
                 }
 
                 Ok(nresults)
-            })
+            });
+
+            // Nothing that implements Drop is left on the Rust stack at this point, so it is safe
+            // for `lua_yield` to unwind through this frame.
+            if yielding {
+                return ffi::lua_yield(state, nresults);
+            }
+            nresults
         }
 
         unsafe {
@@ -1107,13 +3155,11 @@ impl Lua {
             ffi::lua_rawget(self.state, ffi::LUA_REGISTRYINDEX);
             ffi::lua_setmetatable(self.state, -2);
 
-            push_userdata::<Arc<RefCell<ExtraData>>>(self.state, self.extra.clone())?;
-            ffi::lua_pushlightuserdata(
+            ffi::lua_rawgeti(
                 self.state,
-                &FUNCTION_EXTRA_METATABLE_REGISTRY_KEY as *const u8 as *mut c_void,
+                ffi::LUA_REGISTRYINDEX,
+                self.extra.borrow().callback_extra as ffi::lua_Integer,
             );
-            ffi::lua_rawget(self.state, ffi::LUA_REGISTRYINDEX);
-            ffi::lua_setmetatable(self.state, -2);
 
             protect_lua_closure(self.state, 2, 1, |state| {
                 ffi::lua_pushcclosure(state, call_callback, 2);
@@ -1145,6 +3191,176 @@ impl Lua {
     }
 }
 
+/// A handle to interrupt Lua code running in a [`Lua`] state, returned by
+/// [`Lua::interrupt_handle`].
+///
+/// The handle is `Send` and `Sync`, and stays valid for as long as it exists, even after the `Lua`
+/// state it came from has been dropped (in which case interrupting does nothing).
+///
+/// [`Lua`]: struct.Lua.html
+/// [`Lua::interrupt_handle`]: struct.Lua.html#method.interrupt_handle
+#[cfg(not(feature = "luajit"))]
+#[derive(Clone, Debug)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+#[cfg(not(feature = "luajit"))]
+impl InterruptHandle {
+    /// Interrupts the Lua code currently running in the state.
+    ///
+    /// If no Lua code is running, the interrupt stays pending and the next Lua code to run is
+    /// interrupted instead.
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Lines of Lua code executed while coverage was running, returned by [`Lua::stop_coverage`].
+///
+/// Lines are grouped by the source of the chunk they belong to, which is the chunk name given to
+/// [`Chunk::set_name`], or the chunk's source code if it has no name.
+///
+/// [`Lua::stop_coverage`]: struct.Lua.html#method.stop_coverage
+/// [`Chunk::set_name`]: struct.Chunk.html#method.set_name
+#[cfg(not(feature = "luajit"))]
+#[derive(Clone, Debug, Default)]
+pub struct CoverageReport {
+    sources: HashMap<StdString, BTreeMap<u32, u64>>,
+}
+
+#[cfg(not(feature = "luajit"))]
+impl CoverageReport {
+    /// Returns an iterator over the sources which had at least one line executed.
+    pub fn sources(&self) -> impl Iterator<Item = &str> {
+        self.sources.keys().map(|s| s.as_str())
+    }
+
+    /// Returns the executed lines of `source` with the number of times each one ran, ordered by
+    /// line number.
+    pub fn lines(&self, source: &str) -> Option<&BTreeMap<u32, u64>> {
+        self.sources.get(source)
+    }
+
+    /// Returns the number of times `line` of `source` ran.
+    pub fn hits(&self, source: &str, line: u32) -> u64 {
+        self.lines(source)
+            .and_then(|lines| lines.get(&line))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// The global variables of a `Lua` state at some point, returned by [`Lua::snapshot_globals`].
+///
+/// [`Lua::snapshot_globals`]: struct.Lua.html#method.snapshot_globals
+#[derive(Debug)]
+pub struct GlobalsSnapshot(RegistryKey);
+
+/// A value kept on the Lua stack, returned by [`Lua::pin`].
+///
+/// The value is removed from the stack when the guard is dropped.
+///
+/// [`Lua::pin`]: struct.Lua.html#method.pin
+pub struct PinnedValue<'lua> {
+    lua: &'lua Lua,
+    index: c_int,
+}
+
+impl<'lua> PinnedValue<'lua> {
+    /// Returns the absolute stack index of the pinned value.
+    pub fn index(&self) -> c_int {
+        self.index
+    }
+}
+
+impl<'lua> Drop for PinnedValue<'lua> {
+    fn drop(&mut self) {
+        unsafe {
+            let top = ffi::lua_gettop(self.lua.state);
+            if top == self.index {
+                ffi::lua_pop(self.lua.state, 1);
+            } else if !thread::panicking() {
+                panic!(
+                    "pinned value at stack index {} dropped while the stack top is {}",
+                    self.index, top
+                );
+            }
+        }
+    }
+}
+
+/// Version of the Lua implementation that mlua was built with, returned by [`Lua::version`].
+///
+/// [`Lua::version`]: struct.Lua.html#method.version
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum LuaVersion {
+    /// Lua 5.1.
+    Lua51,
+    /// Lua 5.2.
+    Lua52,
+    /// Lua 5.3.
+    Lua53,
+    /// LuaJIT, which implements the Lua 5.1 language.
+    LuaJit,
+}
+
+/// Controls how Rust `f32` and `f64` values which are NaN or infinite are converted to Lua.
+///
+/// Set with [`Lua::set_float_policy`].
+///
+/// [`Lua::set_float_policy`]: struct.Lua.html#method.set_float_policy
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum FloatPolicy {
+    /// Non-finite values are passed to Lua unchanged. This is the default.
+    Passthrough,
+    /// Converting a non-finite value fails with [`Error::ToLuaConversionError`].
+    ///
+    /// [`Error::ToLuaConversionError`]: enum.Error.html#variant.ToLuaConversionError
+    ErrorOnNonFinite,
+    /// Non-finite values are converted to `nil`.
+    CoerceToNil,
+}
+
+/// Controls whether converting Lua values to Rust coerces between strings and numbers.
+///
+/// Set with [`Lua::set_coercion`].
+///
+/// [`Lua::set_coercion`]: struct.Lua.html#method.set_coercion
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Coercion {
+    /// Numbers convert to strings and numeric strings convert to numbers, like in Lua. This is
+    /// the default.
+    Lenient,
+    /// Strings only convert from Lua strings, and numbers only from Lua numbers.
+    Strict,
+}
+
+/// A memory allocator for a Lua state, passed to [`Lua::new_with_allocator`].
+///
+/// Lua does all of its allocations through these methods, which can be used to place its memory
+/// in a custom arena, or to account for it precisely.
+///
+/// # Safety
+///
+/// Like `malloc`, `alloc` and `realloc` must return memory which is suitably aligned for any type
+/// (16 bytes is enough on all common platforms), or a null pointer on failure. `realloc` must
+/// keep the contents of the block up to the smaller of both sizes, and must not fail when
+/// shrinking a block, since Lua assumes it never does. `free` and `realloc` are only given
+/// pointers previously returned by this allocator, together with the size they were allocated
+/// with. None of the methods may call into Lua or panic.
+///
+/// [`Lua::new_with_allocator`]: struct.Lua.html#method.new_with_allocator
+#[cfg(not(feature = "luajit"))]
+pub unsafe trait Allocator: 'static + Send {
+    /// Allocates a block of `size` bytes, which is never 0.
+    fn alloc(&mut self, size: usize) -> *mut u8;
+
+    /// Resizes the block at `ptr` from `old_size` to `new_size` bytes, which is never 0.
+    fn realloc(&mut self, ptr: *mut u8, old_size: usize, new_size: usize) -> *mut u8;
+
+    /// Frees the block at `ptr`, of `size` bytes.
+    fn free(&mut self, ptr: *mut u8, size: usize);
+}
+
 /// Returned from [`Lua::load`] and is used to finalize loading and executing Lua main chunks.
 ///
 /// [`Lua::load`]: struct.Lua.html#method.load
@@ -1199,6 +3415,19 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
     /// the value that it evaluates to.  Otherwise, the chunk is interpreted as a block as normal,
     /// and this is equivalent to calling `exec`.
     pub fn eval<R: FromLuaMulti<'lua>>(self) -> Result<R> {
+        self.eval_with(())
+    }
+
+    /// Evaluate the chunk as either an expression or block, passing it the given arguments.
+    ///
+    /// This works like [`eval`], but `args` are available to the chunk as varargs (`...`).
+    ///
+    /// [`eval`]: #method.eval
+    pub fn eval_with<A, R>(self, args: A) -> Result<R>
+    where
+        A: ToLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
         // First, try interpreting the lua as an expression by adding
         // "return", then as a statement.  This is the same thing the
         // actual lua repl does.
@@ -1208,9 +3437,9 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
             self.lua
                 .load_chunk(&expression_source, self.name.as_ref(), self.env.clone())
         {
-            function.call(())
+            function.call(args)
         } else {
-            self.call(())
+            self.call(args)
         }
     }
 
@@ -1223,7 +3452,8 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
 
     /// Load this chunk into a regular `Function`.
     ///
-    /// This simply compiles the chunk without actually executing it.
+    /// This simply compiles the chunk without actually executing it. The resulting `Function` can
+    /// be stored and called any number of times without parsing the source again.
     pub fn into_function(self) -> Result<Function<'lua>> {
         self.lua
             .load_chunk(self.source, self.name.as_ref(), self.env)
@@ -1343,8 +3573,130 @@ unsafe fn ref_stack_pop(extra: &mut ExtraData) -> c_int {
     }
 }
 
+// Matches the limit on nested C calls of Lua itself.
+const DEFAULT_MAX_CALLBACK_DEPTH: usize = 200;
+
+// `lua_Alloc` function forwarding to the `Allocator` given to `Lua::new_with_allocator`.
+#[cfg(not(feature = "luajit"))]
+unsafe extern "C" fn allocator_shim(
+    ud: *mut c_void,
+    ptr: *mut c_void,
+    osize: usize,
+    nsize: usize,
+) -> *mut c_void {
+    let allocator = &mut **(ud as *mut Box<dyn Allocator>);
+    if nsize == 0 {
+        if !ptr.is_null() {
+            allocator.free(ptr as *mut u8, osize);
+        }
+        ptr::null_mut()
+    } else if ptr.is_null() {
+        // `osize` encodes the kind of object being allocated here, not a size.
+        allocator.alloc(nsize) as *mut c_void
+    } else {
+        allocator.realloc(ptr as *mut u8, osize, nsize) as *mut c_void
+    }
+}
+
+// Panic function for states not created by `luaL_newstate`, which sets the same one.
+#[cfg(not(feature = "luajit"))]
+unsafe extern "C" fn lua_panic(state: *mut ffi::lua_State) -> c_int {
+    let msg = ffi::lua_tostring(state, -1);
+    let msg = if msg.is_null() {
+        "?".into()
+    } else {
+        CStr::from_ptr(msg).to_string_lossy()
+    };
+    eprintln!("PANIC: unprotected error in call to Lua API ({})", msg);
+    0
+}
+
+// Number of VM instructions between checks of the interrupt flag.
+#[cfg(not(feature = "luajit"))]
+pub(crate) const INTERRUPT_HOOK_INSTRUCTIONS: c_int = 1000;
+
+// Line hit counts recorded while coverage is running, keyed by chunk source.
+#[cfg(not(feature = "luajit"))]
+type CoverageHits = HashMap<Vec<u8>, HashMap<u32, u64>>;
+
+// Counts the line of a line hook event, if coverage is running.
+#[cfg(not(feature = "luajit"))]
+pub(crate) unsafe fn record_coverage(state: *mut ffi::lua_State, ar: *mut ffi::lua_Debug) {
+    ffi::lua_pushlightuserdata(state, &COVERAGE_REGISTRY_KEY as *const u8 as *mut c_void);
+    ffi::lua_rawget(state, ffi::LUA_REGISTRYINDEX);
+    if ffi::lua_isnil(state, -1) == 0
+        && ffi::lua_getinfo(state, cstr!("S"), ar) != 0
+        && !(*ar).source.is_null()
+        && (*ar).currentline > 0
+    {
+        let hits = &mut *get_userdata::<CoverageHits>(state, -1);
+        let source = CStr::from_ptr((*ar).source).to_bytes();
+        let lines = match hits.get_mut(source) {
+            Some(lines) => lines,
+            None => hits.entry(source.to_vec()).or_insert_with(HashMap::new),
+        };
+        *lines.entry((*ar).currentline as u32).or_insert(0) += 1;
+    }
+    ffi::lua_pop(state, 1);
+}
+
+// Returns `true` while an `InterruptHandle` for the state is alive. Only the registry holds the
+// interrupt flag once every handle is dropped.
+#[cfg(not(feature = "luajit"))]
+pub(crate) unsafe fn has_interrupt_handles(state: *mut ffi::lua_State) -> bool {
+    ffi::lua_pushlightuserdata(
+        state,
+        &INTERRUPT_FLAG_REGISTRY_KEY as *const u8 as *mut c_void,
+    );
+    ffi::lua_rawget(state, ffi::LUA_REGISTRYINDEX);
+    let alive = Arc::strong_count(&*get_userdata::<Arc<AtomicBool>>(state, -1)) > 1;
+    ffi::lua_pop(state, 1);
+    alive
+}
+
+// Consumes a pending interrupt and raises the "interrupted" error, from inside a count hook.
+// Returns `false` if nothing can interrupt the state anymore, so that the caller may remove
+// its count hook.
+#[cfg(not(feature = "luajit"))]
+pub(crate) unsafe fn check_interrupt(state: *mut ffi::lua_State) -> bool {
+    ffi::lua_pushlightuserdata(
+        state,
+        &INTERRUPT_FLAG_REGISTRY_KEY as *const u8 as *mut c_void,
+    );
+    ffi::lua_rawget(state, ffi::LUA_REGISTRYINDEX);
+    let flag = &*get_userdata::<Arc<AtomicBool>>(state, -1);
+    let interrupted = flag.swap(false, Ordering::SeqCst);
+    let alive = Arc::strong_count(flag) > 1;
+    ffi::lua_pop(state, 1);
+
+    if interrupted {
+        ffi::lua_pushstring(state, cstr!("interrupted"));
+        ffi::lua_error(state);
+    }
+    alive
+}
+
+// Checks the interrupt flag on count events and records coverage on line events.
+#[cfg(not(feature = "luajit"))]
+pub(crate) extern "C" fn debug_hook(state: *mut ffi::lua_State, ar: *mut ffi::lua_Debug) {
+    unsafe {
+        if (*ar).event == ffi::LUA_HOOKLINE {
+            return record_coverage(state, ar);
+        }
+        if !check_interrupt(state) {
+            let mask = ffi::lua_gethookmask(state) & !ffi::LUA_MASKCOUNT;
+            ffi::lua_sethook(state, debug_hook, mask, 0);
+        }
+    }
+}
+
 static FUNCTION_CALLBACK_METATABLE_REGISTRY_KEY: u8 = 0;
+#[cfg(not(feature = "luajit"))]
+static INTERRUPT_FLAG_REGISTRY_KEY: u8 = 0;
+#[cfg(not(feature = "luajit"))]
+static COVERAGE_REGISTRY_KEY: u8 = 0;
 static FUNCTION_EXTRA_METATABLE_REGISTRY_KEY: u8 = 0;
+static ORDERED_TABLE_REGISTRY_KEY: u8 = 0;
 
 struct StaticUserDataMethods<'lua, T: 'static + UserData> {
     methods: Vec<(Vec<u8>, Callback<'lua, 'static>)>,
@@ -1370,8 +3722,9 @@ impl<'lua, T: 'static + UserData> UserDataMethods<'lua, T> for StaticUserDataMet
         R: ToLuaMulti<'lua>,
         M: 'static + Send + Fn(&'lua Lua, &T, A) -> Result<R>,
     {
-        self.methods
-            .push((name.as_ref().to_vec(), Self::box_method(method)));
+        let name = name.as_ref().to_vec();
+        let method = Self::box_method(&name, method);
+        self.methods.push((name, method));
     }
 
     fn add_method_mut<S, A, R, M>(&mut self, name: &S, method: M)
@@ -1381,8 +3734,9 @@ impl<'lua, T: 'static + UserData> UserDataMethods<'lua, T> for StaticUserDataMet
         R: ToLuaMulti<'lua>,
         M: 'static + Send + FnMut(&'lua Lua, &mut T, A) -> Result<R>,
     {
-        self.methods
-            .push((name.as_ref().to_vec(), Self::box_method_mut(method)));
+        let name = name.as_ref().to_vec();
+        let method = Self::box_method_mut(&name, method);
+        self.methods.push((name, method));
     }
 
     fn add_function<S, A, R, F>(&mut self, name: &S, function: F)
@@ -1403,8 +3757,9 @@ impl<'lua, T: 'static + UserData> UserDataMethods<'lua, T> for StaticUserDataMet
         R: ToLuaMulti<'lua>,
         F: 'static + Send + FnMut(&'lua Lua, A) -> Result<R>,
     {
-        self.methods
-            .push((name.as_ref().to_vec(), Self::box_function_mut(function)));
+        let name = name.as_ref().to_vec();
+        let function = Self::box_function_mut(&name, function);
+        self.methods.push((name, function));
     }
 
     fn add_meta_method<A, R, M>(&mut self, meta: MetaMethod, method: M)
@@ -1413,7 +3768,8 @@ impl<'lua, T: 'static + UserData> UserDataMethods<'lua, T> for StaticUserDataMet
         R: ToLuaMulti<'lua>,
         M: 'static + Send + Fn(&'lua Lua, &T, A) -> Result<R>,
     {
-        self.meta_methods.push((meta, Self::box_method(method)));
+        self.meta_methods
+            .push((meta, Self::box_method(meta.name(), method)));
     }
 
     fn add_meta_method_mut<A, R, M>(&mut self, meta: MetaMethod, method: M)
@@ -1422,7 +3778,8 @@ impl<'lua, T: 'static + UserData> UserDataMethods<'lua, T> for StaticUserDataMet
         R: ToLuaMulti<'lua>,
         M: 'static + Send + FnMut(&'lua Lua, &mut T, A) -> Result<R>,
     {
-        self.meta_methods.push((meta, Self::box_method_mut(method)));
+        self.meta_methods
+            .push((meta, Self::box_method_mut(meta.name(), method)));
     }
 
     fn add_meta_function<A, R, F>(&mut self, meta: MetaMethod, function: F)
@@ -1441,21 +3798,22 @@ impl<'lua, T: 'static + UserData> UserDataMethods<'lua, T> for StaticUserDataMet
         F: 'static + Send + FnMut(&'lua Lua, A) -> Result<R>,
     {
         self.meta_methods
-            .push((meta, Self::box_function_mut(function)));
+            .push((meta, Self::box_function_mut(meta.name(), function)));
     }
 }
 
 impl<'lua, T: 'static + UserData> StaticUserDataMethods<'lua, T> {
-    fn box_method<A, R, M>(method: M) -> Callback<'lua, 'static>
+    fn box_method<A, R, M>(name: &[u8], method: M) -> Callback<'lua, 'static>
     where
         A: FromLuaMulti<'lua>,
         R: ToLuaMulti<'lua>,
         M: 'static + Send + Fn(&'lua Lua, &T, A) -> Result<R>,
     {
+        let name = name.to_vec();
         Box::new(move |lua, mut args| {
             if let Some(front) = args.pop_front() {
                 let userdata = AnyUserData::from_lua(front, lua)?;
-                let userdata = userdata.borrow::<T>()?;
+                let userdata = userdata.borrow::<T>().map_err(|e| e.with_method(&name))?;
                 method(lua, &userdata, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
             } else {
                 Err(Error::FromLuaConversionError {
@@ -1467,20 +3825,23 @@ impl<'lua, T: 'static + UserData> StaticUserDataMethods<'lua, T> {
         })
     }
 
-    fn box_method_mut<A, R, M>(method: M) -> Callback<'lua, 'static>
+    fn box_method_mut<A, R, M>(name: &[u8], method: M) -> Callback<'lua, 'static>
     where
         A: FromLuaMulti<'lua>,
         R: ToLuaMulti<'lua>,
         M: 'static + Send + FnMut(&'lua Lua, &mut T, A) -> Result<R>,
     {
+        let name = name.to_vec();
         let method = RefCell::new(method);
         Box::new(move |lua, mut args| {
             if let Some(front) = args.pop_front() {
                 let userdata = AnyUserData::from_lua(front, lua)?;
-                let mut userdata = userdata.borrow_mut::<T>()?;
+                let mut userdata = userdata
+                    .borrow_mut::<T>()
+                    .map_err(|e| e.with_method(&name))?;
                 let mut method = method
                     .try_borrow_mut()
-                    .map_err(|_| Error::RecursiveMutCallback)?;
+                    .map_err(|_| Error::RecursiveMutCallback { method: None }.with_method(&name))?;
                 (&mut *method)(lua, &mut userdata, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
             } else {
                 Err(Error::FromLuaConversionError {
@@ -1501,17 +3862,18 @@ impl<'lua, T: 'static + UserData> StaticUserDataMethods<'lua, T> {
         Box::new(move |lua, args| function(lua, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua))
     }
 
-    fn box_function_mut<A, R, F>(function: F) -> Callback<'lua, 'static>
+    fn box_function_mut<A, R, F>(name: &[u8], function: F) -> Callback<'lua, 'static>
     where
         A: FromLuaMulti<'lua>,
         R: ToLuaMulti<'lua>,
         F: 'static + Send + FnMut(&'lua Lua, A) -> Result<R>,
     {
+        let name = name.to_vec();
         let function = RefCell::new(function);
         Box::new(move |lua, args| {
             let function = &mut *function
                 .try_borrow_mut()
-                .map_err(|_| Error::RecursiveMutCallback)?;
+                .map_err(|_| Error::RecursiveMutCallback { method: None }.with_method(&name))?;
             function(lua, A::from_lua_multi(args, lua)?)?.to_lua_multi(lua)
         })
     }
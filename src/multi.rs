@@ -1,13 +1,20 @@
 use std::iter::FromIterator;
 use std::ops::{Deref, DerefMut};
 use std::result::Result as StdResult;
+use std::string::String as StdString;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::lua::Lua;
 use crate::value::{FromLua, FromLuaMulti, MultiValue, Nil, ToLua, ToLuaMulti};
 
 /// Result is convertible to `MultiValue` following the common Lua idiom of returning the result
 /// on success, or in the case of an error, returning `nil` and an error message.
+///
+/// This means that a callback returning `Ok(Err(e))` returns `nil, e` to Lua rather than raising an
+/// error. To raise the error instead, return it as the callback's own `Err`, for example with
+/// [`ExternalResult::to_lua_err`].
+///
+/// [`ExternalResult::to_lua_err`]: trait.ExternalResult.html#tymethod.to_lua_err
 impl<'lua, T: ToLua<'lua>, E: ToLua<'lua>> ToLuaMulti<'lua> for StdResult<T, E> {
     fn to_lua_multi(self, lua: &'lua Lua) -> Result<MultiValue<'lua>> {
         let mut result = MultiValue::new();
@@ -36,6 +43,20 @@ impl<'lua, T: FromLua<'lua>> FromLuaMulti<'lua> for T {
     fn from_lua_multi(mut values: MultiValue<'lua>, lua: &'lua Lua) -> Result<Self> {
         Ok(T::from_lua(values.pop_front().unwrap_or(Nil), lua)?)
     }
+
+    fn from_lua_multi_buf(values: &mut MultiValue<'lua>, lua: &'lua Lua) -> Result<Self> {
+        T::from_lua(values.pop_front().unwrap_or(Nil), lua)
+    }
+
+    fn from_lua_args(
+        mut values: MultiValue<'lua>,
+        pos: usize,
+        names: &[StdString],
+        lua: &'lua Lua,
+    ) -> Result<Self> {
+        T::from_lua(values.pop_front().unwrap_or(Nil), lua)
+            .map_err(|err| with_arg_name(err, pos, names))
+    }
 }
 
 impl<'lua> ToLuaMulti<'lua> for MultiValue<'lua> {
@@ -137,6 +158,25 @@ impl<'lua, T: FromLua<'lua>> FromLuaMulti<'lua> for Variadic<T> {
     }
 }
 
+// Adds the name of argument `pos` to a conversion error for it, if the argument has a name.
+fn with_arg_name(err: Error, pos: usize, names: &[StdString]) -> Error {
+    match (err, names.get(pos)) {
+        (Error::FromLuaConversionError { from, to, message }, Some(name)) => {
+            let mut msg = format!("bad argument #{} '{}'", pos + 1, name);
+            if let Some(message) = message {
+                msg.push_str(": ");
+                msg.push_str(&message);
+            }
+            Error::FromLuaConversionError {
+                from,
+                to,
+                message: Some(msg),
+            }
+        }
+        (err, _) => err,
+    }
+}
+
 macro_rules! impl_tuple {
     () => (
         impl<'lua> ToLuaMulti<'lua> for () {
@@ -149,6 +189,10 @@ macro_rules! impl_tuple {
             fn from_lua_multi(_: MultiValue, _: &'lua Lua) -> Result<Self> {
                 Ok(())
             }
+
+            fn from_lua_multi_buf(_: &mut MultiValue, _: &'lua Lua) -> Result<Self> {
+                Ok(())
+            }
         }
     );
 
@@ -179,6 +223,30 @@ macro_rules! impl_tuple {
                 let $last = FromLuaMulti::from_lua_multi(values, lua)?;
                 Ok(($(FromLua::from_lua($name, lua)?,)* $last,))
             }
+
+            #[allow(non_snake_case)]
+            fn from_lua_multi_buf(values: &mut MultiValue<'lua>, lua: &'lua Lua) -> Result<Self> {
+                $(let $name = values.pop_front().unwrap_or(Nil);)*
+                let $last = FromLuaMulti::from_lua_multi_buf(values, lua)?;
+                Ok(($(FromLua::from_lua($name, lua)?,)* $last,))
+            }
+
+            #[allow(unused_mut)]
+            #[allow(non_snake_case)]
+            fn from_lua_args(
+                mut values: MultiValue<'lua>,
+                mut pos: usize,
+                names: &[StdString],
+                lua: &'lua Lua,
+            ) -> Result<Self> {
+                $(
+                    let $name = FromLua::from_lua(values.pop_front().unwrap_or(Nil), lua)
+                        .map_err(|err| with_arg_name(err, pos, names))?;
+                    pos += 1;
+                )*
+                let $last = FromLuaMulti::from_lua_args(values, pos, names, lua)?;
+                Ok(($($name,)* $last,))
+            }
         }
     );
 }
@@ -1,12 +1,22 @@
 //! Re-exports most types with an extra `Lua*` prefix to prevent name clashes.
 
 pub use crate::{
-    AnyUserData as LuaAnyUserData, Chunk as LuaChunk, Error as LuaError,
-    ExternalError as LuaExternalError, ExternalResult as LuaExternalResult, FromLua, FromLuaMulti,
-    Function as LuaFunction, Integer as LuaInteger, LightUserData as LuaLightUserData, Lua,
-    MetaMethod as LuaMetaMethod, MultiValue as LuaMultiValue, Nil as LuaNil, Number as LuaNumber,
-    RegistryKey as LuaRegistryKey, Result as LuaResult, Scope as LuaScope, String as LuaString,
-    Table as LuaTable, TablePairs as LuaTablePairs, TableSequence as LuaTableSequence,
-    Thread as LuaThread, ThreadStatus as LuaThreadStatus, ToLua, ToLuaMulti,
+    AnyUserData as LuaAnyUserData, Chunk as LuaChunk, Coercion as LuaCoercion, Error as LuaError,
+    ExternalError as LuaExternalError, ExternalResult as LuaExternalResult,
+    FloatPolicy as LuaFloatPolicy, FromLua, FromLuaMulti, Function as LuaFunction,
+    GlobalsSnapshot as LuaGlobalsSnapshot, Integer as LuaInteger,
+    LightUserData as LuaLightUserData, Lua, LuaVersion, MetaMethod as LuaMetaMethod,
+    MultiValue as LuaMultiValue, Nil as LuaNil, Number as LuaNumber, OwnedValue as LuaOwnedValue,
+    PinnedValue as LuaPinnedValue, RegistryKey as LuaRegistryKey, Result as LuaResult,
+    Scope as LuaScope, String as LuaString, Table as LuaTable, TablePairs as LuaTablePairs,
+    TableSequence as LuaTableSequence, Thread as LuaThread, ThreadStatus as LuaThreadStatus, ToLua,
+    ToLuaMulti, TypedLightUserData as LuaTypedLightUserData, TypedNumber as LuaTypedNumber,
     UserData as LuaUserData, UserDataMethods as LuaUserDataMethods, Value as LuaValue,
+    Weak as LuaWeak,
+};
+
+#[cfg(not(feature = "luajit"))]
+pub use crate::{
+    Allocator as LuaAllocator, CoverageReport as LuaCoverageReport,
+    InterruptHandle as LuaInterruptHandle,
 };
@@ -39,6 +39,21 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
         }
     }
 
+    /// Calls the given function with a nested `Scope`, whose values may borrow data that lives
+    /// shorter than this scope.
+    ///
+    /// Works exactly like [`Lua::scope`]: everything created through the inner scope is invalidated
+    /// and dropped as soon as `f` returns, before anything belonging to this (outer) scope.
+    ///
+    /// [`Lua::scope`]: struct.Lua.html#method.scope
+    pub fn scope<'inner, F, R>(&self, f: F) -> R
+    where
+        'scope: 'inner,
+        F: FnOnce(&Scope<'lua, 'inner>) -> R,
+    {
+        f(&Scope::new(self.lua))
+    }
+
     /// Wraps a Rust function or closure, creating a callable Lua function handle to it.
     ///
     /// This is a version of [`Lua::create_function`] that creates a callback which expires on
@@ -89,7 +104,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
         self.create_function(move |lua, args| {
             (&mut *func
                 .try_borrow_mut()
-                .map_err(|_| Error::RecursiveMutCallback)?)(lua, args)
+                .map_err(|_| Error::RecursiveMutCallback { method: None })?)(lua, args)
         })
     }
 
@@ -158,8 +173,10 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
         fn wrap_method<'scope, 'lua, 'callback: 'scope, T: 'scope>(
             scope: &Scope<'lua, 'scope>,
             data: Rc<RefCell<T>>,
+            name: &[u8],
             method: NonStaticMethod<'callback, T>,
         ) -> Result<Function<'lua>> {
+            let name = name.to_vec();
             // On methods that actually receive the userdata, we fake a type check on the passed in
             // userdata, where we pretend there is a unique type per call to
             // `Scope::create_nonstatic_userdata`.  You can grab a method from a userdata and call
@@ -197,9 +214,9 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
                         if !check_ud_type(lua, args.pop_front()) {
                             return Err(Error::UserDataTypeMismatch);
                         }
-                        let data = method_data
-                            .try_borrow()
-                            .map_err(|_| Error::UserDataBorrowError)?;
+                        let data = method_data.try_borrow().map_err(|_| {
+                            Error::UserDataBorrowError { method: None }.with_method(&name)
+                        })?;
                         method(lua, &*data, args)
                     });
                     unsafe { scope.create_callback(f) }
@@ -211,12 +228,12 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
                         if !check_ud_type(lua, args.pop_front()) {
                             return Err(Error::UserDataTypeMismatch);
                         }
-                        let mut method = method
-                            .try_borrow_mut()
-                            .map_err(|_| Error::RecursiveMutCallback)?;
-                        let mut data = method_data
-                            .try_borrow_mut()
-                            .map_err(|_| Error::UserDataBorrowMutError)?;
+                        let mut method = method.try_borrow_mut().map_err(|_| {
+                            Error::RecursiveMutCallback { method: None }.with_method(&name)
+                        })?;
+                        let mut data = method_data.try_borrow_mut().map_err(|_| {
+                            Error::UserDataBorrowMutError { method: None }.with_method(&name)
+                        })?;
                         (&mut *method)(lua, &mut *data, args)
                     });
                     unsafe { scope.create_callback(f) }
@@ -225,11 +242,9 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
                 NonStaticMethod::FunctionMut(function) => {
                     let function = RefCell::new(function);
                     let f = Box::new(move |lua, args| {
-                        (&mut *function
-                            .try_borrow_mut()
-                            .map_err(|_| Error::RecursiveMutCallback)?)(
-                            lua, args
-                        )
+                        (&mut *function.try_borrow_mut().map_err(|_| {
+                            Error::RecursiveMutCallback { method: None }.with_method(&name)
+                        })?)(lua, args)
                     });
                     unsafe { scope.create_callback(f) }
                 }
@@ -263,7 +278,12 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
 
             for (k, m) in ud_methods.meta_methods {
                 push_string(lua.state, k.name())?;
-                lua.push_value(Value::Function(wrap_method(self, data.clone(), m)?))?;
+                lua.push_value(Value::Function(wrap_method(
+                    self,
+                    data.clone(),
+                    k.name(),
+                    m,
+                )?))?;
 
                 protect_lua_closure(lua.state, 3, 1, |state| {
                     ffi::lua_rawset(state, -3);
@@ -278,7 +298,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
                 })?;
                 for (k, m) in ud_methods.methods {
                     push_string(lua.state, &k)?;
-                    lua.push_value(Value::Function(wrap_method(self, data.clone(), m)?))?;
+                    lua.push_value(Value::Function(wrap_method(self, data.clone(), &k, m)?))?;
                     protect_lua_closure(lua.state, 3, 1, |state| {
                         ffi::lua_rawset(state, -3);
                     })?;
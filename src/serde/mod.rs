@@ -0,0 +1,5 @@
+//! (De)Serialization support using serde.
+
+pub use self::de::Deserializer;
+
+pub mod de;
@@ -1,12 +1,17 @@
 use std::marker::PhantomData;
-use std::os::raw::c_int;
+use std::os::raw::{c_int, c_void};
 
 use crate::error::{Error, Result};
 use crate::ffi;
 use crate::function::Function;
-use crate::types::{Integer, LuaRef};
-use crate::util::{assert_stack, protect_lua, protect_lua_closure, StackGuard};
+use crate::lua::{Coercion, Lua};
+use crate::types::{Integer, LightUserData, LuaRef, Number};
+use crate::util::{assert_stack, check_stack, protect_lua, protect_lua_closure, StackGuard};
 use crate::value::{FromLua, FromLuaMulti, Nil, ToLua, ToLuaMulti, Value};
+use crate::weak::Weak;
+
+// Maximum number of `__index` tables followed by `Table::get_resolved`, as in Lua 5.3.
+const MAX_INDEX_CHAIN: usize = 2000;
 
 /// Handle to an internal Lua table.
 #[derive(Clone, Debug)]
@@ -66,6 +71,68 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Sets many key-value pairs at once.
+    ///
+    /// This is equivalent to calling [`set`] for every pair in order, and might invoke the
+    /// `__newindex` metamethod, but the pairs are sent to Lua in batches rather than one by one,
+    /// which is considerably faster for large tables. If a pair repeats a key, the later value
+    /// wins. On error, some of the pairs preceding the failing one may already have been set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config = lua.create_table()?;
+    /// config.set_all(vec![("width", 800), ("height", 600)])?;
+    /// assert_eq!(config.get::<_, i64>("height")?, 600);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`set`]: #method.set
+    pub fn set_all<K, V, I>(&self, pairs: I) -> Result<()>
+    where
+        K: ToLua<'lua>,
+        V: ToLua<'lua>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.set_pairs(pairs, false)
+    }
+
+    /// Removes the value associated to `key` from the table.
+    ///
+    /// This is equivalent to `table[key] = nil` in Lua, or to calling [`set`] with [`Nil`], and
+    /// might invoke the `__newindex` metamethod.
+    ///
+    /// Unlike [`raw_remove`], this never shifts down the elements of a sequence: removing an
+    /// integer key in the middle of a sequence leaves a hole.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua.load("{ 'a', 'b', 'c', key = 'value' }").eval()?;
+    ///
+    /// table.remove("key")?;
+    /// table.remove(2)?;
+    /// assert!(!table.contains_key("key")?);
+    /// assert!(!table.contains_key(2)?);
+    /// assert_eq!(table.get::<_, String>(3)?, "c");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`set`]: #method.set
+    /// [`Nil`]: enum.Value.html#variant.Nil
+    /// [`raw_remove`]: #method.raw_remove
+    pub fn remove<K: ToLua<'lua>>(&self, key: K) -> Result<()> {
+        self.set(key, Nil)
+    }
+
     /// Gets the value associated to `key` from the table.
     ///
     /// If no value is associated to `key`, returns the `nil` value.
@@ -110,6 +177,106 @@ impl<'lua> Table<'lua> {
         V::from_lua(value, lua)
     }
 
+    /// Gets the value associated to `key` like [`get`], also returning the table it was found in.
+    ///
+    /// This follows the chain of `__index` tables the same way Lua does, and returns the table
+    /// which actually holds the value, so that it can be cached and read with [`raw_get`] next
+    /// time. The returned table is `None` if the value was produced by an `__index` function (or
+    /// other callable), or if the key was not found, in which case the value is `nil`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let (base, obj): (Table, Table) = lua.load(r#"
+    ///     local base = { greet = "hello" }
+    ///     local derived = setmetatable({}, { __index = base })
+    ///     return base, setmetatable({}, { __index = derived })
+    /// "#).eval()?;
+    ///
+    /// let (greet, owner) = obj.get_resolved::<_, String>("greet")?;
+    /// assert_eq!(greet, "hello");
+    /// assert_eq!(owner, Some(base));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`get`]: #method.get
+    /// [`raw_get`]: #method.raw_get
+    pub fn get_resolved<K: ToLua<'lua>, V: FromLua<'lua>>(
+        &self,
+        key: K,
+    ) -> Result<(V, Option<Table<'lua>>)> {
+        let lua = self.0.lua;
+        let key = key.to_lua(lua)?;
+
+        let mut table = self.clone();
+        for _ in 0..MAX_INDEX_CHAIN {
+            let value: Value = table.raw_get(key.clone())?;
+            if value != Nil {
+                return Ok((V::from_lua(value, lua)?, Some(table)));
+            }
+
+            let index = match table.get_metatable() {
+                Some(mt) => mt.raw_get::<_, Value>("__index")?,
+                None => Nil,
+            };
+            match index {
+                Nil => return Ok((V::from_lua(Nil, lua)?, None)),
+                Value::Table(next) => table = next,
+                // Let Lua handle functions and other callables
+                _ => return Ok((table.get(key)?, None)),
+            }
+        }
+
+        Err(Error::RuntimeError(
+            "'__index' chain too long; possible loop".to_string(),
+        ))
+    }
+
+    /// Gets the value associated to `key`, inserting the result of `f` first if there is none.
+    ///
+    /// The lookup goes through [`get`] and the insertion through [`set`], so `__index` and
+    /// `__newindex` metamethods are honored the same way as when calling them in turn. `f` only
+    /// runs on a miss, that is when the lookup yields `nil`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let cache = lua.create_table()?;
+    ///
+    /// let v: i64 = cache.get_or_insert_with("answer", || Ok(42))?;
+    /// assert_eq!(v, 42);
+    /// let v: i64 = cache.get_or_insert_with("answer", || unreachable!())?;
+    /// assert_eq!(v, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`get`]: #method.get
+    /// [`set`]: #method.set
+    pub fn get_or_insert_with<K, V, F>(&self, key: K, f: F) -> Result<V>
+    where
+        K: ToLua<'lua> + Clone,
+        V: ToLua<'lua> + FromLua<'lua>,
+        F: FnOnce() -> Result<V>,
+    {
+        let lua = self.0.lua;
+        match self.get(key.clone())? {
+            Nil => {}
+            value => return V::from_lua(value, lua),
+        }
+
+        let value = f()?.to_lua(lua)?;
+        self.set(key, value.clone())?;
+        V::from_lua(value, lua)
+    }
+
     /// Checks whether the table contains a non-nil value for `key`.
     pub fn contains_key<K: ToLua<'lua>>(&self, key: K) -> Result<bool> {
         let lua = self.0.lua;
@@ -166,6 +333,50 @@ impl<'lua> Table<'lua> {
         self.get::<_, Function>(key)?.call(args)
     }
 
+    /// Like [`call`], but returns `Ok(None)` if the value associated to `key` is not a function.
+    ///
+    /// This is convenient for calling optional methods, such as event handlers that a Lua object
+    /// may or may not define. Errors raised by the function itself are still returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let plugin: Table = lua.load(r#"
+    ///     {
+    ///         on_load = function(self, name) return "loaded " .. name end
+    ///     }
+    /// "#).eval()?;
+    ///
+    /// let loaded: Option<String> = plugin.call_opt("on_load", "plugin")?;
+    /// assert_eq!(loaded, Some("loaded plugin".to_string()));
+    /// assert_eq!(plugin.call_opt::<_, _, String>("on_unload", ())?, None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// This might invoke the `__index` metamethod.
+    ///
+    /// [`call`]: #method.call
+    pub fn call_opt<K, A, R>(&self, key: K, args: A) -> Result<Option<R>>
+    where
+        K: ToLua<'lua>,
+        A: ToLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        match self.get::<_, Value>(key)? {
+            Value::Function(f) => {
+                let lua = self.0.lua;
+                let mut args = args.to_lua_multi(lua)?;
+                args.push_front(Value::Table(self.clone()));
+                f.call(args).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
     /// Compares two tables for equality.
     ///
     /// Tables are compared by reference first.
@@ -222,6 +433,35 @@ impl<'lua> Table<'lua> {
         Ok(false)
     }
 
+    /// Compares two tables structurally, regardless of their iteration order.
+    ///
+    /// The tables are equal if they have the same set of keys and the values under each key are
+    /// equal. Values which are both tables are compared recursively; all other values are compared
+    /// like [`Value`]'s `PartialEq` does, so reference types such as functions and userdata must be
+    /// the same object. Keys are looked up by raw equality. Cyclic tables are supported: a pair of
+    /// tables which is already being compared further up is assumed to be equal. No metamethods
+    /// are invoked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let t1: Table = lua.load("{ a = 1, b = { 1, 2 }, 'x' }").eval()?;
+    /// let t2: Table = lua.load("{ 'x', b = { 1, 2 }, a = 1 }").eval()?;
+    /// let t3: Table = lua.load("{ 'x', b = { 1, 3 }, a = 1 }").eval()?;
+    /// assert!(t1.deep_equals(&t2)?);
+    /// assert!(!t1.deep_equals(&t3)?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Value`]: enum.Value.html
+    pub fn deep_equals<T: AsRef<Self>>(&self, other: T) -> Result<bool> {
+        deep_equals(self, other.as_ref(), &mut Vec::new())
+    }
+
     /// Sets a key-value pair without invoking metamethods.
     pub fn raw_set<K: ToLua<'lua>, V: ToLua<'lua>>(&self, key: K, value: V) -> Result<()> {
         let lua = self.0.lua;
@@ -262,6 +502,22 @@ impl<'lua> Table<'lua> {
         V::from_lua(value, lua)
     }
 
+    /// Sets many key-value pairs at once without invoking metamethods.
+    ///
+    /// This is the raw counterpart of [`set_all`], equivalent to calling [`raw_set`] for every pair
+    /// in order.
+    ///
+    /// [`set_all`]: #method.set_all
+    /// [`raw_set`]: #method.raw_set
+    pub fn raw_set_all<K, V, I>(&self, pairs: I) -> Result<()>
+    where
+        K: ToLua<'lua>,
+        V: ToLua<'lua>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.set_pairs(pairs, true)
+    }
+
     /// Inserts element value at position idx to the table, shifting up the elements from table[idx].
     /// The worst case complexity is O(n), where n is the table length.
     pub fn raw_insert<V: ToLua<'lua>>(&self, idx: Integer, value: V) -> Result<()> {
@@ -297,6 +553,12 @@ impl<'lua> Table<'lua> {
     /// where n is the table length.
     ///
     /// For othey key types this is equivalent to setting table[key] = nil.
+    ///
+    /// Unlike [`remove`], this does not invoke the `__newindex` metamethod. To delete an integer
+    /// key without shifting the sequence, use [`remove`] or [`raw_set`] with `nil`.
+    ///
+    /// [`remove`]: #method.remove
+    /// [`raw_set`]: #method.raw_set
     pub fn raw_remove<K: ToLua<'lua>>(&self, key: K) -> Result<()> {
         let lua = self.0.lua;
         let key = key.to_lua(lua)?;
@@ -353,6 +615,189 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Collects the elements `1..=raw_len()` of the table into a vector of floats.
+    ///
+    /// This is a fast path for large numeric arrays which reads the elements directly from the
+    /// stack, without going through [`Value`] and [`FromLua`]. Numeric strings are converted like
+    /// for `f64`, unless strict coercion is enabled with [`Lua::set_coercion`]. No metamethods
+    /// are invoked. Any other element fails with an `Error::FromLuaConversionError` naming its
+    /// index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let coords: Table = lua.load("{1.5, 2, -3}").eval()?;
+    /// assert_eq!(coords.to_vec_numbers()?, vec![1.5, 2.0, -3.0]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Value`]: enum.Value.html
+    /// [`FromLua`]: trait.FromLua.html
+    /// [`Lua::set_coercion`]: struct.Lua.html#method.set_coercion
+    pub fn to_vec_numbers(&self) -> Result<Vec<Number>> {
+        self.to_vec_with("number", |state| unsafe {
+            let mut isnum = 0;
+            let n = ffi::lua_tonumberx(state, -1, &mut isnum);
+            if isnum != 0 {
+                Some(n)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Collects the elements `1..=raw_len()` of the table into a vector of integers.
+    ///
+    /// This works like [`to_vec_numbers`], but elements must be integers, or floats and numeric
+    /// strings with an exact integer value.
+    ///
+    /// [`to_vec_numbers`]: #method.to_vec_numbers
+    pub fn to_vec_integers(&self) -> Result<Vec<Integer>> {
+        self.to_vec_with("integer", |state| unsafe {
+            let mut isnum = 0;
+            let i = ffi::lua_tointegerx(state, -1, &mut isnum);
+            if isnum != 0 {
+                Some(i)
+            } else {
+                None
+            }
+        })
+    }
+
+    // Converts each element of the array part with `convert`, which is given the element at the
+    // top of the stack.
+    fn to_vec_with<T, F>(&self, to: &'static str, convert: F) -> Result<Vec<T>>
+    where
+        F: Fn(*mut ffi::lua_State) -> Option<T>,
+    {
+        let lua = self.0.lua;
+        let strict = lua.coercion() == Coercion::Strict;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 2);
+
+            lua.push_ref(&self.0);
+            let len = ffi::lua_rawlen(lua.state, -1) as Integer;
+            let mut vec = Vec::with_capacity(len as usize);
+            for i in 1..=len {
+                ffi::lua_rawgeti(lua.state, -1, i);
+                let is_string = ffi::lua_type(lua.state, -1) == ffi::LUA_TSTRING;
+                match convert(lua.state) {
+                    Some(v) if !(strict && is_string) => vec.push(v),
+                    _ => {
+                        return Err(Error::FromLuaConversionError {
+                            from: lua.pop_value().type_name(),
+                            to,
+                            message: Some(format!("element {} is not a valid {}", i, to)),
+                        });
+                    }
+                }
+                ffi::lua_pop(lua.state, 1);
+            }
+            Ok(vec)
+        }
+    }
+
+    /// Returns `true` if the table is a proper sequence.
+    ///
+    /// A table is a proper sequence if its keys are exactly the integers `1..=n`, where `n` is the
+    /// [`raw_len`] of the table, with no holes and no other keys. An empty table is a sequence.
+    /// No metamethods are invoked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let array: Table = lua.load("{ 1, 2, 3 }").eval()?;
+    /// let object: Table = lua.load("{ 1, 2, 3, key = 4 }").eval()?;
+    /// assert!(array.is_sequence()?);
+    /// assert!(!object.is_sequence()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`raw_len`]: #method.raw_len
+    pub fn is_sequence(&self) -> Result<bool> {
+        let len = self.raw_len();
+        let mut count: Integer = 0;
+        for pair in self.clone().pairs::<Value, Value>() {
+            match pair? {
+                (Value::Integer(i), _) if i >= 1 && i <= len => count += 1,
+                _ => return Ok(false),
+            }
+        }
+        Ok(count == len)
+    }
+
+    /// Appends the sequence part of `other` to the end of this table's sequence.
+    ///
+    /// The values `other[1]` to `other[n]`, where `n` is the [`raw_len`] of `other` at the time
+    /// of the call, are copied to consecutive indices starting right after the [`raw_len`] of this
+    /// table. No metamethods are invoked, `other` is left unchanged, and any non-sequence keys in
+    /// `other` are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let batch: Table = lua.load("{ 'a', 'b' }").eval()?;
+    /// let more: Table = lua.load("{ 'c', 'd', name = 'more' }").eval()?;
+    ///
+    /// batch.append_sequence(&more)?;
+    /// let values = batch.sequence_values().collect::<Result<Vec<String>>>()?;
+    /// assert_eq!(values, vec!["a", "b", "c", "d"]);
+    /// assert_eq!(more.raw_len(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`raw_len`]: #method.raw_len
+    pub fn append_sequence<T: AsRef<Self>>(&self, other: T) -> Result<()> {
+        let other = other.as_ref();
+        let offset = self.raw_len();
+        let len = other.raw_len();
+        for i in 1..=len {
+            let value: Value = other.raw_get(i)?;
+            self.raw_set(offset + i, value)?;
+        }
+        Ok(())
+    }
+
+    /// Renders the table in a human-readable, Lua-literal-like form for debugging.
+    ///
+    /// This is a shorthand for [`Value::to_debug_string`] on this table.
+    ///
+    /// [`Value::to_debug_string`]: enum.Value.html#method.to_debug_string
+    pub fn dump(&self, max_depth: usize) -> String {
+        Value::Table(self.clone()).to_debug_string(max_depth)
+    }
+
+    /// Returns `true` if this table was created by `lua`, or by a `Lua` sharing its main state.
+    ///
+    /// Passing handles to a different Lua state fails with [`Error::MismatchedLua`].
+    ///
+    /// [`Error::MismatchedLua`]: enum.Error.html#variant.MismatchedLua
+    pub fn belongs_to(&self, lua: &Lua) -> bool {
+        self.0.lua.shares_state(lua)
+    }
+
+    /// Creates a weak reference to this table.
+    ///
+    /// The weak reference does not keep the table alive. Refer to [`Weak`] for details.
+    ///
+    /// [`Weak`]: struct.Weak.html
+    pub fn downgrade(&self) -> Result<Weak<'lua, Table<'lua>>> {
+        Weak::new(self.0.lua, Value::Table(self.clone()))
+    }
+
     /// Returns a reference to the metatable of this table, or `None` if no metatable is set.
     ///
     /// Unlike the `getmetatable` Lua function, this method ignores the `__metatable` field.
@@ -390,6 +835,206 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Returns the pairs of a table created by [`Lua::create_ordered_table`] in insertion order.
+    ///
+    /// The pairs are collected up front, so the table may be modified while iterating over the
+    /// result. Returns an error if this table was not created by `create_ordered_table`.
+    ///
+    /// [`Lua::create_ordered_table`]: struct.Lua.html#method.create_ordered_table
+    pub fn ordered_pairs<K: FromLua<'lua>, V: FromLua<'lua>>(&self) -> Result<Vec<(K, V)>> {
+        let lua = self.0.lua;
+        let keys = match self.get_metatable() {
+            Some(mt) => match mt.raw_get::<_, Value>(ordered_keys_marker())? {
+                Value::Table(keys) => Some((mt.raw_get::<_, Table>("__index")?, keys)),
+                _ => None,
+            },
+            None => None,
+        };
+        let (data, keys) = keys.ok_or_else(|| {
+            Error::RuntimeError("table was not created by create_ordered_table".to_string())
+        })?;
+
+        let mut pairs = Vec::new();
+        for key in keys.sequence_values::<Value>() {
+            let key = key?;
+            let value = data.raw_get(key.clone())?;
+            pairs.push((K::from_lua(key, lua)?, V::from_lua(value, lua)?));
+        }
+        Ok(pairs)
+    }
+
+    /// Returns a read-only view of this table.
+    ///
+    /// The result is a proxy: a new, empty table whose metatable forwards reads to this table and
+    /// raises "attempt to modify a read-only table" on writes. Nested tables are returned as
+    /// read-only proxies as well, and on Lua 5.2 and 5.3 `pairs` and the length operator work
+    /// through the proxy too. The proxy's metatable is protected from `getmetatable` and
+    /// `setmetatable`.
+    ///
+    /// As a proxy, the returned table has a different identity than this table, and `rawset` or
+    /// `rawget` on it bypass the forwarding. This table itself remains mutable, and changes made to
+    /// it (from Rust, for instance) are visible through the proxy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config = lua.create_table()?;
+    /// config.set("name", "server")?;
+    /// config.set("limits", lua.create_table_from(vec![("connections", 100)])?)?;
+    /// lua.globals().set("config", config.freeze()?)?;
+    ///
+    /// assert_eq!(lua.load("config.limits.connections").eval::<i64>()?, 100);
+    /// assert!(lua.load("config.name = 'client'").exec().is_err());
+    /// assert!(lua.load("config.limits.connections = 1").exec().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn freeze(&self) -> Result<Table<'lua>> {
+        let lua = self.0.lua;
+        let freeze = lua.cached_chunk(
+            &FREEZE_REGISTRY_KEY,
+            "=freeze",
+            r#"
+                local is_table, next, set_metatable, read_only_error, root = ...
+                local proxies = set_metatable({}, { __mode = "k" })
+                local function freeze(t)
+                    local proxy = proxies[t]
+                    if proxy ~= nil then
+                        return proxy
+                    end
+                    local function wrap(v)
+                        if is_table(v) then
+                            return freeze(v)
+                        end
+                        return v
+                    end
+                    proxy = set_metatable({}, {
+                        __index = function(_, k)
+                            return wrap(t[k])
+                        end,
+                        __newindex = read_only_error,
+                        __len = function()
+                            return #t
+                        end,
+                        __pairs = function()
+                            return function(_, k)
+                                local nk, v = next(t, k)
+                                return nk, wrap(v)
+                            end, proxy, nil
+                        end,
+                        __metatable = false,
+                    })
+                    proxies[t] = proxy
+                    return proxy
+                end
+                return freeze(root)
+            "#,
+        )?;
+        // The builtins are passed from Rust, so that scripts replacing the globals of the same
+        // name cannot make the proxies writable
+        let (is_table, next, set_metatable, read_only_error) = unsafe {
+            (
+                lua.create_c_function(freeze_is_table, &[])?,
+                lua.create_c_function(freeze_next, &[])?,
+                lua.create_c_function(freeze_set_metatable, &[])?,
+                lua.create_c_function(freeze_read_only_error, &[])?,
+            )
+        };
+        freeze.call((is_table, next, set_metatable, read_only_error, self.clone()))
+    }
+
+    /// Sets the `__index` metamethod of this table to `f`.
+    ///
+    /// If the table has no metatable, a new one is created. Otherwise the existing metatable is
+    /// kept and only its `__index` field is replaced, so any prior `__index` (function or table) is
+    /// overwritten while other metamethods are left untouched.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let proxy = lua.create_table()?;
+    /// proxy.set_index_handler(lua.create_function(|_, (_, key): (Table, String)| {
+    ///     Ok(key.to_uppercase())
+    /// })?)?;
+    /// assert_eq!(proxy.get::<_, String>("hello")?, "HELLO");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_index_handler(&self, f: Function<'lua>) -> Result<()> {
+        self.set_metatable_field("__index", f)
+    }
+
+    /// Sets the `__newindex` metamethod of this table to `f`.
+    ///
+    /// Like [`set_index_handler`], this creates a metatable if needed and replaces any prior
+    /// `__newindex` without affecting other metamethods.
+    ///
+    /// [`set_index_handler`]: #method.set_index_handler
+    pub fn set_newindex_handler(&self, f: Function<'lua>) -> Result<()> {
+        self.set_metatable_field("__newindex", f)
+    }
+
+    fn set_pairs<K, V, I>(&self, pairs: I, raw: bool) -> Result<()>
+    where
+        K: ToLua<'lua>,
+        V: ToLua<'lua>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        // Number of pairs sent to Lua in a single protected call.
+        const BATCH_SIZE: c_int = 32;
+
+        let lua = self.0.lua;
+        let mut pairs = pairs.into_iter();
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            check_stack(lua.state, 2 * BATCH_SIZE + 6)?;
+
+            loop {
+                lua.push_ref(&self.0);
+                let mut n = 0;
+                for (key, value) in pairs.by_ref().take(BATCH_SIZE as usize) {
+                    lua.push_value(key.to_lua(lua)?)?;
+                    lua.push_value(value.to_lua(lua)?)?;
+                    n += 1;
+                }
+
+                protect_lua_closure(lua.state, 2 * n + 1, 0, |state| {
+                    for i in 0..n {
+                        ffi::lua_pushvalue(state, 2 * i + 2);
+                        ffi::lua_pushvalue(state, 2 * i + 3);
+                        if raw {
+                            ffi::lua_rawset(state, 1);
+                        } else {
+                            ffi::lua_settable(state, 1);
+                        }
+                    }
+                })?;
+
+                if n < BATCH_SIZE {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn set_metatable_field(&self, name: &str, f: Function<'lua>) -> Result<()> {
+        let metatable = match self.get_metatable() {
+            Some(metatable) => metatable,
+            None => {
+                let metatable = self.0.lua.create_table()?;
+                self.set_metatable(Some(metatable.clone()));
+                metatable
+            }
+        };
+        metatable.raw_set(name, f)
+    }
+
     /// Consume this table and return an iterator over the pairs of the table.
     ///
     /// This works like the Lua `pairs` function, but does not invoke the `__pairs` metamethod.
@@ -400,7 +1045,9 @@ impl<'lua> Table<'lua> {
     ///
     /// While this method consumes the `Table` object, it can not prevent code from mutating the
     /// table while the iteration is in progress. Refer to the [Lua manual] for information about
-    /// the consequences of such mutation.
+    /// the consequences of such mutation. If the key reached last can no longer be found (for
+    /// example, because it was removed and the table has since been rehashed), the iterator
+    /// yields the resulting `RuntimeError` once and then stops.
     ///
     /// # Examples
     ///
@@ -431,6 +1078,116 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Returns the pair following `key` in the table, like Lua's `next` function.
+    ///
+    /// Passing `None` returns the first pair. Returns `None` once all pairs have been visited.
+    /// Unlike [`pairs`], this does not hold any state between calls, so iteration can be driven
+    /// manually and paused at any point by keeping the last key. The order of traversal is
+    /// unspecified, and the same rules as for [`pairs`] apply if the table is modified in between.
+    ///
+    /// This does not invoke the `__pairs` metamethod. Fails with a `RuntimeError` if `key` is not
+    /// in the table.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table = lua.create_table()?;
+    /// table.set("a", 1)?;
+    /// table.set("b", 2)?;
+    ///
+    /// let mut sum = 0;
+    /// let mut key = None;
+    /// while let Some((k, v)) = table.next(key)? {
+    ///     sum += lua.unpack::<i64>(v)?;
+    ///     key = Some(k);
+    /// }
+    /// assert_eq!(sum, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`pairs`]: #method.pairs
+    pub fn next<K: ToLua<'lua>>(
+        &self,
+        key: Option<K>,
+    ) -> Result<Option<(Value<'lua>, Value<'lua>)>> {
+        let lua = self.0.lua;
+        let key = match key {
+            Some(key) => key.to_lua(lua)?,
+            None => Nil,
+        };
+
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 5);
+
+            lua.push_ref(&self.0);
+            lua.push_value(key)?;
+
+            if protect_lua_closure(lua.state, 2, ffi::LUA_MULTRET, |state| {
+                ffi::lua_next(state, -2) != 0
+            })? {
+                let value = lua.pop_value();
+                let key = lua.pop_value();
+                Ok(Some((key, value)))
+            } else {
+                Ok(None)
+            }
+        }
+    }
+
+    /// Creates a new table from the pairs of this one, transformed by `f`.
+    ///
+    /// Every key-value pair is converted to `K` and `V` and passed to `f`. Pairs for which `f`
+    /// returns `Some((key, value))` are inserted into the new table with [`raw_set`], and pairs
+    /// for which it returns `None` are skipped. Like [`pairs`], this does not invoke the `__pairs`
+    /// metamethod.
+    ///
+    /// Any conversion error, or error returned by `f`, stops the iteration and is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let prices: Table = lua.load("{ apple = 3, melon = 12, plum = 1 }").eval()?;
+    ///
+    /// // Keep the expensive items, with prices in cents
+    /// let expensive = prices.filter_map(|name: String, price: i64| {
+    ///     Ok(if price > 2 { Some((name, price * 100)) } else { None })
+    /// })?;
+    ///
+    /// assert_eq!(expensive.get::<_, i64>("apple")?, 300);
+    /// assert_eq!(expensive.get::<_, i64>("melon")?, 1200);
+    /// assert!(!expensive.contains_key("plum")?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`raw_set`]: #method.raw_set
+    /// [`pairs`]: #method.pairs
+    pub fn filter_map<K, V, K2, V2, F>(&self, mut f: F) -> Result<Table<'lua>>
+    where
+        K: FromLua<'lua>,
+        V: FromLua<'lua>,
+        K2: ToLua<'lua>,
+        V2: ToLua<'lua>,
+        F: FnMut(K, V) -> Result<Option<(K2, V2)>>,
+    {
+        let table = self.0.lua.create_table()?;
+        for pair in self.clone().pairs::<K, V>() {
+            let (k, v) = pair?;
+            if let Some((k, v)) = f(k, v)? {
+                table.raw_set(k, v)?;
+            }
+        }
+        Ok(table)
+    }
+
     /// Consume this table and return an iterator over all values in the sequence part of the table.
     ///
     /// The iterator will yield all values `t[1]`, `t[2]`, and so on, until a `nil` value is
@@ -524,6 +1281,8 @@ where
                     lua.push_ref(&self.table);
                     lua.push_value(next_key)?;
 
+                    // `lua_next` raises an error if `next_key` is no longer in the table. As
+                    // `self.next_key` has been taken, any error here also ends the iteration.
                     if protect_lua_closure(lua.state, 2, ffi::LUA_MULTRET, |state| {
                         ffi::lua_next(state, -2) != 0
                     })? {
@@ -604,3 +1363,81 @@ where
         }
     }
 }
+
+// Compares `a` and `b` structurally. `visiting` holds the pairs of tables currently being compared
+// further up, which are assumed to be equal to terminate on cycles.
+fn deep_equals<'lua>(
+    a: &Table<'lua>,
+    b: &Table<'lua>,
+    visiting: &mut Vec<(Table<'lua>, Table<'lua>)>,
+) -> Result<bool> {
+    if a == b || visiting.iter().any(|(x, y)| x == a && y == b) {
+        return Ok(true);
+    }
+    visiting.push((a.clone(), b.clone()));
+
+    let mut count: Integer = 0;
+    let mut equal = true;
+    for pair in a.clone().pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        let other: Value = b.raw_get(key)?;
+        equal = match (value, other) {
+            (Value::Table(x), Value::Table(y)) => deep_equals(&x, &y, visiting)?,
+            (Value::Nil, _) | (_, Value::Nil) => false,
+            (x, y) => x == y,
+        };
+        if !equal {
+            break;
+        }
+        count += 1;
+    }
+    if equal {
+        for pair in b.clone().pairs::<Value, Value>() {
+            pair?;
+            count -= 1;
+        }
+        equal = count == 0;
+    }
+
+    visiting.pop();
+    Ok(equal)
+}
+
+// Returns the key under which the metatable of an ordered table stores its key index.
+pub(crate) fn ordered_keys_marker() -> LightUserData {
+    LightUserData(&ORDERED_KEYS_MARKER as *const u8 as *mut c_void)
+}
+
+static ORDERED_KEYS_MARKER: u8 = 0;
+
+// Registry key of the compiled chunk used by `Table::freeze`.
+static FREEZE_REGISTRY_KEY: u8 = 0;
+
+// Returns whether the first argument is a table, like `type(v) == "table"`.
+unsafe extern "C" fn freeze_is_table(state: *mut ffi::lua_State) -> c_int {
+    ffi::lua_pushboolean(state, (ffi::lua_type(state, 1) == ffi::LUA_TTABLE) as c_int);
+    1
+}
+
+// Works like the `next` builtin.
+unsafe extern "C" fn freeze_next(state: *mut ffi::lua_State) -> c_int {
+    ffi::lua_settop(state, 2);
+    if ffi::lua_next(state, 1) != 0 {
+        2
+    } else {
+        ffi::lua_pushnil(state);
+        1
+    }
+}
+
+// Sets the metatable of the first argument to the second one and returns the first argument.
+unsafe extern "C" fn freeze_set_metatable(state: *mut ffi::lua_State) -> c_int {
+    ffi::lua_settop(state, 2);
+    ffi::lua_setmetatable(state, 1);
+    1
+}
+
+// Used directly as `__newindex`, so that the error points at the assignment.
+unsafe extern "C" fn freeze_read_only_error(state: *mut ffi::lua_State) -> c_int {
+    ffi::luaL_error(state, cstr!("attempt to modify a read-only table"))
+}
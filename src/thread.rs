@@ -1,12 +1,25 @@
-use std::os::raw::c_int;
+#[cfg(not(feature = "luajit"))]
+use std::cmp;
+use std::hash::{Hash, Hasher};
+#[cfg(not(feature = "luajit"))]
+use std::mem;
+use std::os::raw::{c_int, c_void};
+#[cfg(not(feature = "luajit"))]
+use std::ptr;
 
 use crate::error::{Error, Result};
 use crate::ffi;
-use crate::types::LuaRef;
+use crate::lua::Lua;
+#[cfg(not(feature = "luajit"))]
+use crate::lua::{
+    check_interrupt, debug_hook, has_interrupt_handles, record_coverage,
+    INTERRUPT_HOOK_INSTRUCTIONS,
+};
+use crate::types::{LightUserData, LuaRef};
 use crate::util::{
     assert_stack, check_stack, error_traceback, pop_error, protect_lua_closure, StackGuard,
 };
-use crate::value::{FromLuaMulti, MultiValue, ToLuaMulti};
+use crate::value::{FromLuaMulti, MultiValue, ToLuaMulti, Value};
 
 /// Status of a Lua thread (or coroutine).
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -78,7 +91,7 @@ impl<'lua> Thread<'lua> {
         let args = args.to_lua_multi(lua)?;
         let results = unsafe {
             let _sg = StackGuard::new(lua.state);
-            assert_stack(lua.state, 3);
+            assert_stack(lua.state, 4);
 
             lua.push_ref(&self.0);
             let thread_state = ffi::lua_tothread(lua.state, -1);
@@ -88,6 +101,9 @@ impl<'lua> Thread<'lua> {
                 return Err(Error::CoroutineInactive);
             }
 
+            #[cfg(not(feature = "luajit"))]
+            prepare_hook(lua.state, thread_state);
+
             ffi::lua_pop(lua.state, 1);
 
             let nargs = args.len() as c_int;
@@ -101,10 +117,12 @@ impl<'lua> Thread<'lua> {
 
             let ret = ffi::lua_resume(thread_state, lua.state, nargs);
             if ret != ffi::LUA_OK && ret != ffi::LUA_YIELD {
-                protect_lua_closure(lua.state, 0, 0, |_| {
-                    error_traceback(thread_state);
-                    0
-                })?;
+                if lua.resume_traceback() {
+                    protect_lua_closure(lua.state, 0, 0, |_| {
+                        error_traceback(thread_state);
+                        0
+                    })?;
+                }
                 return Err(pop_error(thread_state, ret));
             }
 
@@ -121,6 +139,102 @@ impl<'lua> Thread<'lua> {
         R::from_lua_multi(results, lua)
     }
 
+    /// Limits the number of Lua VM instructions this thread may execute per call to [`resume`].
+    ///
+    /// Once the limit is reached, the running code is interrupted with a Lua error carrying the
+    /// message "instruction limit exceeded", and the thread is left in the
+    /// [`ThreadStatus::Error`] state. The count starts over every time the thread is resumed.
+    /// Passing `None` removes the limit.
+    ///
+    /// Not available with LuaJIT, where debug hooks are shared by all threads of a state.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Thread, ThreadStatus};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let thread: Thread = lua.load("coroutine.create(function() while true do end end)").eval()?;
+    /// thread.set_instruction_limit(Some(10_000))?;
+    ///
+    /// assert!(thread.resume::<_, ()>(()).is_err());
+    /// assert_eq!(thread.status(), ThreadStatus::Error);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`resume`]: #method.resume
+    /// [`ThreadStatus::Error`]: enum.ThreadStatus.html#variant.Error
+    #[cfg(not(feature = "luajit"))]
+    pub fn set_instruction_limit(&self, limit: Option<u64>) -> Result<()> {
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 6);
+
+            lua.push_ref(&self.0);
+            let thread_state = ffi::lua_tothread(lua.state, -1);
+
+            protect_lua_closure(lua.state, 1, 0, |state| {
+                push_instruction_limits(state);
+                ffi::lua_pushvalue(state, -2);
+                match limit {
+                    Some(limit) => {
+                        let budget =
+                            ffi::lua_newuserdata(state, mem::size_of::<InstructionBudget>())
+                                as *mut InstructionBudget;
+                        ptr::write(
+                            budget,
+                            InstructionBudget {
+                                limit,
+                                remaining: limit,
+                            },
+                        );
+                    }
+                    None => ffi::lua_pushnil(state),
+                }
+                ffi::lua_rawset(state, -3);
+            })?;
+
+            // Keep recording coverage if it is running
+            let line_mask = ffi::lua_gethookmask(thread_state) & ffi::LUA_MASKLINE;
+            match limit {
+                Some(limit) => ffi::lua_sethook(
+                    thread_state,
+                    instruction_limit_hook,
+                    ffi::LUA_MASKCOUNT | line_mask,
+                    budget_hook_count(limit),
+                ),
+                None if has_interrupt_handles(lua.state) => ffi::lua_sethook(
+                    thread_state,
+                    debug_hook,
+                    ffi::LUA_MASKCOUNT | line_mask,
+                    INTERRUPT_HOOK_INSTRUCTIONS,
+                ),
+                None => ffi::lua_sethook(thread_state, debug_hook, line_mask, 0),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if this thread was created by `lua`, or by a `Lua` sharing its main state.
+    ///
+    /// Passing handles to a different Lua state fails with [`Error::MismatchedLua`].
+    ///
+    /// [`Error::MismatchedLua`]: enum.Error.html#variant.MismatchedLua
+    pub fn belongs_to(&self, lua: &Lua) -> bool {
+        self.0.lua.shares_state(lua)
+    }
+
+    /// Returns the address of the underlying Lua thread.
+    ///
+    /// Handles to the same coroutine return the same pointer, so it can be used as a stable id.
+    /// The pointer is only meaningful while the thread is alive, since Lua may reuse the address
+    /// after the thread is collected, and it must never be dereferenced.
+    pub fn to_pointer(&self) -> *const c_void {
+        self.0.to_pointer()
+    }
+
     /// Gets the status of the thread.
     pub fn status(&self) -> ThreadStatus {
         let lua = self.0.lua;
@@ -149,3 +263,187 @@ impl<'lua> PartialEq for Thread<'lua> {
         self.0 == other.0
     }
 }
+
+impl<'lua> Eq for Thread<'lua> {}
+
+impl<'lua> Hash for Thread<'lua> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.to_pointer().hash(state);
+    }
+}
+
+/// Values to yield from a Rust callback, created by [`Lua::yield_with`].
+///
+/// The coroutine is only suspended when the callback returns the `Yield` as its result.
+///
+/// [`Lua::yield_with`]: struct.Lua.html#method.yield_with
+#[derive(Debug)]
+#[must_use = "the coroutine only yields if the callback returns the `Yield`"]
+pub struct Yield<'lua>(MultiValue<'lua>);
+
+impl<'lua> Yield<'lua> {
+    pub(crate) fn new(values: MultiValue<'lua>) -> Yield<'lua> {
+        Yield(values)
+    }
+}
+
+// Marks the results of a callback which returned a `Yield`. Its address is private to this
+// module, so no other value can be mistaken for it.
+static YIELD_MARKER: u8 = 0;
+
+impl<'lua> ToLuaMulti<'lua> for Yield<'lua> {
+    fn to_lua_multi(self, _: &'lua Lua) -> Result<MultiValue<'lua>> {
+        let mut values = self.0;
+        values.push_front(Value::LightUserData(LightUserData(
+            &YIELD_MARKER as *const u8 as *mut c_void,
+        )));
+        Ok(values)
+    }
+}
+
+// Removes the marker left by a `Yield` from the results of a callback, returning whether the
+// callback should yield them.
+pub(crate) fn take_yield_marker(results: &mut MultiValue) -> bool {
+    match results.get(0) {
+        Some(Value::LightUserData(ud)) if ud.0 == &YIELD_MARKER as *const u8 as *mut c_void => {
+            results.pop_front();
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(not(feature = "luajit"))]
+static INSTRUCTION_LIMITS_REGISTRY_KEY: u8 = 0;
+
+// Instructions left to a thread with a limit, stored in a weak-keyed registry table under
+// `INSTRUCTION_LIMITS_REGISTRY_KEY`.
+#[cfg(not(feature = "luajit"))]
+#[derive(Clone, Copy)]
+struct InstructionBudget {
+    limit: u64,
+    remaining: u64,
+}
+
+// Pushes the table of instruction budgets, creating it on first use. Uses 4 stack spaces and
+// must be called in a protected context.
+#[cfg(not(feature = "luajit"))]
+unsafe fn push_instruction_limits(state: *mut ffi::lua_State) {
+    ffi::lua_pushlightuserdata(
+        state,
+        &INSTRUCTION_LIMITS_REGISTRY_KEY as *const u8 as *mut c_void,
+    );
+    ffi::lua_rawget(state, ffi::LUA_REGISTRYINDEX);
+    if ffi::lua_istable(state, -1) != 0 {
+        return;
+    }
+    ffi::lua_pop(state, 1);
+
+    ffi::lua_newtable(state);
+    ffi::lua_newtable(state);
+    ffi::lua_pushstring(state, cstr!("__mode"));
+    ffi::lua_pushstring(state, cstr!("k"));
+    ffi::lua_rawset(state, -3);
+    ffi::lua_setmetatable(state, -2);
+
+    ffi::lua_pushlightuserdata(
+        state,
+        &INSTRUCTION_LIMITS_REGISTRY_KEY as *const u8 as *mut c_void,
+    );
+    ffi::lua_pushvalue(state, -2);
+    ffi::lua_rawset(state, ffi::LUA_REGISTRYINDEX);
+}
+
+// Returns the budget of the thread on top of the stack, which is left there.
+#[cfg(not(feature = "luajit"))]
+unsafe fn get_instruction_budget(state: *mut ffi::lua_State) -> Option<*mut InstructionBudget> {
+    ffi::lua_pushlightuserdata(
+        state,
+        &INSTRUCTION_LIMITS_REGISTRY_KEY as *const u8 as *mut c_void,
+    );
+    ffi::lua_rawget(state, ffi::LUA_REGISTRYINDEX);
+    let budget = if ffi::lua_istable(state, -1) != 0 {
+        ffi::lua_pushvalue(state, -2);
+        ffi::lua_rawget(state, -2);
+        let budget = ffi::lua_touserdata(state, -1) as *mut InstructionBudget;
+        ffi::lua_pop(state, 1);
+        budget
+    } else {
+        ptr::null_mut()
+    };
+    ffi::lua_pop(state, 1);
+    if budget.is_null() {
+        None
+    } else {
+        Some(budget)
+    }
+}
+
+// The limit hook runs at least as often as the interrupt hook, so that limited threads can be
+// interrupted too.
+#[cfg(not(feature = "luajit"))]
+fn budget_hook_count(remaining: u64) -> c_int {
+    cmp::max(cmp::min(remaining, INTERRUPT_HOOK_INSTRUCTIONS as u64), 1) as c_int
+}
+
+// Called before every resume with the thread on top of `state`. Starts the instruction budget
+// over, and installs the interrupt hook in threads created before the first interrupt handle.
+#[cfg(not(feature = "luajit"))]
+unsafe fn prepare_hook(state: *mut ffi::lua_State, thread_state: *mut ffi::lua_State) {
+    let mask = ffi::lua_gethookmask(thread_state);
+    if let Some(budget) = get_instruction_budget(state) {
+        (*budget).remaining = (*budget).limit;
+        ffi::lua_sethook(
+            thread_state,
+            instruction_limit_hook,
+            mask | ffi::LUA_MASKCOUNT,
+            budget_hook_count((*budget).limit),
+        );
+    } else if mask & ffi::LUA_MASKCOUNT == 0 && has_interrupt_handles(state) {
+        ffi::lua_sethook(
+            thread_state,
+            debug_hook,
+            mask | ffi::LUA_MASKCOUNT,
+            INTERRUPT_HOOK_INSTRUCTIONS,
+        );
+    }
+}
+
+#[cfg(not(feature = "luajit"))]
+extern "C" fn instruction_limit_hook(state: *mut ffi::lua_State, ar: *mut ffi::lua_Debug) {
+    unsafe {
+        if (*ar).event == ffi::LUA_HOOKLINE {
+            return record_coverage(state, ar);
+        }
+        let interruptible = check_interrupt(state);
+
+        // Coroutines created by a limited thread inherit its hook, but not its budget
+        ffi::lua_pushthread(state);
+        let budget = get_instruction_budget(state);
+        ffi::lua_pop(state, 1);
+        let budget = match budget {
+            Some(budget) => &mut *budget,
+            None => {
+                if !interruptible {
+                    let mask = ffi::lua_gethookmask(state) & !ffi::LUA_MASKCOUNT;
+                    ffi::lua_sethook(state, instruction_limit_hook, mask, 0);
+                }
+                return;
+            }
+        };
+
+        let count = ffi::lua_gethookcount(state) as u64;
+        budget.remaining = budget.remaining.saturating_sub(count);
+        if budget.remaining == 0 {
+            ffi::lua_pushstring(state, cstr!("instruction limit exceeded"));
+            ffi::lua_error(state);
+        }
+        let mask = ffi::lua_gethookmask(state);
+        ffi::lua_sethook(
+            state,
+            instruction_limit_hook,
+            mask,
+            budget_hook_count(budget.remaining),
+        );
+    }
+}
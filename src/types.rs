@@ -6,17 +6,89 @@ use crate::error::Result;
 use crate::ffi;
 use crate::lua::Lua;
 use crate::util::{assert_stack, StackGuard};
-use crate::value::MultiValue;
+use crate::value::{MultiValue, Value};
 
 /// Type of Lua integer numbers.
 pub type Integer = ffi::lua_Integer;
 /// Type of Lua floating point numbers.
 pub type Number = ffi::lua_Number;
 
+/// A Lua number which keeps track of whether it is an integer or a float.
+///
+/// Converting a Lua value into `TypedNumber` tells whether a script wrote `3` or `3.0`. Only Lua
+/// 5.3 has an integer subtype, so with older versions (and LuaJIT) every number is a `Float`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TypedNumber {
+    /// A Lua integer.
+    Integer(Integer),
+    /// A Lua floating point number.
+    Float(Number),
+}
+
 /// A "light" userdata value. Equivalent to an unmanaged raw pointer.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct LightUserData(pub *mut c_void);
 
+/// A light userdata holding a pointer to a `T`.
+///
+/// Lua treats it exactly like a [`LightUserData`]: the type parameter only documents what the
+/// pointer points to on the Rust side, and is not checked when converting from Lua. Like a raw
+/// pointer, it does not own or keep alive the value it points to.
+///
+/// [`LightUserData`]: struct.LightUserData.html
+pub struct TypedLightUserData<T>(*mut T);
+
+impl<T> TypedLightUserData<T> {
+    /// Wraps a raw pointer.
+    pub fn from_ptr(ptr: *mut T) -> Self {
+        TypedLightUserData(ptr)
+    }
+
+    /// Returns the wrapped pointer.
+    pub fn as_ptr(self) -> *mut T {
+        self.0
+    }
+
+    /// Returns a reference to the pointed-to value, or `None` if the pointer is null.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be null or point to a valid `T` which outlives `'a`, and no mutable
+    /// reference to it may exist during `'a`. Since Lua code can pass any light userdata in place
+    /// of another, this also means the value must really have been created from a pointer to `T`.
+    pub unsafe fn as_ref<'a>(self) -> Option<&'a T> {
+        self.0.as_ref()
+    }
+}
+
+impl<T> Clone for TypedLightUserData<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TypedLightUserData<T> {}
+
+impl<T> PartialEq for TypedLightUserData<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T> Eq for TypedLightUserData<T> {}
+
+impl<T> fmt::Debug for TypedLightUserData<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("TypedLightUserData").field(&self.0).finish()
+    }
+}
+
+impl<T> From<TypedLightUserData<T>> for LightUserData {
+    fn from(ud: TypedLightUserData<T>) -> Self {
+        LightUserData(ud.0 as *mut c_void)
+    }
+}
+
 pub(crate) type Callback<'lua, 'a> =
     Box<dyn Fn(&'lua Lua, MultiValue<'lua>) -> Result<MultiValue<'lua>> + 'a>;
 
@@ -32,14 +104,14 @@ pub(crate) type Callback<'lua, 'a> =
 /// Be warned, If you place this into Lua via a `UserData` type or a rust callback, it is *very
 /// easy* to accidentally cause reference cycles that the Lua garbage collector cannot resolve.
 /// Instead of placing a `RegistryKey` into a `UserData` type, prefer instead to use
-/// [`UserData::set_user_value`] / [`UserData::get_user_value`], and instead of moving a RegistryKey
-/// into a callback, prefer [`Lua::scope`].
+/// [`AnyUserData::set_user_value`] / [`AnyUserData::get_user_value`], and instead of moving a
+/// RegistryKey into a callback, prefer [`Lua::scope`].
 ///
 /// [`Lua::remove_registry_value`]: struct.Lua.html#method.remove_registry_value
 /// [`Lua::expire_registry_values`]: struct.Lua.html#method.expire_registry_values
 /// [`Lua::scope`]: struct.Lua.html#method.scope
-/// [`UserData::set_user_value`]: struct.UserData.html#method.set_user_value
-/// [`UserData::get_user_value`]: struct.UserData.html#method.get_user_value
+/// [`AnyUserData::set_user_value`]: struct.AnyUserData.html#method.set_user_value
+/// [`AnyUserData::get_user_value`]: struct.AnyUserData.html#method.get_user_value
 pub struct RegistryKey {
     pub(crate) registry_id: c_int,
     pub(crate) unref_list: Arc<Mutex<Option<Vec<c_int>>>>,
@@ -60,6 +132,20 @@ impl Drop for RegistryKey {
 }
 
 impl RegistryKey {
+    /// Returns the value this key refers to in the registry of `lua`.
+    ///
+    /// This is equivalent to [`Lua::registry_value`] returning a [`Value`]. Since
+    /// [`Lua::remove_registry_value`] consumes the key, a `RegistryKey` always refers to a value
+    /// which is still in the registry. Returns `Error::MismatchedRegistryKey` if the key was not
+    /// created by a `Lua` sharing the main state with `lua`.
+    ///
+    /// [`Lua::registry_value`]: struct.Lua.html#method.registry_value
+    /// [`Lua::remove_registry_value`]: struct.Lua.html#method.remove_registry_value
+    /// [`Value`]: enum.Value.html
+    pub fn get<'lua>(&self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        lua.registry_value(self)
+    }
+
     // Destroys the RegistryKey without adding to the drop list
     pub(crate) fn take(self) -> c_int {
         let registry_id = self.registry_id;
@@ -71,11 +157,50 @@ impl RegistryKey {
     }
 }
 
+/// A Lua value detached from the lifetime of the `Lua` borrow, created by [`Value::into_owned`].
+///
+/// This is a thin wrapper around a [`RegistryKey`]: the value is kept alive in the registry until
+/// the `OwnedValue` is dropped, which releases the registry slot the same way dropping a
+/// `RegistryKey` does. Being `'static`, it can be stored in Rust state which outlives any
+/// particular borrow of `Lua`.
+///
+/// The same caveat as for `RegistryKey` applies: storing an `OwnedValue` inside a value it
+/// (indirectly) refers to, such as a `UserData` reachable from the owned table, creates a
+/// reference cycle which the Lua garbage collector cannot resolve.
+///
+/// [`Value::into_owned`]: enum.Value.html#method.into_owned
+/// [`RegistryKey`]: struct.RegistryKey.html
+#[derive(Debug)]
+pub struct OwnedValue(pub(crate) RegistryKey);
+
+impl OwnedValue {
+    /// Returns a handle to the stored value.
+    ///
+    /// Returns `Error::MismatchedRegistryKey` if `lua` does not share the main state with the
+    /// `Lua` which created this value.
+    pub fn to_value<'lua>(&self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        self.0.get(lua)
+    }
+}
+
 pub(crate) struct LuaRef<'lua> {
     pub(crate) lua: &'lua Lua,
     pub(crate) index: c_int,
 }
 
+impl<'lua> LuaRef<'lua> {
+    // Returns the address of the referenced object, only meaningful for identifying it.
+    pub(crate) fn to_pointer(&self) -> *const c_void {
+        let lua = self.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 1);
+            lua.push_ref(self);
+            ffi::lua_topointer(lua.state, -1)
+        }
+    }
+}
+
 impl<'lua> fmt::Debug for LuaRef<'lua> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Ref({})", self.index)
@@ -97,6 +222,9 @@ impl<'lua> Drop for LuaRef<'lua> {
 impl<'lua> PartialEq for LuaRef<'lua> {
     fn eq(&self, other: &Self) -> bool {
         let lua = self.lua;
+        if !lua.shares_state(other.lua) {
+            return false;
+        }
         unsafe {
             let _sg = StackGuard::new(lua.state);
             assert_stack(lua.state, 2);
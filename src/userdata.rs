@@ -1,19 +1,27 @@
+use std::any::TypeId;
 use std::cell::{Ref, RefCell, RefMut};
 
 use crate::error::{Error, Result};
 use crate::ffi;
 use crate::function::Function;
 use crate::lua::Lua;
-use crate::table::Table;
+use crate::table::{Table, TablePairs};
 use crate::types::LuaRef;
 use crate::util::{assert_stack, get_userdata, StackGuard};
-use crate::value::{FromLua, FromLuaMulti, ToLua, ToLuaMulti};
+use crate::value::{FromLua, FromLuaMulti, Nil, ToLua, ToLuaMulti, Value};
+use crate::weak::Weak;
 
 /// Kinds of metamethods that can be overridden.
 ///
 /// Currently, this mechanism does not allow overriding the `__gc` metamethod, since there is
 /// generally no need to do so: [`UserData`] implementors can instead just implement `Drop`.
 ///
+/// Only metamethods which are called as functions by the enabled Lua version have a variant, so
+/// that a misspelled or unsupported name can not be registered by mistake. Metatable fields which
+/// hold plain values are not covered: `__metatable` is always set by mlua to protect the
+/// metatable from scripts, and `__name` and `__mode` are not functions. `__close` requires
+/// Lua 5.4, which is not supported.
+///
 /// [`UserData`]: trait.UserData.html
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum MetaMethod {
@@ -148,6 +156,57 @@ pub trait UserDataMethods<'lua, T: UserData> {
         R: ToLuaMulti<'lua>,
         M: 'static + Send + FnMut(&'lua Lua, &mut T, A) -> Result<R>;
 
+    /// Add a regular method which accepts a `T` by value as the first parameter.
+    ///
+    /// The method receives a clone of the userdata value, so the userdata it was called on stays
+    /// valid and unchanged. This is unlike [`add_method`], which borrows the value, and
+    /// [`add_method_mut`], which mutates it in place. Returning a `T` from the method wraps it in
+    /// a new userdata, which is handy for builder-style APIs:
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// #[derive(Clone)]
+    /// struct Config {
+    ///     width: u32,
+    ///     height: u32,
+    /// }
+    ///
+    /// impl UserData for Config {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_method_consume("with_width", |_, mut this, width: u32| {
+    ///             this.width = width;
+    ///             Ok(this)
+    ///         });
+    ///         methods.add_method_consume("with_height", |_, mut this, height: u32| {
+    ///             this.height = height;
+    ///             Ok(this)
+    ///         });
+    ///         methods.add_method("area", |_, this, ()| Ok(this.width * this.height));
+    ///     }
+    /// }
+    ///
+    /// lua.globals().set("config", Config { width: 1, height: 1 })?;
+    /// let area: u32 = lua.load("config:with_width(4):with_height(5):area()").eval()?;
+    /// assert_eq!(area, 20);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`add_method`]: #method.add_method
+    /// [`add_method_mut`]: #method.add_method_mut
+    fn add_method_consume<S, A, R, M>(&mut self, name: &S, method: M)
+    where
+        T: Clone,
+        S: ?Sized + AsRef<[u8]>,
+        A: FromLuaMulti<'lua>,
+        R: ToLuaMulti<'lua>,
+        M: 'static + Send + Fn(&'lua Lua, T, A) -> Result<R>,
+    {
+        self.add_method(name, move |lua, this, args| method(lua, this.clone(), args))
+    }
+
     /// Add a regular method as a function which accepts generic arguments, the first argument will
     /// be a `UserData` of type T if the method is called with Lua method syntax:
     /// `my_userdata:my_method(arg1, arg2)`, or it is passed in as the first argument:
@@ -332,7 +391,11 @@ impl<'lua> AnyUserData<'lua> {
     /// Returns a `UserDataBorrowError` if the userdata is already mutably borrowed. Returns a
     /// `UserDataTypeMismatch` if the userdata is not of type `T`.
     pub fn borrow<T: 'static + UserData>(&self) -> Result<Ref<T>> {
-        self.inspect(|cell| Ok(cell.try_borrow().map_err(|_| Error::UserDataBorrowError)?))
+        self.inspect(|cell| {
+            Ok(cell
+                .try_borrow()
+                .map_err(|_| Error::UserDataBorrowError { method: None })?)
+        })
     }
 
     /// Borrow this userdata mutably if it is of type `T`.
@@ -345,15 +408,69 @@ impl<'lua> AnyUserData<'lua> {
         self.inspect(|cell| {
             Ok(cell
                 .try_borrow_mut()
-                .map_err(|_| Error::UserDataBorrowMutError)?)
+                .map_err(|_| Error::UserDataBorrowMutError { method: None })?)
         })
     }
 
+    /// Replaces the wrapped value of this userdata if it is of type `T`.
+    ///
+    /// The userdata keeps its identity, metatable and associated value, so every existing
+    /// reference to it sees the new value. The previous value is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `UserDataBorrowMutError` if the userdata is currently borrowed. Returns a
+    /// `UserDataTypeMismatch` if the userdata is not of type `T`.
+    pub fn set_data<T: 'static + UserData>(&self, data: T) -> Result<()> {
+        *self.borrow_mut::<T>()? = data;
+        Ok(())
+    }
+
+    /// Returns the `TypeId` of the Rust type wrapped by this userdata.
+    ///
+    /// This allows dispatching on the type of a userdata without trying to [`borrow`] it as every
+    /// candidate type in turn. Returns `None` for userdata created with
+    /// [`Scope::create_nonstatic_userdata`], whose types are not necessarily `'static`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{AnyUserData, Lua, Result, UserData};
+    /// # use std::any::TypeId;
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Circle;
+    /// impl UserData for Circle {}
+    ///
+    /// let ud: AnyUserData = lua.create_userdata(Circle)?;
+    /// assert_eq!(ud.type_id(), Some(TypeId::of::<Circle>()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`borrow`]: #method.borrow
+    /// [`Scope::create_nonstatic_userdata`]: struct.Scope.html#method.create_nonstatic_userdata
+    pub fn type_id(&self) -> Option<TypeId> {
+        let lua = self.0.lua;
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 1);
+
+            lua.push_ref(&self.0);
+            lua.get_userdata_type_id(-1)
+        }
+    }
+
     /// Sets an associated value to this `AnyUserData`.
     ///
     /// The value may be any Lua value whatsoever, and can be retrieved with [`get_user_value`].
     ///
+    /// The associated value is owned by the userdata on the Lua side and is traced by the garbage
+    /// collector, so unlike a [`RegistryKey`] stored inside of the Rust value, a table (or any other
+    /// value) which refers back to this userdata does not form an uncollectable reference cycle.
+    ///
     /// [`get_user_value`]: #method.get_user_value
+    /// [`RegistryKey`]: struct.RegistryKey.html
     pub fn set_user_value<V: ToLua<'lua>>(&self, v: V) -> Result<()> {
         let lua = self.0.lua;
         #[cfg(any(feature = "lua52", feature = "lua51", feature = "luajit"))]
@@ -393,7 +510,84 @@ impl<'lua> AnyUserData<'lua> {
         V::from_lua(res, lua)
     }
 
-    fn get_metatable(&self) -> Result<Table<'lua>> {
+    /// Associates a value with this `AnyUserData`.
+    ///
+    /// This is the same as [`set_user_value`]. Storing the associated value here instead of keeping
+    /// a [`RegistryKey`] inside of the Rust value avoids an uncollectable reference cycle when the
+    /// value refers back to this userdata.
+    ///
+    /// [`set_user_value`]: #method.set_user_value
+    /// [`RegistryKey`]: struct.RegistryKey.html
+    pub fn set_associated<V: ToLua<'lua>>(&self, v: V) -> Result<()> {
+        self.set_user_value(v)
+    }
+
+    /// Returns the value associated by [`set_associated`].
+    ///
+    /// This is the same as [`get_user_value`].
+    ///
+    /// [`set_associated`]: #method.set_associated
+    /// [`get_user_value`]: #method.get_user_value
+    pub fn get_associated<V: FromLua<'lua>>(&self) -> Result<V> {
+        self.get_user_value()
+    }
+
+    /// Returns the associated value set by [`set_user_value`] and replaces it with `nil`.
+    ///
+    /// If the value cannot be converted to `V`, an error is returned and the associated value is
+    /// left in place.
+    ///
+    /// [`set_user_value`]: #method.set_user_value
+    pub fn take_user_value<V: FromLua<'lua>>(&self) -> Result<V> {
+        let value = self.get_user_value()?;
+        self.clear_user_value()?;
+        Ok(value)
+    }
+
+    /// Removes the associated value set by [`set_user_value`].
+    ///
+    /// The userdata no longer keeps the previous value alive, so it can be collected before the
+    /// userdata itself (if it is not referenced elsewhere).
+    ///
+    /// [`set_user_value`]: #method.set_user_value
+    pub fn clear_user_value(&self) -> Result<()> {
+        self.set_user_value(Nil)
+    }
+
+    /// Returns an iterator over the pairs of an associated table set by [`set_user_value`].
+    ///
+    /// Returns a `FromLuaConversionError` if the associated value is not a table. Otherwise this
+    /// works like [`Table::pairs`] on that table.
+    ///
+    /// [`set_user_value`]: #method.set_user_value
+    /// [`Table::pairs`]: struct.Table.html#method.pairs
+    pub fn user_value_pairs<K, V>(&self) -> Result<TablePairs<'lua, K, V>>
+    where
+        K: FromLua<'lua>,
+        V: FromLua<'lua>,
+    {
+        Ok(self.get_user_value::<Table>()?.pairs())
+    }
+
+    /// Returns `true` if this userdata was created by `lua`, or by a `Lua` sharing its main state.
+    ///
+    /// Passing handles to a different Lua state fails with [`Error::MismatchedLua`].
+    ///
+    /// [`Error::MismatchedLua`]: enum.Error.html#variant.MismatchedLua
+    pub fn belongs_to(&self, lua: &Lua) -> bool {
+        self.0.lua.shares_state(lua)
+    }
+
+    /// Creates a weak reference to this userdata.
+    ///
+    /// The weak reference does not keep the userdata alive. Refer to [`Weak`] for details.
+    ///
+    /// [`Weak`]: struct.Weak.html
+    pub fn downgrade(&self) -> Result<Weak<'lua, AnyUserData<'lua>>> {
+        Weak::new(self.0.lua, Value::UserData(self.clone()))
+    }
+
+    pub(crate) fn get_metatable(&self) -> Result<Table<'lua>> {
         unsafe {
             let lua = self.0.lua;
             let _sg = StackGuard::new(lua.state);
@@ -377,6 +377,21 @@ where
             ffi::lua_remove(state, 1);
             r
         }
+        Ok(Err(Error::RuntimeValue { key, .. })) => {
+            ffi::lua_settop(state, 0);
+            ffi::lua_rawgeti(
+                state,
+                ffi::LUA_REGISTRYINDEX,
+                key.registry_id as ffi::lua_Integer,
+            );
+            // The value is on the stack now, so free its registry slot right away unless the
+            // error was cloned and the key is still shared. Nothing that implements Drop may be
+            // left on the Rust stack when raising the error.
+            if let Ok(key) = Arc::try_unwrap(key) {
+                ffi::luaL_unref(state, ffi::LUA_REGISTRYINDEX, key.take());
+            }
+            ffi::lua_error(state)
+        }
         Ok(Err(err)) => {
             ffi::lua_settop(state, 1);
             ptr::write(ud as *mut WrappedError, WrappedError(err));
@@ -1,5 +1,7 @@
 use std::iter::{self, FromIterator};
-use std::{slice, str, vec};
+use std::os::raw::c_void;
+use std::string::String as StdString;
+use std::{mem, slice, str, vec};
 
 use crate::error::{Error, Result};
 use crate::ffi;
@@ -8,7 +10,7 @@ use crate::lua::Lua;
 use crate::string::String;
 use crate::table::Table;
 use crate::thread::Thread;
-use crate::types::{Integer, LightUserData, Number};
+use crate::types::{Integer, LightUserData, Number, OwnedValue};
 use crate::userdata::AnyUserData;
 
 /// A dynamically typed Lua value.  The `String`, `Table`, `Function`, `Thread`, and `UserData`
@@ -63,6 +65,136 @@ impl<'lua> Value<'lua> {
         }
     }
 
+    /// Detaches the value from the `Lua` borrow by storing it in the registry.
+    ///
+    /// The returned [`OwnedValue`] is `'static` and can be turned back into a `Value` with
+    /// [`OwnedValue::to_value`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, OwnedValue, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Handler {
+    ///     callback: OwnedValue,
+    /// }
+    ///
+    /// let value: Value = lua.load("function(x) return x * 2 end").eval()?;
+    /// let handler = Handler { callback: value.into_owned(&lua)? };
+    ///
+    /// match handler.callback.to_value(&lua)? {
+    ///     Value::Function(f) => assert_eq!(f.call::<_, i64>(21)?, 42),
+    ///     _ => unreachable!(),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`OwnedValue`]: struct.OwnedValue.html
+    /// [`OwnedValue::to_value`]: struct.OwnedValue.html#method.to_value
+    pub fn into_owned(self, lua: &'lua Lua) -> Result<OwnedValue> {
+        Ok(OwnedValue(lua.create_registry_value(self)?))
+    }
+
+    /// Renders the value in a human-readable, Lua-literal-like form for debugging.
+    ///
+    /// Tables are rendered as `{ [1] = 1, key = "value" }`, recursing into nested tables up to
+    /// `max_depth` levels deep; tables beyond that are shown as `{ ... }`. A table which contains
+    /// itself (directly or indirectly) is shown as `<cycle table: 0x...>` where it reappears.
+    /// Functions, threads and userdata are shown by their type and address. No metamethods are
+    /// invoked, and table entries appear in iteration order.
+    ///
+    /// This is meant for logging and diagnostics only; the format is not stable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let value: Value = lua.load("{ { 1, 'two' } }").eval()?;
+    /// assert_eq!(value.to_debug_string(2), r#"{ [1] = { [1] = 1, [2] = "two" } }"#);
+    /// assert_eq!(value.to_debug_string(1), "{ [1] = { ... } }");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_debug_string(&self, max_depth: usize) -> StdString {
+        let mut out = StdString::new();
+        write_debug(&mut out, self, max_depth, &mut Vec::new());
+        out
+    }
+
+    /// Returns `true` if the value can be called like a function.
+    ///
+    /// This is the case for functions, and for tables and userdata whose metatable has a `__call`
+    /// field. Nothing is called, and the `__metatable` field is ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let callable: Value = lua.load("setmetatable({}, { __call = function() end })").eval()?;
+    /// let plain: Value = lua.load("{}").eval()?;
+    /// assert!(callable.is_callable());
+    /// assert!(!plain.is_callable());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_callable(&self) -> bool {
+        let metatable = match self {
+            Value::Function(_) => return true,
+            Value::Table(t) => t.get_metatable(),
+            Value::UserData(ud) => ud.get_metatable().ok(),
+            _ => None,
+        };
+        match metatable {
+            Some(mt) => match mt.raw_get::<_, Value>("__call") {
+                Ok(Value::Nil) | Err(_) => false,
+                Ok(_) => true,
+            },
+            None => false,
+        }
+    }
+
+    /// Converts an `Integer` value into the equivalent `Number`, leaving other values unchanged.
+    ///
+    /// Pushing the result to Lua 5.3 stores a float even if it is integral, so for example
+    /// `math.type` reports `float` for it. To convert a Rust number directly, use
+    /// [`TypedNumber::Float`].
+    ///
+    /// [`TypedNumber::Float`]: enum.TypedNumber.html#variant.Float
+    pub fn force_float(self) -> Value<'lua> {
+        match self {
+            Value::Integer(i) => Value::Number(i as Number),
+            v => v,
+        }
+    }
+
+    /// Converts a `Number` value into the equivalent `Integer`, leaving other values unchanged.
+    ///
+    /// Returns an error if the number has a fractional part or is out of range for an `Integer`.
+    pub fn force_integer(self) -> Result<Value<'lua>> {
+        match self {
+            Value::Number(n) => {
+                // The upper bound is exclusive, as `Integer::MAX` is not representable as a float
+                if n.fract() == 0.0 && n >= Integer::MIN as Number && n < -(Integer::MIN as Number)
+                {
+                    Ok(Value::Integer(n as Integer))
+                } else {
+                    Err(Error::FromLuaConversionError {
+                        from: "number",
+                        to: "integer",
+                        message: Some(format!("{} has no integer representation", n)),
+                    })
+                }
+            }
+            v => Ok(v),
+        }
+    }
+
     /// Compares two values for equality.
     ///
     /// Equality comparisons do not convert strings to numbers or vice versa.
@@ -82,6 +214,81 @@ impl<'lua> Value<'lua> {
     }
 }
 
+// Appends the debug representation of `value` to `out`. `visiting` holds the addresses of the
+// tables currently being rendered further up, to detect cycles.
+fn write_debug(
+    out: &mut StdString,
+    value: &Value,
+    depth: usize,
+    visiting: &mut Vec<*const c_void>,
+) {
+    match value {
+        Value::Nil => out.push_str("nil"),
+        Value::Boolean(b) => out.push_str(&b.to_string()),
+        Value::LightUserData(ud) => out.push_str(&format!("<lightuserdata {:?}>", ud.0)),
+        Value::Integer(i) => out.push_str(&i.to_string()),
+        Value::Number(n) => out.push_str(&n.to_string()),
+        Value::String(s) => {
+            out.push_str(&format!("{:?}", StdString::from_utf8_lossy(s.as_bytes())))
+        }
+        Value::Table(t) => {
+            let ptr = t.0.to_pointer();
+            if visiting.contains(&ptr) {
+                out.push_str(&format!("<cycle table: {:?}>", ptr));
+                return;
+            }
+            if depth == 0 {
+                out.push_str("{ ... }");
+                return;
+            }
+
+            visiting.push(ptr);
+            let mut first = true;
+            out.push('{');
+            for pair in t.clone().pairs::<Value, Value>() {
+                out.push_str(if first { " " } else { ", " });
+                first = false;
+                match pair {
+                    Ok((key, value)) => {
+                        match key {
+                            Value::String(ref s) if is_identifier(s.as_bytes()) => {
+                                out.push_str(&StdString::from_utf8_lossy(s.as_bytes()))
+                            }
+                            ref key => {
+                                out.push('[');
+                                write_debug(out, key, depth - 1, visiting);
+                                out.push(']');
+                            }
+                        }
+                        out.push_str(" = ");
+                        write_debug(out, &value, depth - 1, visiting);
+                    }
+                    Err(err) => {
+                        out.push_str(&format!("<error: {}>", err));
+                        break;
+                    }
+                }
+            }
+            out.push_str(if first { "}" } else { " }" });
+            visiting.pop();
+        }
+        Value::Function(f) => out.push_str(&format!("<function {:?}>", f.0.to_pointer())),
+        Value::Thread(t) => out.push_str(&format!("<thread {:?}>", t.0.to_pointer())),
+        Value::UserData(ud) => out.push_str(&format!("<userdata {:?}>", ud.0.to_pointer())),
+        Value::Error(err) => out.push_str(&format!("<error: {}>", err)),
+    }
+}
+
+// Checks whether a table key can be written without brackets.
+fn is_identifier(s: &[u8]) -> bool {
+    match s.first() {
+        Some(c) if c.is_ascii_alphabetic() || *c == b'_' => {
+            s.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'_')
+        }
+        _ => false,
+    }
+}
+
 impl<'lua> PartialEq for Value<'lua> {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -163,17 +370,23 @@ impl<'a, 'lua> IntoIterator for &'a MultiValue<'lua> {
 }
 
 impl<'lua> MultiValue<'lua> {
+    /// Creates a `MultiValue` from a `Vec` of values, the first element becoming the first value.
     pub fn from_vec(mut v: Vec<Value<'lua>>) -> MultiValue<'lua> {
         v.reverse();
         MultiValue(v)
     }
 
+    /// Consumes the `MultiValue`, returning its values in order.
     pub fn into_vec(self) -> Vec<Value<'lua>> {
         let mut v = self.0;
         v.reverse();
         v
     }
 
+    pub(crate) fn clear(&mut self) {
+        self.0.clear();
+    }
+
     pub(crate) fn reserve(&mut self, size: usize) {
         self.0.reserve(size);
     }
@@ -186,14 +399,32 @@ impl<'lua> MultiValue<'lua> {
         self.0.pop()
     }
 
+    /// Returns the number of values.
     pub fn len(&self) -> usize {
         self.0.len()
     }
 
+    /// Returns true if there are no values.
     pub fn is_empty(&self) -> bool {
         self.0.len() == 0
     }
 
+    /// Returns the value at position `index` (starting from 0), or `None` if out of bounds.
+    ///
+    /// Together with [`iter`], this allows inspecting arguments positionally without consuming the
+    /// `MultiValue`.
+    ///
+    /// [`iter`]: #method.iter
+    pub fn get(&self, index: usize) -> Option<&Value<'lua>> {
+        let len = self.0.len();
+        if index < len {
+            self.0.get(len - 1 - index)
+        } else {
+            None
+        }
+    }
+
+    /// Returns an iterator over references to the values, in order.
     pub fn iter(&self) -> iter::Rev<slice::Iter<Value<'lua>>> {
         self.0.iter().rev()
     }
@@ -220,4 +451,29 @@ pub trait FromLuaMulti<'lua>: Sized {
     /// assigning values. Similarly, if not enough values are given, conversions should assume that
     /// any missing values are nil.
     fn from_lua_multi(values: MultiValue<'lua>, lua: &'lua Lua) -> Result<Self>;
+
+    /// Performs the conversion, taking values from the front of `values`.
+    ///
+    /// This allows the caller to reuse the allocation of `values`. The default implementation
+    /// takes all of the values and calls `from_lua_multi`.
+    #[doc(hidden)]
+    fn from_lua_multi_buf(values: &mut MultiValue<'lua>, lua: &'lua Lua) -> Result<Self> {
+        Self::from_lua_multi(mem::replace(values, MultiValue::new()), lua)
+    }
+
+    /// Performs the conversion of callback arguments, where the first value is argument number
+    /// `pos` (counting from 0) and `names` are the names of all arguments.
+    ///
+    /// Conversion errors for a named argument should mention its name. The default implementation
+    /// calls `from_lua_multi`.
+    #[doc(hidden)]
+    fn from_lua_args(
+        values: MultiValue<'lua>,
+        pos: usize,
+        names: &[StdString],
+        lua: &'lua Lua,
+    ) -> Result<Self> {
+        let _ = (pos, names);
+        Self::from_lua_multi(values, lua)
+    }
 }
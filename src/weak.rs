@@ -0,0 +1,126 @@
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+
+use crate::error::Result;
+use crate::ffi;
+use crate::lua::Lua;
+use crate::types::Integer;
+use crate::util::{assert_stack, protect_lua_closure, StackGuard};
+use crate::value::{FromLua, Value};
+
+/// A weak reference to a Lua value.
+///
+/// Unlike the strong handles such as [`Table`], a `Weak` does not keep the referenced value alive.
+/// Once the value is no longer referenced elsewhere and has been garbage collected, [`upgrade`]
+/// returns `None`. This makes it possible to keep caches of Lua values on the Rust side without
+/// forming uncollectable reference cycles.
+///
+/// Weak references are created with [`Table::downgrade`], [`Function::downgrade`] and
+/// [`AnyUserData::downgrade`].
+///
+/// [`Table`]: struct.Table.html
+/// [`upgrade`]: #method.upgrade
+/// [`Table::downgrade`]: struct.Table.html#method.downgrade
+/// [`Function::downgrade`]: struct.Function.html#method.downgrade
+/// [`AnyUserData::downgrade`]: struct.AnyUserData.html#method.downgrade
+pub struct Weak<'lua, T> {
+    lua: &'lua Lua,
+    id: Integer,
+    _phantom: PhantomData<T>,
+}
+
+impl<'lua, T> Weak<'lua, T>
+where
+    T: FromLua<'lua>,
+{
+    pub(crate) fn new(lua: &'lua Lua, value: Value<'lua>) -> Result<Weak<'lua, T>> {
+        unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 4);
+
+            lua.push_value(value)?;
+            // Ids are never reused, so a stale `Weak` can not observe a newer value.
+            let id = protect_lua_closure(lua.state, 1, 0, |state| {
+                push_weak_table(state);
+                ffi::lua_rawgeti(state, -1, 0);
+                let id = ffi::lua_tointeger(state, -1) + 1;
+                ffi::lua_pop(state, 1);
+                ffi::lua_pushinteger(state, id);
+                ffi::lua_rawseti(state, -2, 0);
+                ffi::lua_pushvalue(state, -2);
+                ffi::lua_rawseti(state, -2, id);
+                id
+            })?;
+
+            Ok(Weak {
+                lua,
+                id,
+                _phantom: PhantomData,
+            })
+        }
+    }
+
+    /// Returns a strong handle to the referenced value, or `None` if it has been collected.
+    pub fn upgrade(&self) -> Option<T> {
+        let lua = self.lua;
+        let value = unsafe {
+            let _sg = StackGuard::new(lua.state);
+            assert_stack(lua.state, 2);
+
+            get_weak_table(lua.state);
+            ffi::lua_rawgeti(lua.state, -1, self.id);
+            lua.pop_value()
+        };
+
+        match value {
+            Value::Nil => None,
+            value => T::from_lua(value, lua).ok(),
+        }
+    }
+}
+
+impl<'lua, T> Drop for Weak<'lua, T> {
+    fn drop(&mut self) {
+        unsafe {
+            let state = self.lua.state;
+            let _sg = StackGuard::new(state);
+            assert_stack(state, 3);
+
+            get_weak_table(state);
+            // Clearing an existing entry never allocates, and a collected entry is already nil.
+            if ffi::lua_rawgeti(state, -1, self.id) != ffi::LUA_TNIL {
+                ffi::lua_pushnil(state);
+                ffi::lua_rawseti(state, -3, self.id);
+            }
+        }
+    }
+}
+
+// Pushes the table of weak references onto the stack. It must already exist.
+unsafe fn get_weak_table(state: *mut ffi::lua_State) {
+    ffi::lua_pushlightuserdata(state, &WEAK_REFS_REGISTRY_KEY as *const u8 as *mut c_void);
+    ffi::lua_rawget(state, ffi::LUA_REGISTRYINDEX);
+}
+
+// Pushes the table of weak references onto the stack, creating it if needed. Must be called
+// inside `protect_lua_closure`, as it may allocate.
+unsafe fn push_weak_table(state: *mut ffi::lua_State) {
+    get_weak_table(state);
+    if ffi::lua_isnil(state, -1) == 0 {
+        return;
+    }
+    ffi::lua_pop(state, 1);
+
+    ffi::lua_newtable(state);
+    ffi::lua_newtable(state);
+    ffi::lua_pushstring(state, cstr!("__mode"));
+    ffi::lua_pushstring(state, cstr!("v"));
+    ffi::lua_rawset(state, -3);
+    ffi::lua_setmetatable(state, -2);
+
+    ffi::lua_pushlightuserdata(state, &WEAK_REFS_REGISTRY_KEY as *const u8 as *mut c_void);
+    ffi::lua_pushvalue(state, -2);
+    ffi::lua_rawset(state, ffi::LUA_REGISTRYINDEX);
+}
+
+static WEAK_REFS_REGISTRY_KEY: u8 = 0;
@@ -1,5 +1,5 @@
 use bstr::{BStr, BString};
-use mlua::{Lua, Result};
+use mlua::{Error, Lua, Result, Value};
 
 #[test]
 fn byte_string_round_trip() -> Result<()> {
@@ -94,3 +94,18 @@ fn byte_string_round_trip() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn byte_string_no_coercion() -> Result<()> {
+    let lua = Lua::new();
+
+    match lua.unpack::<BString>(lua.pack(42)?) {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected conversion error, got {:?}", r),
+    }
+
+    let s = lua.coerce_string(lua.pack(42)?)?.unwrap();
+    assert_eq!(lua.unpack::<BString>(Value::String(s))?, "42");
+
+    Ok(())
+}
@@ -0,0 +1,17 @@
+use std::cell::Cell;
+
+use mlua::{Lua, Result};
+
+fn main() {
+    let lua = Lua::new();
+    lua.scope(|outer| -> Result<()> {
+        outer.scope(|_inner| -> Result<()> {
+            let local = Cell::new(0);
+            let f = outer.create_function(|_, ()| {
+                local.set(1);
+                Ok(())
+            })?;
+            f.call::<_, ()>(())
+        })
+    });
+}
@@ -0,0 +1,7 @@
+use mlua::Lua;
+
+fn main() {
+    let lua = Lua::new();
+    let scope = lua.scope(|scope| scope);
+    let _ = scope;
+}
@@ -0,0 +1,11 @@
+use mlua::{UserData, UserDataMethods};
+
+struct MyUserData;
+
+impl UserData for MyUserData {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method("__eqq", |_, _this, ()| Ok(true));
+    }
+}
+
+fn main() {}
@@ -1,4 +1,9 @@
-use mlua::{Function, Lua, Result, String};
+use std::os::raw::c_int;
+
+use mlua::{
+    lua_State, lua_upvalueindex, Error, Function, Lua, MultiValue, Nil, Result, String, Thread,
+    ThreadStatus, Value, Variadic,
+};
 
 #[test]
 fn test_function() -> Result<()> {
@@ -75,3 +80,357 @@ fn test_rust_function() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_optional_args() -> Result<()> {
+    let lua = Lua::new();
+
+    let describe = lua.create_function(|_, (a, b): (i64, Option<i64>)| {
+        Ok(match b {
+            Some(b) => format!("{} {}", a, b),
+            None => format!("{} none", a),
+        })
+    })?;
+    lua.globals().set("describe", describe.clone())?;
+
+    // Absent trailing argument
+    assert_eq!(describe.call::<_, String>(1)?, "1 none");
+    assert_eq!(lua.load("describe(1)").eval::<String>()?, "1 none");
+    // Explicit nil
+    assert_eq!(describe.call::<_, String>((1, Nil))?, "1 none");
+    assert_eq!(lua.load("describe(1, nil)").eval::<String>()?, "1 none");
+    // Present
+    assert_eq!(describe.call::<_, String>((1, 2))?, "1 2");
+    assert_eq!(lua.load("describe(1, 2)").eval::<String>()?, "1 2");
+
+    // A present value of the wrong type is still an error
+    assert!(lua.load("describe(1, {})").exec().is_err());
+    // A missing required argument is an error
+    assert!(lua.load("describe()").exec().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_c_function() -> Result<()> {
+    extern "C" {
+        fn lua_pushvalue(state: *mut lua_State, index: c_int);
+    }
+
+    unsafe extern "C" fn first_upvalue(state: *mut lua_State) -> c_int {
+        lua_pushvalue(state, lua_upvalueindex(1));
+        1
+    }
+
+    let lua = Lua::new();
+
+    let f = unsafe {
+        lua.create_c_function(first_upvalue, &[Value::Integer(42), Value::Boolean(true)])?
+    };
+    assert_eq!(f.call::<_, i64>(())?, 42);
+
+    lua.globals().set("first_upvalue", f)?;
+    assert_eq!(lua.load("first_upvalue()").eval::<i64>()?, 42);
+
+    let too_many = vec![Nil; 256];
+    assert!(unsafe { lua.create_c_function(first_upvalue, &too_many) }.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_call_into() -> Result<()> {
+    let lua = Lua::new();
+
+    let func: Function = lua.load("function(...) return 'first', ... end").eval()?;
+    let mut buf = MultiValue::new();
+
+    let first: String = func.call_into((1, 2, 3), &mut buf)?;
+    assert_eq!(first.to_str()?, "first");
+    assert!(buf.is_empty());
+
+    let (first, rest): (String, Variadic<i64>) = func.call_into((1, 2, 3), &mut buf)?;
+    assert_eq!(first.to_str()?, "first");
+    assert_eq!(*rest, vec![1, 2, 3]);
+    assert!(buf.is_empty());
+
+    let all: MultiValue = func.call_into((), &mut buf)?;
+    assert_eq!(all.len(), 1);
+
+    let err: Function = lua.load("function() error('boom') end").eval()?;
+    assert!(err.call_into::<_, ()>((), &mut buf).is_err());
+    assert!(buf.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_call_slice() -> Result<()> {
+    let lua = Lua::new();
+
+    let concat: Function = lua
+        .load("function(...) return table.concat({...}, ','), select('#', ...) end")
+        .eval()?;
+
+    let args = vec![
+        Value::Integer(1),
+        Value::Number(2.5),
+        Value::String(lua.create_string("x")?),
+    ];
+    let (joined, count): (String, usize) = concat.call_slice(&args)?;
+    assert_eq!(joined, "1,2.5,x");
+    assert_eq!(count, 3);
+    let (joined, count): (String, usize) = concat.call_slice(&[])?;
+    assert_eq!(joined, "");
+    assert_eq!(count, 0);
+
+    let (joined, _): (String, Value) = concat.call_multi(MultiValue::from_vec(args))?;
+    assert_eq!(joined, "1,2.5,x");
+
+    match concat.call_slice::<(i64, usize)>(&[Value::Boolean(true)]) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    match concat.call_slice::<(Function, usize)>(&[]) {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_call_in_env() -> Result<()> {
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals.set("name", "global")?;
+
+    let get_name: Function = lua.load("function() return name end").eval()?;
+    let set_name: Function = lua.load("function(v) name = v end").eval()?;
+
+    let env1 = lua.create_table()?;
+    env1.set("name", "env1")?;
+    let env2 = lua.create_table()?;
+
+    assert_eq!(get_name.call_in_env::<_, String>(env1.clone(), ())?, "env1");
+    set_name.call_in_env::<_, ()>(env2.clone(), "env2")?;
+    assert_eq!(env2.get::<_, String>("name")?, "env2");
+    assert_eq!(get_name.call::<_, String>(())?, "global");
+    assert_eq!(globals.get::<_, String>("name")?, "global");
+
+    // The environment is restored after errors
+    env1.set("error", globals.get::<_, Function>("error")?)?;
+    let fail: Function = lua.load("function() error(name) end").eval()?;
+    match fail.call_in_env::<_, ()>(env1.clone(), ()) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("env1")),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    match fail.call::<_, ()>(()) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("global")),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    // Functions without an environment are called as they are
+    let rust_fn = lua.create_function(|_, x: i64| Ok(x * 2))?;
+    assert_eq!(rust_fn.call_in_env::<_, i64>(env1, 21)?, 42);
+
+    Ok(())
+}
+
+#[test]
+fn test_function_named_args() -> Result<()> {
+    let lua = Lua::new();
+
+    let f = lua.create_function_named(
+        &["name", "count"],
+        |_, (name, count, rest): (String, i64, Variadic<i64>)| {
+            Ok(format!("{}:{}:{}", name.to_str()?, count, rest.len()))
+        },
+    )?;
+    assert_eq!(f.call::<_, String>(("a", 1, 2, 3))?, "a:1:2");
+
+    let check = |res: Result<String>, expected: Option<&str>| match res {
+        Err(Error::CallbackError { cause, .. }) => match *cause {
+            Error::FromLuaConversionError { ref message, .. } => {
+                assert_eq!(message.as_ref().map(|m| m.as_str()), expected)
+            }
+            ref err => panic!("expected FromLuaConversionError, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    };
+    check(
+        f.call(("a", "x")),
+        Some("bad argument #2 'count': expected number or string coercible to number"),
+    );
+    check(
+        f.call((true, 1)),
+        Some("bad argument #1 'name': expected string or number"),
+    );
+    // Unnamed arguments are reported as before
+    check(
+        f.call(("a", 1, "x")),
+        Some("expected number or string coercible to number"),
+    );
+
+    // A single argument
+    let g = lua.create_function_named(&["limit"], |_, limit: i64| Ok(limit))?;
+    assert_eq!(g.call::<_, i64>(5)?, 5);
+    match g.call::<_, i64>(lua.create_table()?) {
+        Err(Error::CallbackError { cause, .. }) => {
+            assert!(cause.to_string().contains("bad argument #1 'limit'"))
+        }
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_callback_depth_limit() -> Result<()> {
+    let lua = Lua::new();
+
+    let recurse = lua.create_function(|lua, n: u32| {
+        if n == 0 {
+            return Ok(0);
+        }
+        let recurse: Function = lua.globals().get("recurse")?;
+        Ok(recurse.call::<_, u32>(n - 1)? + 1)
+    })?;
+    lua.globals().set("recurse", recurse.clone())?;
+
+    lua.set_max_callback_depth(10);
+    assert_eq!(recurse.call::<_, u32>(9)?, 9);
+
+    let err = recurse.call::<_, u32>(10).unwrap_err();
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(&err);
+    let mut found = false;
+    while let Some(err) = source {
+        if let Some(Error::RecursionLimit) = err.downcast_ref::<Error>() {
+            found = true;
+        }
+        source = err.source();
+    }
+    assert!(found, "expected RecursionLimit in {:?}", err);
+
+    // The depth is restored after errors
+    assert_eq!(recurse.call::<_, u32>(9)?, 9);
+
+    Ok(())
+}
+
+#[test]
+fn test_function_from_callable() -> Result<()> {
+    let lua = Lua::new();
+
+    let func: Value = lua.load("function(a) return a + 1 end").eval()?;
+    assert_eq!(Function::from_callable(func)?.call::<_, i64>(1)?, 2);
+
+    let callable: Value = lua
+        .load(
+            r#"
+            setmetatable({ base = 10 }, {
+                __call = function(self, a, b) return self.base + a, b end
+            })
+            "#,
+        )
+        .eval()?;
+    let wrapped = Function::from_callable(callable)?;
+    let (sum, s) = wrapped.call::<_, (i64, String)>((5, "x"))?;
+    assert_eq!(sum, 15);
+    assert_eq!(s, "x");
+
+    // Every call wraps its own value
+    let other: Value = lua
+        .load("setmetatable({}, { __call = function() return 'other' end })")
+        .eval()?;
+    let other = Function::from_callable(other)?;
+    assert_ne!(other, wrapped);
+    assert_eq!(other.call::<_, String>(())?, "other");
+    assert_eq!(wrapped.call::<_, i64>((1, 2))?, 11);
+
+    match Function::from_callable(lua.load("{}").eval()?) {
+        Err(Error::FromLuaConversionError { from: "table", .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+    match Function::from_callable(Value::Integer(1)) {
+        Err(Error::FromLuaConversionError {
+            from: "integer", ..
+        }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_call_no_yield() -> Result<()> {
+    let lua = Lua::new();
+
+    fn check_yield_error<T: std::fmt::Debug>(res: Result<T>) {
+        match res {
+            Err(Error::RuntimeError(msg)) => {
+                assert_eq!(msg, "attempt to yield across a non-yieldable boundary")
+            }
+            r => panic!("expected yield error, got {:?}", r),
+        }
+    }
+
+    // `yield_with` is rejected inside the callback, keeping its traceback
+    fn check_callback_yield_error<T: std::fmt::Debug>(res: Result<T>) {
+        match res {
+            Err(Error::CallbackError { cause, traceback }) => {
+                check_yield_error::<()>(Err((*cause).clone()));
+                assert!(traceback.contains("stack traceback"));
+            }
+            r => panic!("expected callback error, got {:?}", r),
+        }
+    }
+
+    let add: Function = lua.load("function(a, b) return a + b end").eval()?;
+    assert_eq!(add.call_no_yield::<_, i64>((1, 2))?, 3);
+
+    let fail: Function = lua.load("function() error('boom') end").eval()?;
+    match fail.call_no_yield::<_, ()>(()) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("boom")),
+        r => panic!("expected runtime error, got {:?}", r),
+    }
+
+    let yielding: Function = lua.load("function() coroutine.yield(1) end").eval()?;
+    check_yield_error(yielding.call_no_yield::<_, ()>(()));
+
+    // The function runs on a coroutine of its own
+    let running: Function = lua
+        .load("function() return (coroutine.running()) end")
+        .eval()?;
+    let thread = running.call_no_yield::<_, Thread>(())?;
+    assert_eq!(thread.status(), ThreadStatus::Unresumable);
+
+    // From within a callback running in a coroutine
+    let rust_yield = lua.create_function(|lua, ()| lua.yield_with(1))?;
+    lua.globals().set("yielding", yielding)?;
+    lua.globals().set("rust_yield", rust_yield)?;
+    let checker = lua.create_function(|lua, name: std::string::String| {
+        let f: Function = lua.globals().get(name.as_str())?;
+        let res = f.call_no_yield::<_, ()>(());
+        match name.as_str() {
+            "rust_yield" => check_callback_yield_error(res),
+            _ => check_yield_error(res),
+        }
+        Ok(())
+    })?;
+    lua.globals().set("check", checker)?;
+    let thread = lua.create_thread(lua.load("function(name) check(name) end").eval()?)?;
+    thread.resume::<_, ()>("yielding")?;
+
+    // LuaJIT aborts on errors raised from Rust callbacks
+    #[cfg(not(feature = "luajit"))]
+    {
+        let rust_yield: Function = lua.globals().get("rust_yield")?;
+        check_callback_yield_error(rust_yield.call_no_yield::<_, ()>(()));
+
+        let thread = lua.create_thread(lua.load("function(name) check(name) end").eval()?)?;
+        thread.resume::<_, ()>("rust_yield")?;
+    }
+
+    Ok(())
+}
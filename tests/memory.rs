@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use mlua::{Lua, Result, UserData};
+use mlua::{Lua, Result, Table, UserData};
 
 #[test]
 fn test_gc_control() -> Result<()> {
@@ -58,3 +58,103 @@ fn test_gc_error() {
         Ok(()) => panic!("__gc error did not result in error"),
     }
 }
+
+#[test]
+fn test_weak_references() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua.create_table()?;
+    table.set("key", "value")?;
+    let weak_table = table.downgrade()?;
+    let func = lua.create_function(|_, ()| Ok(42))?;
+    let weak_func = func.downgrade()?;
+
+    lua.gc_collect()?;
+    let upgraded: Table = weak_table.upgrade().expect("table must be alive");
+    assert_eq!(upgraded.get::<_, String>("key")?, "value");
+    assert_eq!(weak_func.upgrade().unwrap().call::<_, i32>(())?, 42);
+
+    drop((table, upgraded, func));
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert!(weak_table.upgrade().is_none());
+    assert!(weak_func.upgrade().is_none());
+
+    // Ids are not reused after a value is collected
+    let other = lua.create_table()?;
+    let weak_other = other.downgrade()?;
+    assert!(weak_table.upgrade().is_none());
+    assert_eq!(weak_other.upgrade(), Some(other));
+
+    Ok(())
+}
+
+#[cfg(not(feature = "luajit"))]
+#[test]
+fn test_custom_allocator() -> Result<()> {
+    use std::alloc::{self, Layout};
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use mlua::{Allocator, Error};
+
+    struct Tracking {
+        used: Arc<AtomicUsize>,
+        limit: usize,
+    }
+
+    fn layout(size: usize) -> Layout {
+        Layout::from_size_align(size, 16).unwrap()
+    }
+
+    unsafe impl Allocator for Tracking {
+        fn alloc(&mut self, size: usize) -> *mut u8 {
+            if self.used.load(Ordering::SeqCst) + size > self.limit {
+                return ptr::null_mut();
+            }
+            self.used.fetch_add(size, Ordering::SeqCst);
+            unsafe { alloc::alloc(layout(size)) }
+        }
+
+        fn realloc(&mut self, ptr: *mut u8, old_size: usize, new_size: usize) -> *mut u8 {
+            let used = self.used.load(Ordering::SeqCst);
+            if new_size > old_size && used + new_size - old_size > self.limit {
+                return ptr::null_mut();
+            }
+            self.used
+                .store(used + new_size - old_size, Ordering::SeqCst);
+            unsafe { alloc::realloc(ptr, layout(old_size), new_size) }
+        }
+
+        fn free(&mut self, ptr: *mut u8, size: usize) {
+            self.used.fetch_sub(size, Ordering::SeqCst);
+            unsafe { alloc::dealloc(ptr, layout(size)) }
+        }
+    }
+
+    let used = Arc::new(AtomicUsize::new(0));
+    let lua = Lua::new_with_allocator(Tracking {
+        used: used.clone(),
+        limit: 1024 * 1024,
+    });
+    assert!(used.load(Ordering::SeqCst) > 0);
+
+    let before = used.load(Ordering::SeqCst);
+    lua.load("big = {} for i = 1, 1000 do big[i] = i end")
+        .exec()?;
+    assert!(used.load(Ordering::SeqCst) > before);
+    assert_eq!(lua.load("#big").eval::<i64>()?, 1000);
+
+    match lua
+        .load("local t = {} for i = 1, 1e7 do t[i] = i end")
+        .exec()
+    {
+        Err(Error::MemoryError(_)) => {}
+        r => panic!("expected MemoryError, got {:?}", r),
+    }
+
+    drop(lua);
+    assert_eq!(used.load(Ordering::SeqCst), 0);
+
+    Ok(())
+}
@@ -229,3 +229,84 @@ fn scope_userdata_mismatch() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn scope_return_value() -> Result<()> {
+    struct Counter<'a>(&'a Cell<i64>);
+
+    impl<'a> UserData for Counter<'a> {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("inc", |_, data, n: i64| {
+                data.0.set(data.0.get() + n);
+                Ok(data.0.get())
+            });
+        }
+    }
+
+    let lua = Lua::new();
+
+    let c = Cell::new(0);
+    let sum: i64 = lua.scope(|scope| {
+        lua.globals()
+            .set("counter", scope.create_nonstatic_userdata(Counter(&c))?)?;
+        lua.load("counter:inc(1) + counter:inc(2) + counter:inc(3)")
+            .eval()
+    })?;
+    assert_eq!(sum, 1 + 3 + 6);
+    assert_eq!(c.get(), 6);
+
+    let s: std::string::String = lua.scope(|scope| -> Result<_> {
+        let f = scope.create_function(|_, s: String| Ok(s.to_str()?.to_uppercase()))?;
+        f.call("hello")
+    })?;
+    assert_eq!(s, "HELLO");
+
+    Ok(())
+}
+
+#[test]
+fn scope_nested() -> Result<()> {
+    let lua = Lua::new();
+
+    let outer_rc = Rc::new(Cell::new(0));
+    lua.scope(|outer| {
+        let r = outer_rc.clone();
+        let f = outer.create_function(move |_, ()| {
+            r.set(r.get() + 1);
+            Ok(())
+        })?;
+        lua.globals().set("outer", f)?;
+
+        // Inner callbacks may borrow locals of the outer scope callback
+        let inner_count = Cell::new(0);
+        let inner_rc = Rc::new(());
+        outer.scope(|inner| -> Result<()> {
+            let r = inner_rc.clone();
+            let count = &inner_count;
+            let g = inner.create_function(move |_, ()| {
+                let _ = &r;
+                count.set(count.get() + 1);
+                Ok(())
+            })?;
+            lua.globals().set("inner", g)?;
+            lua.load("outer() inner() inner()").exec()?;
+            assert_eq!(Rc::strong_count(&inner_rc), 2);
+            Ok(())
+        })?;
+        assert_eq!(inner_count.get(), 2);
+        // The inner scope has been dropped, but the outer one is still alive
+        assert_eq!(Rc::strong_count(&inner_rc), 1);
+        assert_eq!(Rc::strong_count(&outer_rc), 2);
+
+        match lua.load("inner()").exec() {
+            Err(Error::CallbackError { .. }) => {}
+            r => panic!("improper return for destructed function: {:?}", r),
+        };
+        lua.load("outer()").exec()
+    })?;
+
+    assert_eq!(outer_rc.get(), 2);
+    assert_eq!(Rc::strong_count(&outer_rc), 1);
+
+    Ok(())
+}
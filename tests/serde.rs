@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use mlua::{Error, Lua, Result, Value};
+use serde::Deserialize;
+
+#[test]
+fn test_from_value_struct() -> Result<()> {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Config {
+        name: String,
+        port: u16,
+        ratio: f64,
+        enabled: bool,
+        tags: Vec<String>,
+        limits: HashMap<String, i64>,
+        parent: Option<String>,
+    }
+
+    let lua = Lua::new();
+    let value: Value = lua
+        .load(
+            r#"
+            {
+                name = "server",
+                port = 8080,
+                ratio = 0.5,
+                enabled = true,
+                tags = { "a", "b" },
+                limits = { cpu = 2, mem = 512 },
+                unknown = "ignored",
+            }
+        "#,
+        )
+        .eval()?;
+
+    let config: Config = lua.from_value(value)?;
+    assert_eq!(config.name, "server");
+    assert_eq!(config.port, 8080);
+    assert_eq!(config.ratio, 0.5);
+    assert!(config.enabled);
+    assert_eq!(config.tags, vec!["a", "b"]);
+    assert_eq!(config.limits["cpu"], 2);
+    assert_eq!(config.limits["mem"], 512);
+    assert_eq!(config.parent, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_from_value_enum() -> Result<()> {
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Point(i64, i64),
+        Rect { w: i64, h: i64 },
+    }
+
+    let lua = Lua::new();
+
+    let shape: Shape = lua.from_value(lua.load(r#""Empty""#).eval()?)?;
+    assert_eq!(shape, Shape::Empty);
+
+    let shape: Shape = lua.from_value(lua.load("{ Circle = 1.5 }").eval()?)?;
+    assert_eq!(shape, Shape::Circle(1.5));
+
+    let shape: Shape = lua.from_value(lua.load("{ Point = { 1, 2 } }").eval()?)?;
+    assert_eq!(shape, Shape::Point(1, 2));
+
+    let shape: Shape = lua.from_value(lua.load("{ Rect = { w = 3, h = 4 } }").eval()?)?;
+    assert_eq!(shape, Shape::Rect { w: 3, h: 4 });
+
+    match lua.from_value::<Shape>(lua.load(r#""Triangle""#).eval()?) {
+        Err(Error::DeserializeError(_)) => {}
+        r => panic!("expected deserialize error, got {:?}", r),
+    }
+    match lua.from_value::<Shape>(lua.load("{ Circle = 1, Empty = true }").eval()?) {
+        Err(Error::DeserializeError(_)) => {}
+        r => panic!("expected deserialize error, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_from_value_deny_unknown_fields() -> Result<()> {
+    #[derive(Deserialize, Debug)]
+    #[serde(deny_unknown_fields)]
+    struct Strict {
+        #[allow(dead_code)]
+        a: i64,
+    }
+
+    let lua = Lua::new();
+
+    lua.from_value::<Strict>(lua.load("{ a = 1 }").eval()?)?;
+    match lua.from_value::<Strict>(lua.load("{ a = 1, b = 2 }").eval()?) {
+        Err(Error::DeserializeError(_)) => {}
+        r => panic!("expected deserialize error, got {:?}", r),
+    }
+
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use std::borrow::Cow;
+use std::sync::Arc;
 
-use mlua::{Lua, Result, String};
+use mlua::{Error, Lua, Result, String, Table, Value};
 
 #[test]
 fn compare() {
@@ -63,3 +64,82 @@ fn raw_string() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn string_conversions() -> Result<()> {
+    let lua = Lua::new();
+    let globals = lua.globals();
+
+    let bytes: &[u8] = &[0xff, 0x00, 0xfe, b'a', 0x80];
+    globals.set("bytes", bytes)?;
+    assert_eq!(globals.get::<_, String>("bytes")?.as_bytes(), bytes);
+    assert_eq!(lua.load("#bytes").eval::<i64>()?, 5);
+
+    globals.set("borrowed", Cow::Borrowed("borrowed"))?;
+    globals.set("owned", Cow::<str>::Owned("owned".to_owned()))?;
+    globals.set("shared", Arc::<str>::from("shared"))?;
+    assert_eq!(globals.get::<_, String>("borrowed")?, "borrowed");
+    assert_eq!(globals.get::<_, String>("owned")?, "owned");
+    assert_eq!(globals.get::<_, String>("shared")?, "shared");
+
+    Ok(())
+}
+
+#[test]
+fn test_tostring() -> Result<()> {
+    let lua = Lua::new();
+
+    let check = |lua: &Lua, expr: &str| -> Result<()> {
+        let value: Value = lua.load(expr).eval()?;
+        let expected: String = lua.load(&format!("tostring({})", expr)).eval()?;
+        assert_eq!(lua.tostring(value)?, expected.to_str()?);
+        Ok(())
+    };
+    for expr in &[
+        "1", "1.5", "1/3", "2^53", "0.1", "1e100", "'text'", "nil", "false",
+    ] {
+        check(&lua, expr)?;
+    }
+
+    let value: Table = lua
+        .load("setmetatable({}, { __tostring = function() return 'custom' end })")
+        .eval()?;
+    assert_eq!(lua.tostring(value)?, "custom");
+
+    let failing: Table = lua
+        .load("setmetatable({}, { __tostring = function() error('no') end })")
+        .eval()?;
+    match lua.tostring(failing) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_tostring_bytes() -> Result<()> {
+    let lua = Lua::new();
+
+    assert_eq!(lua.tostring_bytes(42)?, b"42");
+    assert_eq!(lua.tostring_bytes(&b"\xfe\x00\xff"[..])?, b"\xfe\x00\xff");
+
+    let value: Table = lua
+        .load(r#"setmetatable({}, { __tostring = function() return "\255\254" end })"#)
+        .eval()?;
+    assert!(lua.tostring(value.clone())?.to_str().is_err());
+    assert_eq!(lua.tostring_bytes(value)?, b"\xff\xfe");
+
+    #[cfg(not(feature = "luajit"))]
+    {
+        let failing: Table = lua
+            .load("setmetatable({}, { __tostring = function() error('no') end })")
+            .eval()?;
+        match lua.tostring_bytes(failing) {
+            Err(Error::RuntimeError(_)) => {}
+            r => panic!("expected RuntimeError, got {:?}", r),
+        }
+    }
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use mlua::{Lua, Nil, Result, Table, Value};
+use mlua::{Error, Lua, Nil, Result, Table, Value};
 
 #[test]
 fn test_set_get() -> Result<()> {
@@ -226,3 +226,598 @@ fn test_table_error() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_table_filter_map() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua.load("{ 1, 2, 3, 4, 5, 6 }").eval()?;
+    let evens = table.filter_map(|k: i64, v: i64| {
+        Ok(if v % 2 == 0 {
+            Some((k / 2, v * 10))
+        } else {
+            None
+        })
+    })?;
+    assert_eq!(
+        evens.sequence_values::<i64>().collect::<Result<Vec<_>>>()?,
+        vec![20, 40, 60]
+    );
+
+    let mixed: Table = lua.load(r#"{ 1, 2, "three" }"#).eval()?;
+    assert!(mixed.filter_map(|k: i64, v: i64| Ok(Some((k, v)))).is_err());
+    assert!(table
+        .filter_map(|_: i64, _: i64| -> Result<Option<(i64, i64)>> {
+            Err(Error::RuntimeError("abort".to_owned()))
+        })
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_table_append_sequence() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua.load("{ 1, 2 }").eval()?;
+    let other: Table = lua.load("{ 3, 4, 5, key = 'ignored' }").eval()?;
+    table.append_sequence(&other)?;
+    assert_eq!(
+        table
+            .clone()
+            .sequence_values::<i64>()
+            .collect::<Result<Vec<_>>>()?,
+        vec![1, 2, 3, 4, 5]
+    );
+    assert!(!table.contains_key("key")?);
+    assert_eq!(other.raw_len(), 3);
+
+    // Appending a table to itself duplicates its sequence once
+    table.append_sequence(&table)?;
+    assert_eq!(table.raw_len(), 10);
+    assert_eq!(table.raw_get::<_, i64>(10)?, 5);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_pairs_invalid_key() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua.load("{ key = 1 }").eval()?;
+    let mut pairs = table.clone().pairs::<String, i64>();
+    let (key, _) = pairs.next().unwrap()?;
+
+    // Remove the current key and force a rehash, so `next` can no longer find it
+    table.set(key, Nil)?;
+    for i in 1..100 {
+        table.set(format!("new_key_{}", i), i)?;
+    }
+
+    match pairs.next() {
+        Some(Err(Error::RuntimeError(_))) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    assert!(pairs.next().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_create_table_from_duplicates() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua.create_table_from(vec![("a", 1), ("b", 2), ("a", 3), ("c", 4), ("b", 5)])?;
+    assert_eq!(table.get::<_, i64>("a")?, 3);
+    assert_eq!(table.get::<_, i64>("b")?, 5);
+    assert_eq!(table.get::<_, i64>("c")?, 4);
+    assert_eq!(table.pairs::<String, i64>().count(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_is_sequence() -> Result<()> {
+    let lua = Lua::new();
+
+    let is_sequence =
+        |code: &str| -> Result<bool> { lua.load(code).eval::<Table>()?.is_sequence() };
+    assert!(is_sequence("{}")?);
+    assert!(is_sequence("{ 1, 2, 3 }")?);
+    assert!(is_sequence("{ [1] = 'a', [2] = 'b' }")?);
+    assert!(!is_sequence("{ 1, 2, 3, key = 4 }")?);
+    assert!(!is_sequence("{ [1] = 1, [2] = 2, [4] = 4 }")?);
+    assert!(!is_sequence("{ [2] = 2 }")?);
+    assert!(!is_sequence("{ [0] = 0, 1 }")?);
+    assert!(!is_sequence("{ 1, [1.5] = 2 }")?);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_call_opt() -> Result<()> {
+    let lua = Lua::new();
+
+    let object: Table = lua
+        .load(
+            r#"
+        {
+            value = 10,
+            not_a_function = 1,
+            add = function(self, x) return self.value + x end,
+            fail = function() error("fail") end,
+        }
+    "#,
+        )
+        .eval()?;
+
+    assert_eq!(object.call_opt::<_, _, i64>("add", 5)?, Some(15));
+    assert_eq!(object.call_opt::<_, _, i64>("missing", 5)?, None);
+    assert_eq!(object.call_opt::<_, _, i64>("not_a_function", 5)?, None);
+    assert!(object.call_opt::<_, _, ()>("fail", ()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_table_remove() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua.load("{ 1, 2, 3, key = 'value' }").eval()?;
+    table.remove("key")?;
+    table.remove(2)?;
+    assert!(!table.contains_key("key")?);
+    assert_eq!(table.get::<_, Value>(2)?, Nil);
+    assert_eq!(table.get::<_, i64>(3)?, 3);
+
+    // `remove` honors `__newindex` for missing keys, while `raw_remove` does not
+    let proxied: Table = lua
+        .load(
+            r#"
+        local removed = {}
+        _G.removed = removed
+        return setmetatable({ 1, 2, 3 }, {
+            __newindex = function(t, k, v) removed[#removed + 1] = k end,
+        })
+    "#,
+        )
+        .eval()?;
+    proxied.remove("missing")?;
+    proxied.raw_remove("missing_raw")?;
+    let removed: Table = lua.globals().get("removed")?;
+    assert_eq!(removed.raw_len(), 1);
+    assert_eq!(removed.get::<_, std::string::String>(1)?, "missing");
+
+    proxied.raw_remove(1)?;
+    assert_eq!(proxied.raw_len(), 2);
+    assert_eq!(proxied.raw_get::<_, i64>(1)?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_deep_equals() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.load(
+        r#"
+        f = function() end
+        t1 = { 1, 2, a = { x = "x", y = { true } }, f = f }
+        t2 = { a = { y = { true }, x = "x" }, f = f, 1, 2 }
+        t3 = { 1, 2, a = { x = "x", y = { false } }, f = f }
+        t4 = { 1, 2, a = { x = "x", y = { true } }, f = function() end }
+        t5 = { 1, 2, a = { x = "x", y = { true } }, f = f, extra = 0 }
+
+        c1 = { name = "c" }
+        c1.self = c1
+        c2 = { name = "c" }
+        c2.self = c2
+        "#,
+    )
+    .exec()?;
+
+    let globals = lua.globals();
+    let t1: Table = globals.get("t1")?;
+    assert!(t1.deep_equals(&t1)?);
+    assert!(t1.deep_equals(globals.get::<_, Table>("t2")?)?);
+    assert!(!t1.deep_equals(globals.get::<_, Table>("t3")?)?);
+    assert!(!t1.deep_equals(globals.get::<_, Table>("t4")?)?);
+    assert!(!t1.deep_equals(globals.get::<_, Table>("t5")?)?);
+    assert!(!globals.get::<_, Table>("t5")?.deep_equals(&t1)?);
+
+    let c1: Table = globals.get("c1")?;
+    assert!(c1.deep_equals(globals.get::<_, Table>("c2")?)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_index_handlers() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua
+        .load(r#"setmetatable({}, { __len = function() return 42 end })"#)
+        .eval()?;
+    table.set_index_handler(
+        lua.create_function(|_, (_, key): (Table, String)| Ok(format!("missing {}", key)))?,
+    )?;
+    table.set_newindex_handler(lua.create_function(
+        |_, (table, key, value): (Table, String, i64)| table.raw_set(key, value * 2),
+    )?)?;
+
+    assert_eq!(table.get::<_, String>("a")?, "missing a");
+    table.set("b", 5)?;
+    assert_eq!(table.get::<_, i64>("b")?, 10);
+    // The existing metatable is kept
+    assert_eq!(table.len()?, 42);
+
+    // Replaces the previous handler
+    table.set_index_handler(lua.create_function(|_, (_, _): (Table, Value)| Ok("other"))?)?;
+    assert_eq!(table.get::<_, String>("a")?, "other");
+
+    let plain = lua.create_table()?;
+    plain.set_index_handler(lua.create_function(|_, (_, _): (Table, Value)| Ok(1))?)?;
+    assert_eq!(plain.get::<_, i64>("x")?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_set_all() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua.create_table()?;
+    table.set_all((1..=100).map(|i| (i, i * 2)))?;
+    assert_eq!(table.raw_len(), 100);
+    assert_eq!(table.get::<_, i64>(1)?, 2);
+    assert_eq!(table.get::<_, i64>(100)?, 200);
+
+    // Later pairs win
+    table.set_all(vec![("key", 1), ("key", 2)])?;
+    assert_eq!(table.get::<_, i64>("key")?, 2);
+    table.set_all(Vec::<(Value, Value)>::new())?;
+
+    let proxied: Table = lua
+        .load(
+            r#"
+            setmetatable({}, {
+                __newindex = function(t, k, v) rawset(t, k, v * 10) end
+            })
+            "#,
+        )
+        .eval()?;
+    proxied.set_all(vec![("a", 1), ("b", 2)])?;
+    assert_eq!(proxied.get::<_, i64>("a")?, 10);
+    assert_eq!(proxied.get::<_, i64>("b")?, 20);
+    proxied.raw_set_all(vec![("c", 3)])?;
+    assert_eq!(proxied.get::<_, i64>("c")?, 3);
+
+    match table.raw_set_all(vec![(Value::Nil, 1)]) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_ordered_table() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua.create_ordered_table()?;
+    for (i, key) in ["z", "a", "m", "b", "y"].iter().enumerate() {
+        table.set(*key, i as i64)?;
+    }
+    table.set("a", 10)?;
+    table.set("m", Nil)?;
+    table.set("z", Nil)?;
+    table.set("z", 20)?;
+
+    let pairs = table.ordered_pairs::<String, i64>()?;
+    assert_eq!(
+        pairs,
+        vec![
+            ("a".to_string(), 10),
+            ("b".to_string(), 3),
+            ("y".to_string(), 4),
+            ("z".to_string(), 20)
+        ]
+    );
+    assert_eq!(table.get::<_, i64>("b")?, 3);
+    assert_eq!(table.get::<_, Option<i64>>("m")?, None);
+
+    lua.globals().set("ordered", table)?;
+    #[cfg(any(feature = "lua53", feature = "lua52"))]
+    {
+        let keys: String = lua
+            .load("local s = '' for k in pairs(ordered) do s = s .. k end return s")
+            .eval()?;
+        assert_eq!(keys, "abyz");
+    }
+
+    match lua.create_table()?.ordered_pairs::<Value, Value>() {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    // Ordered tables do not rely on globals which scripts may replace
+    let lua = Lua::new();
+    lua.load("setmetatable, next, type = nil, nil, nil")
+        .exec()?;
+    let table = lua.create_ordered_table()?;
+    table.set("b", 1)?;
+    table.set("a", 2)?;
+    assert_eq!(
+        table.ordered_pairs::<String, i64>()?,
+        vec![("b".to_string(), 1), ("a".to_string(), 2)]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_table_next() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua.create_table()?;
+    assert!(table.next(None::<Value>)?.is_none());
+
+    table.set(1, "one")?;
+    table.set("two", 2)?;
+    table.set(true, false)?;
+
+    let mut seen = Vec::new();
+    let mut key = None;
+    while let Some((k, v)) = table.next(key)? {
+        assert_eq!(table.raw_get::<_, Value>(k.clone())?, v);
+        seen.push(k.clone());
+        key = Some(k);
+    }
+    assert_eq!(seen.len(), 3);
+
+    // Iteration can start again from any key
+    let (k, _) = table.next(None::<Value>)?.unwrap();
+    assert_eq!(table.next(Some(seen[2].clone()))?, None);
+    assert_eq!(table.next(Some(k))?.map(|(k, _)| k), Some(seen[1].clone()));
+
+    match table.next(Some("missing")) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_table_to_vec_numbers() -> Result<()> {
+    let lua = Lua::new();
+
+    let numbers: Table = lua.load("{1, 2.5, '3', -4}").eval()?;
+    assert_eq!(numbers.to_vec_numbers()?, vec![1.0, 2.5, 3.0, -4.0]);
+    match numbers.to_vec_integers() {
+        Err(Error::FromLuaConversionError {
+            to: "integer",
+            message: Some(ref msg),
+            ..
+        }) => assert_eq!(msg, "element 2 is not a valid integer"),
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    let integers: Table = lua.load("{1, 2.0, '3'}").eval()?;
+    assert_eq!(integers.to_vec_integers()?, vec![1, 2, 3]);
+
+    let mixed: Table = lua.load("{1, {}, 3}").eval()?;
+    match mixed.to_vec_numbers() {
+        Err(Error::FromLuaConversionError {
+            from: "table",
+            message: Some(ref msg),
+            ..
+        }) => assert_eq!(msg, "element 2 is not a valid number"),
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    assert!(lua.create_table()?.to_vec_numbers()?.is_empty());
+
+    lua.set_coercion(mlua::Coercion::Strict);
+    assert!(numbers.to_vec_numbers().is_err());
+    let strict: Table = lua.load("{1, 2.0}").eval()?;
+    assert_eq!(strict.to_vec_integers()?, vec![1, 2]);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_get_resolved() -> Result<()> {
+    let lua = Lua::new();
+
+    let (base, derived, obj): (Table, Table, Table) = lua
+        .load(
+            r#"
+            local base = { kind = "base", name = "base" }
+            local derived = setmetatable({ name = "derived" }, { __index = base })
+            local obj = setmetatable({ own = 1 }, { __index = derived })
+            return base, derived, obj
+        "#,
+        )
+        .eval()?;
+
+    let (own, owner) = obj.get_resolved::<_, i64>("own")?;
+    assert_eq!((own, owner), (1, Some(obj.clone())));
+    let (name, owner) = obj.get_resolved::<_, String>("name")?;
+    assert_eq!(name, "derived");
+    assert_eq!(owner, Some(derived));
+    let (kind, owner) = obj.get_resolved::<_, String>("kind")?;
+    assert_eq!(kind, "base");
+    assert_eq!(owner, Some(base));
+
+    let (missing, owner) = obj.get_resolved::<_, Value>("missing")?;
+    assert_eq!((missing, owner), (Nil, None));
+
+    // Values computed by `__index` functions have no owner
+    let computed: Table = lua
+        .load("setmetatable({}, { __index = function(_, k) return k .. '!' end })")
+        .eval()?;
+    let (value, owner) = computed.get_resolved::<_, String>("hi")?;
+    assert_eq!(value, "hi!");
+    assert_eq!(owner, None);
+
+    let looped: Table = lua
+        .load("local t = {} t.__index = t return setmetatable(t, t)")
+        .eval()?;
+    match looped.get_resolved::<_, Value>("x") {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_table_get_or_insert_with() -> Result<()> {
+    let lua = Lua::new();
+    let table = lua.create_table()?;
+    table.set("existing", "hello")?;
+
+    let mut calls = 0;
+    let v: std::string::String = table.get_or_insert_with("existing", || {
+        calls += 1;
+        Ok("other".to_string())
+    })?;
+    assert_eq!(v, "hello");
+    assert_eq!(calls, 0);
+
+    let v: i64 = table.get_or_insert_with("missing", || {
+        calls += 1;
+        Ok(7)
+    })?;
+    assert_eq!(v, 7);
+    assert_eq!(calls, 1);
+    assert_eq!(table.get::<_, i64>("missing")?, 7);
+
+    // Misses go through `__newindex`, hits through `__index`
+    let store = lua.create_table()?;
+    lua.globals().set("store", store.clone())?;
+    let proxy: Table = lua
+        .load(
+            r#"
+        setmetatable({}, {
+            __index = store,
+            __newindex = function(_, k, v) rawset(store, k, v * 2) end,
+        })
+    "#,
+        )
+        .eval()?;
+
+    let v: i64 = proxy.get_or_insert_with("a", || Ok(1))?;
+    assert_eq!(v, 1);
+    assert_eq!(store.get::<_, i64>("a")?, 2);
+    let v: i64 = proxy.get_or_insert_with("a", || unreachable!())?;
+    assert_eq!(v, 2);
+
+    let err =
+        table.get_or_insert_with::<_, i64, _>("fail", || Err(Error::RuntimeError("nope".into())));
+    assert!(err.is_err());
+    assert_eq!(table.get::<_, Value>("fail")?, Nil);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_freeze() -> Result<()> {
+    let lua = Lua::new();
+
+    let config: Table = lua
+        .load(
+            r#"
+        local t = { name = "server", limits = { connections = 100 }, 1, 2, 3 }
+        t.self = t
+        return t
+    "#,
+        )
+        .eval()?;
+    let frozen = config.freeze()?;
+    lua.globals().set("frozen", frozen.clone())?;
+
+    assert_eq!(frozen.get::<_, std::string::String>("name")?, "server");
+    lua.load(
+        r#"
+        assert(frozen.limits.connections == 100)
+        assert(frozen.self == frozen)
+        assert(frozen.limits == frozen.limits)
+        assert(getmetatable(frozen) == false)
+    "#,
+    )
+    .exec()?;
+
+    for code in &[
+        "frozen.name = 'client'",
+        "frozen.limits.connections = 1",
+        "frozen.new = true",
+        "setmetatable(frozen, nil)",
+    ] {
+        match lua.load(code).exec() {
+            Err(Error::RuntimeError(msg)) => {
+                if !code.starts_with("setmetatable") {
+                    assert!(
+                        msg.contains("attempt to modify a read-only table"),
+                        "{}",
+                        msg
+                    )
+                }
+            }
+            r => panic!("expected error for `{}`, got {:?}", code, r),
+        }
+    }
+    assert_eq!(config.get::<_, std::string::String>("name")?, "server");
+
+    // The original stays mutable and changes show through the proxy
+    config.set("name", "client")?;
+    assert_eq!(frozen.get::<_, std::string::String>("name")?, "client");
+
+    #[cfg(any(feature = "lua53", feature = "lua52"))]
+    lua.load(
+        r#"
+        assert(#frozen == 3)
+        local n = 0
+        for k, v in pairs(frozen) do
+            n = n + 1
+            if k == "limits" then assert(v == frozen.limits) end
+        end
+        assert(n == 6)
+    "#,
+    )
+    .exec()?;
+
+    // Replacing builtins from scripts does not make proxies writable
+    lua.load(
+        r#"
+        type = function() return "nope" end
+        next, error, setmetatable = nil, nil, nil
+    "#,
+    )
+    .exec()?;
+    lua.globals().set("refrozen", config.freeze()?)?;
+    for code in &[
+        "frozen.limits.connections = 1",
+        "refrozen.limits.connections = 1",
+    ] {
+        match lua.load(code).exec() {
+            Err(Error::RuntimeError(msg)) => {
+                assert!(
+                    msg.contains("attempt to modify a read-only table"),
+                    "{}",
+                    msg
+                )
+            }
+            r => panic!("expected error for `{}`, got {:?}", code, r),
+        }
+    }
+    assert_eq!(
+        config
+            .get::<_, Table>("limits")?
+            .get::<_, i64>("connections")?,
+        100
+    );
+
+    Ok(())
+}
@@ -1,10 +1,13 @@
 use std::iter::FromIterator;
-use std::panic::catch_unwind;
-use std::sync::Arc;
-use std::{error, f32, f64, fmt};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::raw::c_int;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::{error, f32, f64, fmt, io, thread};
 
 use mlua::{
-    Error, ExternalError, Function, Lua, Nil, Result, String, Table, UserData, Value, Variadic,
+    lua_State, Coercion, Error, ExternalError, FloatPolicy, Function, Lua, LuaVersion, Nil, Result,
+    StdLib, String, Table, Thread, UserData, Value, Variadic,
 };
 
 #[test]
@@ -19,6 +22,84 @@ fn test_load() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_compile() -> Result<()> {
+    let lua = Lua::new();
+
+    let func = lua.compile("adder", "local a, b = ... return a + b")?;
+    for i in 0..10 {
+        assert_eq!(func.call::<_, i64>((i, 1))?, i + 1);
+    }
+
+    let func = lua.compile("failing", "error('boom')")?;
+    match func.call::<_, ()>(()) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("[string \"failing\"]")),
+        r => panic!("expected runtime error, got {:?}", r),
+    }
+
+    match lua.compile("broken", "return +") {
+        Err(Error::SyntaxError { .. }) => {}
+        r => panic!("expected syntax error, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_load_from_reader() -> Result<()> {
+    // Hands out the source a few bytes at a time, then fails if `fail_at_end` is set
+    struct SlowReader {
+        source: &'static [u8],
+        fail_at_end: bool,
+    }
+
+    impl io::Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.source.is_empty() && self.fail_at_end {
+                return Err(io::Error::new(io::ErrorKind::Other, "connection reset"));
+            }
+            let n = self.source.len().min(buf.len()).min(3);
+            buf[..n].copy_from_slice(&self.source[..n]);
+            self.source = &self.source[n..];
+            Ok(n)
+        }
+    }
+
+    let lua = Lua::new();
+
+    let source = b"local sum = 0 for i = 1, 10 do sum = sum + i end return sum";
+    let func = lua.load_from_reader(
+        "sum",
+        SlowReader {
+            source,
+            fail_at_end: false,
+        },
+    )?;
+    assert_eq!(func.call::<_, i64>(())?, 55);
+
+    match lua.load_from_reader(
+        "broken",
+        SlowReader {
+            source,
+            fail_at_end: true,
+        },
+    ) {
+        Err(Error::ExternalError(err)) => assert_eq!(err.to_string(), "connection reset"),
+        r => panic!("expected external error, got {:?}", r),
+    }
+
+    match lua.load_from_reader("syntax", &b"return +"[..]) {
+        Err(Error::SyntaxError { .. }) => {}
+        r => panic!("expected syntax error, got {:?}", r),
+    }
+    match lua.load_from_reader("binary", &b"\x1bLua"[..]) {
+        Err(Error::SyntaxError { .. }) => {}
+        r => panic!("expected syntax error, got {:?}", r),
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_exec() -> Result<()> {
     let lua = Lua::new();
@@ -75,6 +156,59 @@ fn test_eval() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_check_syntax() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.check_syntax("x = 1")?;
+    lua.check_syntax("error('must not run')")?;
+    assert_eq!(lua.globals().get::<_, Value>("x")?, Nil);
+
+    match lua.check_syntax("for i = 1, 2 do") {
+        Err(Error::SyntaxError {
+            incomplete_input: true,
+            ..
+        }) => {}
+        r => panic!("expected incomplete SyntaxError, got {:?}", r),
+    }
+    match lua.check_syntax("x = = 1") {
+        Err(Error::SyntaxError {
+            incomplete_input: false,
+            ..
+        }) => {}
+        r => panic!("expected SyntaxError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_eval_with() -> Result<()> {
+    let lua = Lua::new();
+
+    assert_eq!(lua.eval_with::<_, _, i64>("... * 2", 21)?, 42);
+    assert_eq!(
+        lua.eval_with::<_, _, (i64, i64)>("local a, b = ... return b, a", (1, 2))?,
+        (2, 1)
+    );
+    assert_eq!(lua.eval_with::<_, _, usize>("select('#', ...)", ())?, 0);
+    lua.eval_with::<_, _, ()>("x = ...", "set")?;
+    assert_eq!(lua.globals().get::<_, std::string::String>("x")?, "set");
+
+    let sum: i64 = lua
+        .load("local s = 0 for _, v in ipairs({...}) do s = s + v end return s")
+        .set_name("sum")?
+        .eval_with((1, 2, 3))?;
+    assert_eq!(sum, 6);
+
+    match lua.eval_with::<_, _, ()>("if true then", ()) {
+        Err(Error::SyntaxError { .. }) => {}
+        r => panic!("expected SyntaxError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_lua_multi() -> Result<()> {
     let lua = Lua::new();
@@ -326,6 +460,60 @@ fn test_error() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_error_value() -> Result<()> {
+    let lua = Lua::new();
+
+    let fail = lua.create_function(|lua, code: i64| -> Result<()> {
+        let err = lua.create_table()?;
+        err.set("code", code)?;
+        Err(lua.create_error_value(err)?)
+    })?;
+    lua.globals().set("fail", fail.clone())?;
+
+    lua.load(
+        r#"
+        local ok, err = pcall(fail, 42)
+        assert(not ok)
+        assert(type(err) == "table" and err.code == 42)
+
+        -- The registry does not keep raised values alive
+        local weak = setmetatable({}, {__mode = "k"})
+        weak[err] = true
+        err = nil
+        collectgarbage()
+        assert(next(weak) == nil)
+    "#,
+    )
+    .exec()?;
+
+    match lua.create_error_value(lua.create_table()?)? {
+        err @ Error::RuntimeValue { .. } => {
+            assert_eq!(err.to_string(), "runtime error with table value")
+        }
+        e => panic!("expected runtime value, got {:?}", e),
+    }
+
+    match fail.call::<_, ()>(7) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected runtime error, got {:?}", r),
+    }
+
+    // Values from another Lua state are rejected
+    let other = Lua::new();
+    let foreign = other.create_error_value("foreign")?;
+    let fail = lua.create_function(move |_, ()| -> Result<()> { Err(foreign.clone()) })?;
+    match fail.call::<_, ()>(()) {
+        Err(Error::CallbackError { cause, .. }) => match *cause {
+            Error::MismatchedRegistryKey => {}
+            ref e => panic!("expected mismatched registry key, got {:?}", e),
+        },
+        r => panic!("expected callback error, got {:?}", r),
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_result_conversions() -> Result<()> {
     let lua = Lua::new();
@@ -412,6 +600,133 @@ fn test_num_conversion() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_float_policy() -> Result<()> {
+    let lua = Lua::new();
+
+    match lua.pack(f64::NAN)? {
+        Value::Number(n) => assert!(n.is_nan()),
+        v => panic!("expected number, got {:?}", v),
+    }
+
+    lua.set_float_policy(FloatPolicy::ErrorOnNonFinite);
+    match lua.pack(f64::NAN) {
+        Err(Error::ToLuaConversionError { from: "f64", .. }) => {}
+        r => panic!("expected ToLuaConversionError, got {:?}", r),
+    }
+    match lua.pack(f32::INFINITY) {
+        Err(Error::ToLuaConversionError { from: "f32", .. }) => {}
+        r => panic!("expected ToLuaConversionError, got {:?}", r),
+    }
+    assert_eq!(lua.unpack::<f64>(lua.pack(1.5f64)?)?, 1.5);
+    // Non-finite values computed by Lua are not affected
+    let inf: f64 = lua.load("1/0").eval()?;
+    assert!(inf.is_infinite());
+
+    lua.set_float_policy(FloatPolicy::CoerceToNil);
+    assert_eq!(lua.pack(f64::NEG_INFINITY)?, Nil);
+    let f = lua.create_function(|_, ()| Ok(f64::NAN))?;
+    assert_eq!(f.call::<_, Value>(())?, Nil);
+
+    Ok(())
+}
+
+#[test]
+fn test_strict_coercion() -> Result<()> {
+    let lua = Lua::new();
+
+    assert_eq!(lua.unpack::<std::string::String>(Value::Integer(42))?, "42");
+    assert_eq!(lua.unpack::<i64>(lua.pack("42")?)?, 42);
+    assert_eq!(lua.unpack::<f64>(lua.pack("1.5")?)?, 1.5);
+
+    lua.set_coercion(Coercion::Strict);
+    match lua.unpack::<std::string::String>(Value::Integer(42)) {
+        Err(Error::FromLuaConversionError {
+            from: "integer", ..
+        }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+    match lua.unpack::<String>(Value::Number(1.5)) {
+        Err(Error::FromLuaConversionError { from: "number", .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+    match lua.unpack::<i64>(lua.pack("42")?) {
+        Err(Error::FromLuaConversionError { from: "string", .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+    match lua.unpack::<f32>(lua.pack("1.5")?) {
+        Err(Error::FromLuaConversionError { from: "string", .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+    assert_eq!(
+        lua.unpack::<std::string::String>(lua.pack("hello")?)?,
+        "hello"
+    );
+    assert_eq!(lua.unpack::<i64>(Value::Number(3.0))?, 3);
+    assert_eq!(lua.coerce_string(Value::Integer(1))?.unwrap(), "1");
+
+    Ok(())
+}
+
+#[test]
+fn test_addr_conversion() -> Result<()> {
+    let lua = Lua::new();
+
+    let v4: Ipv4Addr = lua.load(r#""127.0.0.1""#).eval()?;
+    assert_eq!(v4, Ipv4Addr::LOCALHOST);
+    let v6: Ipv6Addr = lua.load(r#""::1""#).eval()?;
+    assert_eq!(v6, Ipv6Addr::LOCALHOST);
+    let ip: IpAddr = lua.load(r#""::1""#).eval()?;
+    assert_eq!(ip, IpAddr::V6(Ipv6Addr::LOCALHOST));
+    let sock: SocketAddr = lua.load(r#""10.0.0.1:8080""#).eval()?;
+    assert_eq!(
+        sock,
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 8080)
+    );
+
+    let s: String = lua.unpack(lua.pack(sock)?)?;
+    assert_eq!(s, "10.0.0.1:8080");
+    let s: String = lua.unpack(lua.pack(Ipv6Addr::LOCALHOST)?)?;
+    assert_eq!(s, "::1");
+
+    match lua.load(r#""300.0.0.1""#).eval::<Ipv4Addr>() {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected conversion error, got {:?}", r),
+    }
+    match lua.load(r#""10.0.0.1""#).eval::<SocketAddr>() {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected conversion error, got {:?}", r),
+    }
+    assert!(lua.load("1").eval::<IpAddr>().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_try_catch() -> Result<()> {
+    let lua = Lua::new();
+
+    let sum = lua.try_catch(|| {
+        let a: i64 = lua.load("1 + 2").eval()?;
+        let b: i64 = lua.load("3 + 4").eval()?;
+        Ok(a + b)
+    })?;
+    assert_eq!(sum, 10);
+
+    match lua.try_catch(|| lua.load("error('boom')").exec()) {
+        Err(Error::CallbackError { traceback, cause }) => {
+            assert!(traceback.contains("stack traceback"));
+            match *cause {
+                Error::RuntimeError(ref msg) => assert!(msg.contains("boom")),
+                ref e => panic!("unexpected cause: {:?}", e),
+            }
+        }
+        r => panic!("expected callback error, got {:?}", r),
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_pcall_xpcall() -> Result<()> {
     let lua = Lua::new();
@@ -620,12 +935,32 @@ fn test_lua_registry_ownership() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_registry_value_is_set() -> Result<()> {
+    let lua1 = Lua::new();
+    let lua2 = Lua::new();
+
+    let r = lua1.create_registry_value(lua1.create_table()?)?;
+    let nil = lua1.create_registry_value(Nil)?;
+
+    lua1.load(r#"collectgarbage("collect")"#).exec()?;
+    assert!(lua1.registry_value_is_set(&r));
+    assert!(!lua1.registry_value_is_set(&nil));
+    assert!(!lua2.registry_value_is_set(&r));
+
+    Ok(())
+}
+
 #[test]
 fn test_mismatched_registry_key() -> Result<()> {
     let lua1 = Lua::new();
     let lua2 = Lua::new();
 
     let r = lua1.create_registry_value("hello")?;
+    match lua2.registry_value::<Value>(&r) {
+        Err(Error::MismatchedRegistryKey) => {}
+        r => panic!("wrong result type for mismatched registry key, {:?}", r),
+    };
     match lua2.remove_registry_value(r) {
         Err(Error::MismatchedRegistryKey) => {}
         r => panic!("wrong result type for mismatched registry key, {:?}", r),
@@ -634,6 +969,56 @@ fn test_mismatched_registry_key() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_mismatched_lua() -> Result<()> {
+    struct MyUserData;
+
+    impl UserData for MyUserData {}
+
+    let lua1 = Lua::new();
+    let lua2 = Lua::new();
+
+    let table = lua1.create_table()?;
+    let function = lua1.create_function(|_, ()| Ok(()))?;
+    let thread = lua1.create_thread(function.clone())?;
+    let userdata = lua1.create_userdata(MyUserData)?;
+    assert!(table.belongs_to(&lua1) && !table.belongs_to(&lua2));
+    assert!(function.belongs_to(&lua1) && !function.belongs_to(&lua2));
+    assert!(thread.belongs_to(&lua1) && !thread.belongs_to(&lua2));
+    assert!(userdata.belongs_to(&lua1) && !userdata.belongs_to(&lua2));
+
+    match lua2.globals().set("t", table.clone()) {
+        Err(Error::MismatchedLua) => {}
+        r => panic!("expected MismatchedLua, got {:?}", r),
+    }
+    match lua2.create_thread(function.clone()) {
+        Err(Error::MismatchedLua) => {}
+        r => panic!("expected MismatchedLua, got {:?}", r),
+    }
+    assert!(table != lua2.create_table()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_registry_key_get() -> Result<()> {
+    let lua = Lua::new();
+
+    let key = lua.create_registry_value("hello")?;
+    match key.get(&lua)? {
+        Value::String(s) => assert_eq!(s, "hello"),
+        v => panic!("expected string, got {:?}", v),
+    }
+
+    let other = Lua::new();
+    match key.get(&other) {
+        Err(Error::MismatchedRegistryKey) => {}
+        r => panic!("expected MismatchedRegistryKey, got {:?}", r),
+    }
+
+    Ok(())
+}
+
 #[test]
 fn too_many_returns() -> Result<()> {
     let lua = Lua::new();
@@ -828,3 +1213,544 @@ fn context_thread_51() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn shared_between_threads() -> Result<()> {
+    let lua = Arc::new(Mutex::new(Lua::new()));
+    lua.lock().unwrap().globals().set("counter", 0)?;
+
+    let handles = (0..4)
+        .map(|_| {
+            let lua = lua.clone();
+            thread::spawn(move || -> Result<()> {
+                for _ in 0..100 {
+                    let lua = lua.lock().unwrap();
+                    lua.load("counter = counter + 1").exec()?;
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap()?;
+    }
+
+    // A panic while holding the lock poisons it, but leaves the Lua state usable
+    let lua2 = lua.clone();
+    let _ = thread::spawn(move || {
+        let lua = lua2.lock().unwrap();
+        let panic = lua
+            .create_function(|_, ()| -> Result<()> { panic!("test panic") })
+            .unwrap();
+        let _ = panic.call::<_, ()>(());
+    })
+    .join();
+
+    let lua = lua.lock().unwrap_or_else(|e| e.into_inner());
+    assert_eq!(lua.globals().get::<_, i64>("counter")?, 400);
+
+    Ok(())
+}
+
+#[cfg(not(feature = "luajit"))]
+#[test]
+fn interrupt() -> Result<()> {
+    let lua = Lua::new_with(StdLib::ALL);
+
+    let handle = lua.interrupt_handle();
+    let thrd = thread::spawn(move || {
+        thread::sleep(std::time::Duration::from_millis(50));
+        handle.interrupt();
+    });
+    match lua.load("while true do end").exec() {
+        Err(Error::RuntimeError(msg)) => assert!(msg.starts_with("interrupted")),
+        r => panic!("expected interrupted error, got {:?}", r),
+    }
+    thrd.join().unwrap();
+
+    // The flag is cleared once the interrupt fires
+    assert_eq!(
+        lua.load("local n = 0 for i = 1, 100000 do n = n + i end return n")
+            .eval::<i64>()?,
+        5000050000
+    );
+
+    // An interrupt requested while no Lua code is running interrupts the next call
+    let handle = lua.interrupt_handle();
+    handle.interrupt();
+    assert!(lua.load("for i = 1, 100000 do end").exec().is_err());
+    lua.load("for i = 1, 100000 do end").exec()?;
+
+    // Threads with an instruction limit are interruptible too
+    let co: Thread = lua
+        .load("coroutine.create(function() while true do end end)")
+        .eval()?;
+    co.set_instruction_limit(Some(u64::max_value()))?;
+    handle.interrupt();
+    match co.resume::<_, ()>(()) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.starts_with("interrupted")),
+        r => panic!("expected interrupted error, got {:?}", r),
+    }
+
+    // Coroutines created afterwards are interruptible too
+    let co: Thread = lua
+        .load("coroutine.create(function() while true do end end)")
+        .eval()?;
+    let thrd = thread::spawn(move || {
+        thread::sleep(std::time::Duration::from_millis(50));
+        handle.interrupt();
+    });
+    assert!(co.resume::<_, ()>(()).is_err());
+    thrd.join().unwrap();
+
+    // The count hook removes itself once every handle is dropped
+    lua.load("for i = 1, 100000 do end").exec()?;
+    let mask: String = lua.load("return select(2, debug.gethook())").eval()?;
+    assert_eq!(mask, "");
+
+    // Coroutines created while no handle was alive get the hook when resumed
+    let co: Thread = lua
+        .load("coroutine.create(function() while true do end end)")
+        .eval()?;
+    let handle = lua.interrupt_handle();
+    let thrd = thread::spawn(move || {
+        thread::sleep(std::time::Duration::from_millis(50));
+        handle.interrupt();
+    });
+    assert!(co.resume::<_, ()>(()).is_err());
+    thrd.join().unwrap();
+
+    Ok(())
+}
+
+#[cfg(not(feature = "luajit"))]
+#[test]
+fn coverage() -> Result<()> {
+    let lua = Lua::new();
+
+    assert!(lua.stop_coverage().sources().next().is_none());
+
+    lua.start_coverage()?;
+    lua.load(
+        r#"
+            local function add(a, b)
+                return a + b
+            end
+            local co = coroutine.wrap(function()
+                for i = 1, 3 do
+                    coroutine.yield(add(i, i))
+                end
+            end)
+            co()
+            co()
+        "#,
+    )
+    .set_name("=coverage")?
+    .exec()?;
+
+    // Recording stays active across interrupt handles and instruction limits
+    let _handle = lua.interrupt_handle();
+    let co: Thread = lua
+        .load("coroutine.create(function()\nreturn 1\nend)")
+        .set_name("=limited")?
+        .eval()?;
+    co.set_instruction_limit(Some(1000))?;
+    co.resume::<_, i64>(())?;
+
+    let report = lua.stop_coverage();
+    assert_eq!(report.hits("=coverage", 3), 2);
+    assert_eq!(report.hits("=coverage", 7), 2);
+    assert_eq!(report.hits("=coverage", 10), 1);
+    assert_eq!(report.hits("=coverage", 11), 1);
+    assert_eq!(report.hits("=coverage", 12), 0);
+    assert_eq!(report.hits("=limited", 2), 1);
+    assert!(report.lines("=coverage").unwrap().contains_key(&7));
+    assert!(report.lines("=unknown").is_none());
+
+    // Nothing is recorded once stopped
+    lua.load("local x = 1").set_name("=after")?.exec()?;
+    co.resume::<_, ()>(()).ok();
+    lua.start_coverage()?;
+    assert!(lua.stop_coverage().lines("=after").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_with_stack() -> Result<()> {
+    extern "C" {
+        fn lua_pushinteger(state: *mut lua_State, n: i64);
+        fn lua_gettop(state: *mut lua_State) -> c_int;
+    }
+
+    let lua = Lua::new();
+    let top = lua.stack_top();
+
+    let pushed = lua.with_stack(|state| unsafe {
+        for i in 0..10 {
+            lua_pushinteger(state, i);
+        }
+        lua_gettop(state)
+    });
+    assert_eq!(pushed, top + 10);
+    assert_eq!(lua.stack_top(), top);
+
+    // Stack-using API calls keep working afterwards
+    lua.globals().set("x", 1)?;
+    assert_eq!(lua.globals().get::<_, i64>("x")?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_pin() -> Result<()> {
+    extern "C" {
+        fn lua_rawequal(state: *mut lua_State, idx1: c_int, idx2: c_int) -> c_int;
+    }
+
+    let lua = Lua::new();
+    let top = lua.stack_top();
+
+    let t1 = Value::Table(lua.create_table()?);
+    let t2 = Value::Table(lua.create_table()?);
+    let outer = lua.pin(&t1)?;
+    let inner = lua.pin(&t2)?;
+    assert_eq!(outer.index(), top + 1);
+    assert_eq!(inner.index(), top + 2);
+
+    // Other API calls keep working while values are pinned
+    lua.globals().set("x", 1)?;
+    assert_eq!(lua.load("x + 1").eval::<i64>()?, 2);
+    assert_eq!(lua.stack_top(), top + 2);
+    lua.with_stack(|state| unsafe {
+        assert_eq!(lua_rawequal(state, outer.index(), inner.index()), 0);
+        assert_eq!(lua_rawequal(state, outer.index(), outer.index()), 1);
+    });
+
+    drop(inner);
+    drop(outer);
+    assert_eq!(lua.stack_top(), top);
+
+    // Out of order release is detected
+    let outer = lua.pin(&t1)?;
+    let inner = lua.pin(&t2)?;
+    assert!(catch_unwind(AssertUnwindSafe(move || drop(outer))).is_err());
+    drop(inner);
+
+    Ok(())
+}
+
+#[test]
+fn test_register_module() -> Result<()> {
+    let lua = Lua::new();
+
+    let module = lua.create_table()?;
+    module.set("name", "mymod")?;
+    lua.register_module("mymod", module.clone())?;
+    let (first, second): (Table, Table) = lua
+        .load("return require('mymod'), require('mymod')")
+        .eval()?;
+    assert_eq!(first, module);
+    assert_eq!(second, module);
+    assert_eq!(first.get::<_, String>("name")?, "mymod");
+
+    let lua = Lua::new_with(StdLib::TABLE);
+    match lua.register_module("mymod", lua.create_table()?) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("package library")),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_version() -> Result<()> {
+    let lua = Lua::new();
+    let version: String = lua.globals().get("_VERSION")?;
+    let version = version.to_str()?;
+
+    match Lua::version() {
+        LuaVersion::Lua53 => assert_eq!(version, "Lua 5.3"),
+        LuaVersion::Lua52 => assert_eq!(version, "Lua 5.2"),
+        LuaVersion::Lua51 | LuaVersion::LuaJit => assert_eq!(version, "Lua 5.1"),
+    }
+
+    assert_eq!(Lua::float_size(), 8);
+    #[cfg(feature = "lua53")]
+    assert_eq!(Lua::integer_size(), 8);
+
+    Ok(())
+}
+
+#[test]
+fn test_on_drop() -> Result<()> {
+    struct Logged(Arc<Mutex<Vec<&'static str>>>);
+
+    impl UserData for Logged {}
+
+    impl Drop for Logged {
+        fn drop(&mut self) {
+            self.0.lock().unwrap().push("userdata");
+        }
+    }
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+
+    let lua = Lua::new();
+    lua.globals().set("ud", Logged(log.clone()))?;
+
+    let log2 = log.clone();
+    lua.on_drop(move || log2.lock().unwrap().push("first"));
+    let log2 = log.clone();
+    let f = lua.create_function(move |lua, ()| {
+        let log2 = log2.clone();
+        lua.on_drop(move || log2.lock().unwrap().push("second"));
+        Ok(())
+    })?;
+    f.call::<_, ()>(())?;
+    drop(f);
+
+    assert!(log.lock().unwrap().is_empty());
+    drop(lua);
+    assert_eq!(*log.lock().unwrap(), vec!["userdata", "first", "second"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_virtual_table() -> Result<()> {
+    let lua = Lua::new();
+
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let calls2 = calls.clone();
+    let config = lua.create_virtual_table(move |lua, key| {
+        calls2.lock().unwrap().push(format!("{:?}", key));
+        match key {
+            Value::String(s) if s == "name" => Ok(Value::String(lua.create_string("mlua")?)),
+            _ => Ok(Nil),
+        }
+    })?;
+    lua.globals().set("config", config.clone())?;
+
+    assert_eq!(
+        lua.load("config.name").eval::<std::string::String>()?,
+        "mlua"
+    );
+    assert_eq!(lua.load("config.missing").eval::<Value>()?, Nil);
+    assert_eq!(calls.lock().unwrap().len(), 2);
+
+    // Stored keys shadow the callback
+    lua.load("config.name = 'other'").exec()?;
+    assert_eq!(config.get::<_, std::string::String>("name")?, "other");
+    assert_eq!(calls.lock().unwrap().len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_values_equal() -> Result<()> {
+    let lua = Lua::new();
+
+    let s1 = Value::String(lua.create_string("abc")?);
+    let s2 = Value::String(lua.create_string("abc")?);
+    assert!(lua.values_equal(&s1, &s2)?);
+    assert!(lua.values_equal(&Value::Integer(1), &Value::Number(1.0))?);
+    assert!(!lua.values_equal(&Value::Integer(1), &s1)?);
+    assert!(lua.values_equal(&Nil, &Nil)?);
+
+    let (t1, t2, t3): (Value, Value, Value) = lua
+        .load(
+            r#"
+        local mt = { __eq = function(a, b) return a.id == b.id end }
+        return setmetatable({ id = 1 }, mt), setmetatable({ id = 1 }, mt), { id = 1 }
+    "#,
+        )
+        .eval()?;
+    assert!(lua.values_equal(&t1, &t1)?);
+    assert!(lua.values_equal(&t1, &t2)?);
+    assert!(!lua.values_equal(&t1, &Value::Integer(1))?);
+    // Only Lua 5.3 calls `__eq` when just one operand defines it
+    #[cfg(feature = "lua53")]
+    assert!(lua.values_equal(&t1, &t3)?);
+    #[cfg(not(feature = "lua53"))]
+    assert!(!lua.values_equal(&t1, &t3)?);
+
+    #[cfg(not(feature = "luajit"))]
+    {
+        let (t1, t2): (Value, Value) = lua
+            .load(
+                r#"
+            local mt = { __eq = function() error("no comparison") end }
+            return setmetatable({}, mt), setmetatable({}, mt)
+        "#,
+            )
+            .eval()?;
+        assert!(lua.values_equal(&t1, &t2).is_err());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_values_ordering() -> Result<()> {
+    let lua = Lua::new();
+
+    let one = Value::Integer(1);
+    let two = Value::Number(2.0);
+    assert!(lua.values_less_than(&one, &two)?);
+    assert!(!lua.values_less_than(&two, &one)?);
+    assert!(lua.values_less_equal(&one, &one)?);
+    assert!(!lua.values_less_equal(&two, &one)?);
+
+    let a = Value::String(lua.create_string("a")?);
+    let b = Value::String(lua.create_string("b")?);
+    assert!(lua.values_less_than(&a, &b)?);
+    assert!(lua.values_less_equal(&a, &a)?);
+
+    let (v1, v2): (Value, Value) = lua
+        .load(
+            r#"
+        local mt = { __lt = function(a, b) return a.v < b.v end }
+        return setmetatable({ v = 1 }, mt), setmetatable({ v = 2 }, mt)
+    "#,
+        )
+        .eval()?;
+    assert!(lua.values_less_than(&v1, &v2)?);
+    assert!(!lua.values_less_than(&v2, &v1)?);
+    // `<=` falls back to `__lt`
+    assert!(lua.values_less_equal(&v1, &v2)?);
+
+    #[cfg(not(feature = "luajit"))]
+    {
+        assert!(lua.values_less_than(&one, &a).is_err());
+        assert!(lua.values_less_equal(&Nil, &Nil).is_err());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_callback_name() -> Result<()> {
+    let lua = Lua::new();
+
+    let name = lua.create_function(|lua, ()| Ok(lua.callback_name()))?;
+    let globals = lua.globals();
+    globals.set("whoami", name.clone())?;
+    let t = lua.create_table()?;
+    t.set("field", name.clone())?;
+    t.set("method", name.clone())?;
+    globals.set("t", t)?;
+
+    assert_eq!(
+        lua.load("local n = whoami(); return n")
+            .eval::<Option<std::string::String>>()?,
+        Some("whoami".into())
+    );
+    assert_eq!(
+        lua.load("local n = t.field(); return n")
+            .eval::<Option<std::string::String>>()?,
+        Some("field".into())
+    );
+    assert_eq!(
+        lua.load("local n = t:method(); return n")
+            .eval::<Option<std::string::String>>()?,
+        Some("method".into())
+    );
+
+    assert_eq!(name.call::<_, Option<std::string::String>>(())?, None);
+    assert_eq!(lua.callback_name(), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_create_iterator() -> Result<()> {
+    let lua = Lua::new();
+
+    let produced = Arc::new(Mutex::new(0));
+    let produced2 = produced.clone();
+    let iter = lua.create_iterator((1..=10).map(move |i| {
+        *produced2.lock().unwrap() += 1;
+        Ok(format!("item{}", i))
+    }))?;
+    lua.globals().set("iter", iter.clone())?;
+
+    let first: Vec<std::string::String> = lua
+        .load(
+            r#"
+        local items = {}
+        for item in iter do
+            items[#items + 1] = item
+            if #items == 3 then break end
+        end
+        return items
+    "#,
+        )
+        .eval()?;
+    assert_eq!(first, vec!["item1", "item2", "item3"]);
+    assert_eq!(*produced.lock().unwrap(), 3);
+
+    let rest: i64 = lua
+        .load("local n = 0 for _ in iter do n = n + 1 end return n")
+        .eval()?;
+    assert_eq!(rest, 7);
+    assert_eq!(iter.call::<_, Value>(())?, Nil);
+    assert_eq!(iter.call::<_, Value>(())?, Nil);
+
+    #[cfg(not(feature = "luajit"))]
+    {
+        let failing = lua
+            .create_iterator(vec![Ok(1), Err(Error::RuntimeError("broken".into()))].into_iter())?;
+        lua.globals().set("failing", failing)?;
+        match lua.load("for _ in failing do end").exec() {
+            Err(Error::CallbackError { cause, .. }) => match *cause {
+                Error::RuntimeError(ref msg) => assert_eq!(msg, "broken"),
+                ref e => panic!("unexpected cause {:?}", e),
+            },
+            r => panic!("expected callback error, got {:?}", r),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_globals_snapshot() -> Result<()> {
+    let lua = Lua::new();
+    lua.load("counter = 1; config = { debug = false }").exec()?;
+
+    let snapshot = lua.snapshot_globals()?;
+    for _ in 0..2 {
+        lua.load(
+            r#"
+            counter = counter + 1
+            leaked = "value"
+            config.debug = true
+            config = nil
+            print = nil
+        "#,
+        )
+        .exec()?;
+        lua.restore_globals(&snapshot)?;
+
+        lua.load(
+            r#"
+            assert(counter == 1)
+            assert(leaked == nil)
+            assert(print ~= nil)
+            -- Snapshots are shallow
+            assert(config.debug == true)
+            config.debug = false
+        "#,
+        )
+        .exec()?;
+    }
+
+    let other = Lua::new();
+    match other.restore_globals(&snapshot) {
+        Err(Error::MismatchedRegistryKey) => {}
+        r => panic!("expected MismatchedRegistryKey, got {:?}", r),
+    }
+
+    Ok(())
+}
@@ -1,6 +1,7 @@
+use std::collections::HashMap;
 use std::panic::catch_unwind;
 
-use mlua::{Error, Function, Lua, Result, Thread, ThreadStatus};
+use mlua::{Error, Function, Lua, Result, Table, Thread, ThreadStatus};
 
 #[test]
 fn test_thread() -> Result<()> {
@@ -128,3 +129,282 @@ fn coroutine_panic() {
         Err(p) => assert!(*p.downcast::<&str>().unwrap() == "test_panic"),
     }
 }
+
+#[cfg(not(feature = "luajit"))]
+#[test]
+fn thread_instruction_limit() -> Result<()> {
+    let lua = Lua::new();
+
+    let thrd: Thread = lua
+        .load("coroutine.create(function() while true do end end)")
+        .eval()?;
+    thrd.set_instruction_limit(Some(10_000))?;
+    match thrd.resume::<_, ()>(()) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.starts_with("instruction limit exceeded")),
+        r => panic!("expected instruction limit error, got {:?}", r),
+    }
+    assert_eq!(thrd.status(), ThreadStatus::Error);
+
+    // The count starts over on every resume
+    let thrd: Thread = lua
+        .load(
+            r#"
+            coroutine.create(function()
+                for i = 1, 100 do
+                    for j = 1, 100 do end
+                    coroutine.yield(i)
+                end
+            end)
+        "#,
+        )
+        .eval()?;
+    thrd.set_instruction_limit(Some(10_000))?;
+    for i in 1..=100 {
+        assert_eq!(thrd.resume::<_, i64>(())?, i);
+    }
+
+    thrd.set_instruction_limit(None)?;
+    thrd.resume::<_, ()>(())?;
+    assert_eq!(thrd.status(), ThreadStatus::Unresumable);
+
+    Ok(())
+}
+
+#[test]
+fn thread_from_fn() -> Result<()> {
+    let lua = Lua::new();
+
+    let thrd = lua.create_thread_from_fn(|_, (a, b): (i64, i64)| Ok(a * b))?;
+    assert_eq!(thrd.status(), ThreadStatus::Resumable);
+    assert_eq!(thrd.resume::<_, i64>((6, 7))?, 42);
+    assert_eq!(thrd.status(), ThreadStatus::Unresumable);
+
+    // LuaJIT aborts when a Rust callback yields
+    #[cfg(not(feature = "luajit"))]
+    {
+        let thrd = lua.create_thread_from_fn(|lua, n: i64| lua.yield_with(n * 2))?;
+        assert_eq!(thrd.resume::<_, i64>(21)?, 42);
+        assert_eq!(thrd.status(), ThreadStatus::Resumable);
+        assert_eq!(thrd.resume::<_, String>("done")?, "done");
+        assert_eq!(thrd.status(), ThreadStatus::Unresumable);
+
+        // Yielded values are not kept alive once they have been yielded
+        let weak: Table = lua.load("setmetatable({}, { __mode = 'v' })").eval()?;
+        let thrd = lua.create_thread_from_fn(|lua, ()| lua.yield_with(lua.create_table()?))?;
+        weak.set(1, thrd.resume::<_, Table>(())?)?;
+        lua.gc_collect()?;
+        assert_eq!(weak.get::<_, Option<Table>>(1)?, None);
+    }
+
+    // Lua 5.1 cannot resume into a Rust function which is the body of the coroutine
+    #[cfg(feature = "lua51")]
+    {
+        let thrd = lua.create_thread(lua.create_function(|lua, ()| lua.yield_with(()))?)?;
+        assert!(thrd.resume::<_, ()>(()).is_err());
+    }
+
+    let thrd = lua.create_thread_from_fn(|lua, ()| {
+        let coroutine: Table = lua.globals().get("coroutine")?;
+        coroutine.get::<_, Function>("yield")?.call::<_, ()>(1)
+    })?;
+    assert!(thrd.resume::<_, ()>(()).is_err());
+    assert_eq!(thrd.status(), ThreadStatus::Error);
+
+    Ok(())
+}
+
+#[test]
+fn thread_main_state() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals().set("main_thread", lua.current_thread())?;
+
+    let in_main = lua.create_function(|lua, ()| {
+        let key = lua.with_main_state(|main| -> Result<_> {
+            let main_thread: Thread = main.globals().get("main_thread")?;
+            assert!(main.current_thread() == main_thread);
+            main.create_registry_value("shared")
+        })?;
+        assert_eq!(lua.registry_value::<String>(&key)?, "shared");
+
+        let main_thread: Thread = lua.globals().get("main_thread")?;
+        Ok(lua.current_thread() == main_thread)
+    })?;
+    lua.globals().set("in_main", in_main)?;
+
+    assert!(lua.load("in_main()").eval::<bool>()?);
+    let co: Thread = lua
+        .load("coroutine.create(function() return in_main() end)")
+        .eval()?;
+    assert!(!co.resume::<_, bool>(())?);
+
+    Ok(())
+}
+
+#[test]
+fn thread_error_traceback() -> Result<()> {
+    let lua = Lua::new();
+
+    let resume_error = |lua: &Lua| -> Result<String> {
+        let thread = lua.create_thread(lua.load("function() error('boom') end").eval()?)?;
+        match thread.resume::<_, ()>(()) {
+            Err(Error::RuntimeError(msg)) => Ok(msg),
+            r => panic!("expected RuntimeError, got {:?}", r),
+        }
+    };
+
+    let msg = resume_error(&lua)?;
+    assert!(msg.contains("boom"));
+    assert!(msg.contains("stack traceback"));
+
+    lua.set_error_traceback(false);
+    let msg = resume_error(&lua)?;
+    assert!(msg.contains("boom"));
+    assert!(!msg.contains("stack traceback"));
+
+    Ok(())
+}
+
+#[test]
+fn thread_yield_from_callback() -> Result<()> {
+    let lua = Lua::new();
+
+    let pause = lua.create_function(|lua, (a, b): (i64, i64)| lua.yield_with((a + b, "paused")))?;
+    lua.globals().set("pause", pause)?;
+
+    let thread: Thread = lua
+        .load(
+            r#"
+            coroutine.create(function(a, b)
+                local x = pause(a, b)
+                return x * 10
+            end)
+            "#,
+        )
+        .eval()?;
+
+    let (sum, msg): (i64, String) = thread.resume((1, 2))?;
+    assert_eq!(sum, 3);
+    assert_eq!(msg, "paused");
+    assert_eq!(thread.status(), ThreadStatus::Resumable);
+
+    assert_eq!(thread.resume::<_, i64>(4)?, 40);
+    assert_eq!(thread.status(), ThreadStatus::Unresumable);
+
+    // Only a returned `Yield` suspends the coroutine
+    let no_pause = lua.create_function(|lua, n: i64| {
+        drop(lua.yield_with(n)?);
+        Ok(n + 1)
+    })?;
+    let thread = lua.create_thread(
+        lua.load("function(f, n) return f(n) * 10 end")
+            .eval::<Function>()?,
+    )?;
+    assert_eq!(thread.resume::<_, i64>((no_pause, 1))?, 20);
+    assert_eq!(thread.status(), ThreadStatus::Unresumable);
+
+    // Yielding on the main thread is an error
+    match lua.load("pause(1, 2)").exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::RuntimeError(ref msg) => {
+                assert_eq!(msg, "attempt to yield from outside a coroutine")
+            }
+            ref err => panic!("unexpected error: {:?}", err),
+        },
+        r => panic!("expected error, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn thread_current_status() -> Result<()> {
+    let lua = Lua::new();
+
+    assert!(!lua.is_in_coroutine());
+    assert_eq!(lua.current_thread_status(), ThreadStatus::Unresumable);
+
+    let check = lua.create_function(|lua, ()| {
+        Ok((
+            lua.is_in_coroutine(),
+            lua.current_thread_status() == ThreadStatus::Unresumable,
+        ))
+    })?;
+    lua.globals().set("check", check)?;
+
+    let (in_coroutine, running): (bool, bool) = lua.load("check()").eval()?;
+    assert!(!in_coroutine);
+    assert!(running);
+
+    let (in_coroutine, running): (bool, bool) = lua
+        .load("coroutine.wrap(function() return check() end)()")
+        .eval()?;
+    assert!(in_coroutine);
+    assert!(running);
+
+    Ok(())
+}
+
+#[test]
+fn thread_run_scheduler() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.load(
+        r#"
+        log = {}
+        function worker(name, steps)
+            return coroutine.create(function()
+                for i = 1, steps do
+                    log[#log + 1] = name .. i
+                    coroutine.yield(i)
+                end
+            end)
+        end
+        "#,
+    )
+    .exec()?;
+
+    let a: Thread = lua.load("worker('a', 3)").eval()?;
+    let b: Thread = lua.load("worker('b', 1)").eval()?;
+    let c: Thread = lua.load("worker('c', 2)").eval()?;
+    lua.run_scheduler(vec![a.clone(), b, c])?;
+    assert_eq!(a.status(), ThreadStatus::Unresumable);
+
+    let log: Vec<String> = lua.load("log").eval()?;
+    assert_eq!(log, vec!["a1", "b1", "c1", "a2", "c2", "a3"]);
+
+    // The first error stops the scheduler
+    let failing: Thread = lua
+        .load("coroutine.create(function() coroutine.yield() error('boom') end)")
+        .eval()?;
+    let other: Thread = lua.load("worker('d', 5)").eval()?;
+    match lua.run_scheduler(vec![failing, other.clone()]) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected error, got {:?}", r),
+    }
+    assert_eq!(other.status(), ThreadStatus::Resumable);
+
+    Ok(())
+}
+
+#[test]
+fn thread_identity() -> Result<()> {
+    let lua = Lua::new();
+
+    let func: Function = lua.load("function() coroutine.yield() end").eval()?;
+    let t1 = lua.create_thread(func.clone())?;
+    let t2 = lua.create_thread(func)?;
+    lua.globals().set("t1", t1.clone())?;
+    let t1_copy: Thread = lua.globals().get("t1")?;
+
+    assert_eq!(t1.to_pointer(), t1_copy.to_pointer());
+    assert_ne!(t1.to_pointer(), t2.to_pointer());
+
+    let mut names = HashMap::new();
+    names.insert(t1, "first");
+    names.insert(t2.clone(), "second");
+    assert_eq!(names[&t1_copy], "first");
+    assert_eq!(names[&t2], "second");
+    assert_eq!(names.len(), 2);
+
+    Ok(())
+}
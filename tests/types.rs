@@ -1,6 +1,6 @@
 use std::os::raw::c_void;
 
-use mlua::{Function, LightUserData, Lua, Result};
+use mlua::{Error, Function, LightUserData, Lua, Result, TypedLightUserData, TypedNumber};
 
 #[test]
 fn test_lightuserdata() -> Result<()> {
@@ -24,3 +24,69 @@ fn test_lightuserdata() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_typed_lightuserdata() -> Result<()> {
+    struct Config {
+        name: &'static str,
+    }
+
+    let lua = Lua::new();
+
+    let mut config = Config { name: "host" };
+    let ptr = TypedLightUserData::from_ptr(&mut config as *mut Config);
+    lua.globals().set("config", ptr)?;
+
+    let res: TypedLightUserData<Config> = lua.load("config").eval()?;
+    assert_eq!(res, ptr);
+    assert_eq!(res.as_ptr(), &mut config as *mut Config);
+    assert_eq!(unsafe { res.as_ref() }.map(|c| c.name), Some("host"));
+
+    // Lua sees a plain light userdata
+    let untyped: LightUserData = lua.load("config").eval()?;
+    assert_eq!(
+        untyped,
+        LightUserData(&mut config as *mut Config as *mut c_void)
+    );
+    assert_eq!(lua.load("type(config)").eval::<String>()?, "userdata");
+
+    let null = TypedLightUserData::<Config>::from_ptr(std::ptr::null_mut());
+    assert!(unsafe { null.as_ref() }.is_none());
+
+    match lua.load("1").eval::<TypedLightUserData<Config>>() {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_typed_number() -> Result<()> {
+    let lua = Lua::new();
+
+    let float: TypedNumber = lua.load("3.0").eval()?;
+    assert_eq!(float, TypedNumber::Float(3.0));
+    let float: TypedNumber = lua.load("0.5").eval()?;
+    assert_eq!(float, TypedNumber::Float(0.5));
+
+    let int: TypedNumber = lua.load("3").eval()?;
+    #[cfg(feature = "lua53")]
+    assert_eq!(int, TypedNumber::Integer(3));
+    #[cfg(not(feature = "lua53"))]
+    assert_eq!(int, TypedNumber::Float(3.0));
+
+    assert!(lua.load("'3'").eval::<TypedNumber>().is_err());
+
+    let globals = lua.globals();
+    globals.set("i", TypedNumber::Integer(7))?;
+    globals.set("f", TypedNumber::Float(7.0))?;
+    #[cfg(feature = "lua53")]
+    {
+        assert_eq!(lua.load("math.type(i)").eval::<String>()?, "integer");
+        assert_eq!(lua.load("math.type(f)").eval::<String>()?, "float");
+    }
+    assert!(lua.load("i == f").eval::<bool>()?);
+
+    Ok(())
+}
@@ -1,7 +1,9 @@
+use std::any::TypeId;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use mlua::{
-    AnyUserData, ExternalError, Function, Lua, MetaMethod, Result, String, UserData,
+    AnyUserData, Error, ExternalError, Function, Lua, MetaMethod, Result, String, UserData,
     UserDataMethods, Value,
 };
 
@@ -227,6 +229,61 @@ fn user_value() -> Result<()> {
     assert_eq!(ud.get_user_value::<String>()?, "hello");
     assert!(ud.get_user_value::<u32>().is_err());
 
+    ud.set_associated(42)?;
+    assert_eq!(ud.get_associated::<u32>()?, 42);
+    assert_eq!(ud.get_user_value::<u32>()?, 42);
+
+    Ok(())
+}
+
+#[test]
+fn take_user_value() -> Result<()> {
+    struct MyUserData;
+    impl UserData for MyUserData {}
+
+    struct Holder(Arc<()>);
+    impl UserData for Holder {}
+
+    let lua = Lua::new();
+    let ud = lua.create_userdata(MyUserData)?;
+
+    let data = Arc::new(());
+    ud.set_user_value(lua.create_userdata(Holder(data.clone()))?)?;
+    assert!(ud.take_user_value::<u32>().is_err());
+    let held = ud.take_user_value::<AnyUserData>()?;
+    assert_eq!(ud.get_user_value::<Value>()?, Value::Nil);
+    drop(held);
+
+    ud.set_user_value("hello")?;
+    ud.clear_user_value()?;
+    assert_eq!(ud.get_user_value::<Option<String>>()?, None);
+
+    lua.gc_collect()?;
+    assert_eq!(Arc::strong_count(&data), 1);
+
+    Ok(())
+}
+
+#[test]
+fn user_value_pairs() -> Result<()> {
+    struct MyUserData;
+    impl UserData for MyUserData {}
+
+    let lua = Lua::new();
+    let ud = lua.create_userdata(MyUserData)?;
+    ud.set_user_value(lua.create_table_from(vec![("a", 1), ("b", 2)])?)?;
+    let mut pairs = ud
+        .user_value_pairs::<std::string::String, i64>()?
+        .collect::<Result<Vec<_>>>()?;
+    pairs.sort();
+    assert_eq!(pairs, vec![("a".to_owned(), 1), ("b".to_owned(), 2)]);
+
+    ud.set_user_value("not a table")?;
+    match ud.user_value_pairs::<Value, Value>() {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        _ => panic!("expected FromLuaConversionError"),
+    }
+
     Ok(())
 }
 
@@ -279,3 +336,198 @@ fn test_functions() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn user_value_cycle() -> Result<()> {
+    struct MyUserData(Arc<()>);
+    impl UserData for MyUserData {}
+
+    let rc = Arc::new(());
+
+    let lua = Lua::new();
+    {
+        let ud = lua.create_userdata(MyUserData(rc.clone()))?;
+        let table = lua.create_table()?;
+        table.set("owner", ud.clone())?;
+        ud.set_user_value(table)?;
+    }
+    assert_eq!(Arc::strong_count(&rc), 2);
+
+    // The userdata and its associated table refer to each other, but are both collectable
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert_eq!(Arc::strong_count(&rc), 1);
+
+    Ok(())
+}
+
+#[test]
+fn register_metatable() -> Result<()> {
+    static ADD_METHODS_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    struct MyUserData(i64);
+
+    impl UserData for MyUserData {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            ADD_METHODS_CALLS.fetch_add(1, Ordering::SeqCst);
+            methods.add_method("get", |_, data, ()| Ok(data.0));
+        }
+    }
+
+    let lua = Lua::new();
+    lua.register_userdata_metatable::<MyUserData>()?;
+    lua.register_userdata_metatable::<MyUserData>()?;
+    assert_eq!(ADD_METHODS_CALLS.load(Ordering::SeqCst), 1);
+
+    let globals = lua.globals();
+    globals.set("a", lua.create_userdata(MyUserData(1))?)?;
+    globals.set("b", lua.create_userdata(MyUserData(2))?)?;
+    assert_eq!(lua.load("a:get() + b:get()").eval::<i64>()?, 3);
+    assert_eq!(ADD_METHODS_CALLS.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_method_consume() -> Result<()> {
+    #[derive(Clone)]
+    struct Builder(Vec<i64>);
+
+    impl UserData for Builder {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method_consume("with", |_, mut this, value: i64| {
+                this.0.push(value);
+                Ok(this)
+            });
+            methods.add_method("len", |_, this, ()| Ok(this.0.len()));
+        }
+    }
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals.set("builder", Builder(Vec::new()))?;
+    lua.load(
+        r#"
+        built = builder:with(1):with(2):with(3)
+    "#,
+    )
+    .exec()?;
+
+    let built: AnyUserData = globals.get("built")?;
+    assert_eq!(built.borrow::<Builder>()?.0, vec![1, 2, 3]);
+    let builder: AnyUserData = globals.get("builder")?;
+    assert_eq!(builder.borrow::<Builder>()?.0.len(), 0);
+    assert_eq!(lua.load("builder:len()").eval::<usize>()?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_borrow_error_method_name() -> Result<()> {
+    struct MyUserData(Vec<i64>);
+
+    impl UserData for MyUserData {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method_mut("push", |_, this, f: Function| {
+                this.0.push(f.call(())?);
+                Ok(())
+            });
+            methods.add_method("len", |_, this, ()| Ok(this.0.len()));
+        }
+    }
+
+    let lua = Lua::new();
+    lua.globals().set("ud", MyUserData(Vec::new()))?;
+
+    lua.load("ud:push(function() return 1 end)").exec()?;
+    assert_eq!(lua.load("ud:len()").eval::<usize>()?, 1);
+
+    match lua.load("ud:push(function() return ud:len() end)").exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match **cause {
+            Error::CallbackError { ref cause, .. } => match **cause {
+                Error::UserDataBorrowError {
+                    method: Some(ref method),
+                } => {
+                    assert_eq!(method, "len");
+                    assert_eq!(
+                        cause.to_string(),
+                        "cannot borrow userdata for method 'len' because it is already mutably borrowed"
+                    );
+                }
+                ref err => panic!("expected UserDataBorrowError, got {:?}", err),
+            },
+            ref err => panic!("expected CallbackError, got {:?}", err),
+        },
+        r => panic!("expected CallbackError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_set_data() -> Result<()> {
+    struct MyUserData(i64);
+
+    impl UserData for MyUserData {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("get", |_, this, ()| Ok(this.0));
+        }
+    }
+
+    struct OtherUserData;
+    impl UserData for OtherUserData {}
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+    let ud = lua.create_userdata(MyUserData(1))?;
+    globals.set("ud", ud.clone())?;
+    globals.set("alias", ud.clone())?;
+
+    ud.set_data(MyUserData(2))?;
+    assert_eq!(lua.load("ud:get()").eval::<i64>()?, 2);
+    assert_eq!(lua.load("alias:get()").eval::<i64>()?, 2);
+    assert!(lua.load("rawequal(ud, alias)").eval::<bool>()?);
+
+    match ud.set_data(OtherUserData) {
+        Err(Error::UserDataTypeMismatch) => {}
+        r => panic!("expected UserDataTypeMismatch, got {:?}", r),
+    }
+
+    let guard = ud.borrow::<MyUserData>()?;
+    match ud.set_data(MyUserData(3)) {
+        Err(Error::UserDataBorrowMutError { .. }) => {}
+        r => panic!("expected UserDataBorrowMutError, got {:?}", r),
+    }
+    drop(guard);
+    assert_eq!(ud.borrow::<MyUserData>()?.0, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_type_id() -> Result<()> {
+    struct MyUserData1;
+    impl UserData for MyUserData1 {}
+
+    struct MyUserData2;
+    impl UserData for MyUserData2 {}
+
+    let lua = Lua::new();
+    let ud1 = lua.create_userdata(MyUserData1)?;
+    let ud2 = lua.create_userdata(MyUserData2)?;
+    let ud3 = lua.create_userdata(MyUserData1)?;
+
+    assert_eq!(ud1.type_id(), Some(TypeId::of::<MyUserData1>()));
+    assert_eq!(ud2.type_id(), Some(TypeId::of::<MyUserData2>()));
+    assert_eq!(ud3.type_id(), Some(TypeId::of::<MyUserData1>()));
+
+    lua.scope(|scope| {
+        let scoped = scope.create_static_userdata(MyUserData2)?;
+        assert_eq!(scoped.type_id(), Some(TypeId::of::<MyUserData2>()));
+        let scoped = scope.create_nonstatic_userdata(MyUserData2)?;
+        assert_eq!(scoped.type_id(), None);
+        Ok(())
+    })?;
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use mlua::{Lua, Result, Value};
+use mlua::{Error, Lua, MetaMethod, MultiValue, Result, Table, UserData, UserDataMethods, Value};
 
 #[test]
 fn test_value_eq() -> Result<()> {
@@ -55,3 +55,162 @@ fn test_value_eq() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_value_debug_string() -> Result<()> {
+    let lua = Lua::new();
+
+    let value: Value = lua
+        .load(r#"{ name = "x", ["two words"] = true, inner = { 1.5 } }"#)
+        .eval()?;
+    let rendered = value.to_debug_string(2);
+    assert!(rendered.starts_with("{ ") && rendered.ends_with(" }"));
+    assert!(rendered.contains(r#"name = "x""#));
+    assert!(rendered.contains(r#"["two words"] = true"#));
+    assert!(rendered.contains("inner = { [1] = 1.5 }"));
+    assert!(value.to_debug_string(1).contains("inner = { ... }"));
+
+    let empty: Value = lua.load("{}").eval()?;
+    assert_eq!(empty.to_debug_string(1), "{}");
+    assert_eq!(Value::Nil.to_debug_string(0), "nil");
+
+    let function: Value = lua.load("function() end").eval()?;
+    assert!(function.to_debug_string(0).starts_with("<function 0x"));
+
+    let cyclic: Table = lua.load("local t = {} t.t = t return t").eval()?;
+    assert!(cyclic.dump(3).starts_with("{ t = <cycle table: 0x"));
+
+    Ok(())
+}
+
+#[test]
+fn test_value_into_owned() -> Result<()> {
+    let lua = Lua::new();
+
+    let owned = {
+        let value: Value = lua.load("{ answer = 42 }").eval()?;
+        value.into_owned(&lua)?
+    };
+    lua.gc_collect()?;
+
+    match owned.to_value(&lua)? {
+        Value::Table(t) => assert_eq!(t.get::<_, i64>("answer")?, 42),
+        v => panic!("expected table, got {:?}", v),
+    }
+
+    let other = Lua::new();
+    match owned.to_value(&other) {
+        Err(Error::MismatchedRegistryKey) => {}
+        r => panic!("expected MismatchedRegistryKey, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_value_is_callable() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.load(
+        r#"
+        func = function() end
+        callable = setmetatable({}, { __call = function() return 1 end })
+        indexed = setmetatable({}, { __index = function() end })
+        "#,
+    )
+    .exec()?;
+
+    let globals = lua.globals();
+    assert!(globals.get::<_, Value>("func")?.is_callable());
+    assert!(globals.get::<_, Value>("callable")?.is_callable());
+    assert!(!globals.get::<_, Value>("indexed")?.is_callable());
+    assert!(!Value::Nil.is_callable());
+    assert!(!Value::Integer(1).is_callable());
+
+    let callback = lua.create_function(|_, ()| Ok(()))?;
+    assert!(Value::Function(callback).is_callable());
+
+    struct Plain;
+    impl UserData for Plain {}
+
+    struct Callable;
+    impl UserData for Callable {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_meta_method(MetaMethod::Call, |_, _, ()| Ok(()));
+        }
+    }
+
+    assert!(!Value::UserData(lua.create_userdata(Plain)?).is_callable());
+    assert!(Value::UserData(lua.create_userdata(Callable)?).is_callable());
+
+    Ok(())
+}
+
+#[test]
+fn test_multi_value_access() -> Result<()> {
+    let lua = Lua::new();
+
+    let values = MultiValue::from_vec(vec![
+        Value::Integer(1),
+        Value::Boolean(true),
+        Value::String(lua.create_string("three")?),
+    ]);
+
+    assert_eq!(values.len(), 3);
+    assert!(!values.is_empty());
+    assert!(MultiValue::new().is_empty());
+
+    assert_eq!(values.get(0), Some(&Value::Integer(1)));
+    assert_eq!(values.get(1), Some(&Value::Boolean(true)));
+    match values.get(2) {
+        Some(Value::String(s)) => assert_eq!(s.to_str()?, "three"),
+        v => panic!("expected string, got {:?}", v),
+    }
+    assert_eq!(values.get(3), None);
+
+    let ints = values
+        .iter()
+        .filter(|v| matches!(v, Value::Integer(_)))
+        .count();
+    assert_eq!(ints, 1);
+    assert_eq!((&values).into_iter().count(), 3);
+    assert_eq!(values.len(), 3);
+
+    let owned = values.into_iter().collect::<Vec<_>>();
+    assert_eq!(owned[0], Value::Integer(1));
+
+    Ok(())
+}
+
+#[test]
+fn test_value_force_number_subtype() -> Result<()> {
+    let lua = Lua::new();
+
+    assert_eq!(Value::Integer(3).force_float(), Value::Number(3.0));
+    assert_eq!(Value::Number(2.5).force_float(), Value::Number(2.5));
+    assert_eq!(Value::Boolean(true).force_float(), Value::Boolean(true));
+
+    assert_eq!(Value::Number(3.0).force_integer()?, Value::Integer(3));
+    assert_eq!(Value::Number(-0.0).force_integer()?, Value::Integer(0));
+    assert_eq!(Value::Integer(4).force_integer()?, Value::Integer(4));
+    assert_eq!(Value::Nil.force_integer()?, Value::Nil);
+    for n in &[2.5, f64::NAN, f64::INFINITY, 1e300, 9223372036854775808.0] {
+        match Value::Number(*n).force_integer() {
+            Err(Error::FromLuaConversionError { .. }) => {}
+            r => panic!("expected conversion error for {}, got {:?}", n, r),
+        }
+    }
+
+    #[cfg(feature = "lua53")]
+    {
+        let math_type = lua.load("math.type").eval::<mlua::Function>()?;
+        let ty: std::string::String = math_type.call(Value::Integer(1).force_float())?;
+        assert_eq!(ty, "float");
+        let ty: std::string::String = math_type.call(Value::Number(1.0).force_integer()?)?;
+        assert_eq!(ty, "integer");
+    }
+    #[cfg(not(feature = "lua53"))]
+    let _ = lua;
+
+    Ok(())
+}